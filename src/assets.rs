@@ -0,0 +1,98 @@
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::render::{CameraPath, Environment};
+
+async fn fetch_bytes(url: &str) -> Result<Vec<u8>, JsValue> {
+    let window = web_sys::window().ok_or("no window")?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(url))
+        .await?
+        .dyn_into()?;
+    if !response.ok() {
+        return Err(format!("{url}: HTTP {}", response.status()).into());
+    }
+    let buffer = JsFuture::from(response.array_buffer()?).await?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+pub fn query_parameter(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    web_sys::UrlSearchParams::new_with_str(&search)
+        .ok()?
+        .get(name)
+}
+
+// The sky is picked with the page query, either one equirectangular image
+//   ?skybox=sky.hdr
+// or six faces ordered +X, -X, +Y, -Y, +Z, -Z
+//   ?skybox=px.png,nx.png,py.png,ny.png,pz.png,nz.png
+pub async fn load_environment() -> Option<Environment> {
+    let urls: Vec<String> = query_parameter("skybox")?
+        .split(',')
+        .map(|url| url.trim().to_owned())
+        .collect();
+    let mut files = Vec::with_capacity(urls.len());
+    for url in &urls {
+        match fetch_bytes(url).await {
+            Ok(bytes) => files.push(bytes),
+            Err(e) => {
+                log::warn!("Failed to fetch skybox {url}: {e:?}");
+                return None;
+            }
+        }
+    }
+
+    let environment = match files.as_slice() {
+        [equirect] => Environment::from_equirect(equirect),
+        [px, nx, py, ny, pz, nz] => Environment::from_faces([px, nx, py, ny, pz, nz]),
+        _ => {
+            log::warn!("Expected 1 or 6 skybox images, got {}", files.len());
+            return None;
+        }
+    };
+    environment
+        .inspect_err(|e| log::warn!("Failed to decode skybox: {e}"))
+        .ok()
+}
+
+// A flythrough recorded earlier, in the format of `CameraPath::to_text`
+//   ?path=camera_path.txt
+pub async fn load_camera_path() -> Option<CameraPath> {
+    let url = query_parameter("path")?;
+    let bytes = fetch_bytes(&url)
+        .await
+        .inspect_err(|e| log::warn!("Failed to fetch camera path {url}: {e:?}"))
+        .ok()?;
+    CameraPath::parse(&String::from_utf8_lossy(&bytes))
+        .inspect_err(|e| log::warn!("Failed to parse camera path {url}: {e}"))
+        .ok()
+}
+
+// saves `data` through the browser's download prompt
+pub fn download(name: &str, data: &[u8], mime: &str) {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime);
+    match web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options) {
+        Ok(blob) => download_blob(name, &blob),
+        Err(e) => log::warn!("Failed to download {name}: {e:?}"),
+    }
+}
+
+pub fn download_blob(name: &str, blob: &web_sys::Blob) {
+    if let Err(e) = try_download(name, blob) {
+        log::warn!("Failed to download {name}: {e:?}");
+    }
+}
+
+fn try_download(name: &str, blob: &web_sys::Blob) -> Result<(), JsValue> {
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or("no document")?;
+    let url = web_sys::Url::create_object_url_with_blob(blob)?;
+    let anchor: web_sys::HtmlAnchorElement = document.create_element("a")?.dyn_into()?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url)
+}
@@ -0,0 +1,251 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use instant::{Duration, Instant};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    Blob, BlobEvent, BlobPropertyBag, CanvasCaptureMediaStreamTrack, HtmlCanvasElement,
+    MediaRecorder, MediaRecorderOptions,
+};
+
+use crate::assets;
+
+thread_local! {
+    // scale of the screenshot asked for by `take_screenshot`
+    static SCREENSHOT_REQUEST: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+// preferred first, the browser picks what it can encode
+const VIDEO_TYPES: &[&str] = &["video/webm;codecs=vp9", "video/webm", "video/mp4"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaptureFormat {
+    // the canvas through the browser's `MediaRecorder`
+    Video,
+    // every frame read back and downloaded as a numbered PNG
+    Png,
+}
+
+// Picked with the page query, ?capture=png&capture_fps=60&capture_frames=600&screenshot_scale=2
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureSettings {
+    pub format: CaptureFormat,
+    // frames per second of simulated time
+    pub fps: f32,
+    // stops by itself after this many frames
+    pub frames: Option<u32>,
+    // screenshots from the key binding are supersampled this many times along each axis
+    pub screenshot_scale: u32,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            format: CaptureFormat::Video,
+            fps: 30.0,
+            frames: None,
+            screenshot_scale: 1,
+        }
+    }
+}
+
+impl CaptureSettings {
+    pub fn from_query() -> Self {
+        let mut settings = Self::default();
+        if assets::query_parameter("capture").as_deref() == Some("png") {
+            settings.format = CaptureFormat::Png;
+        }
+        if let Some(fps) = assets::query_parameter("capture_fps").and_then(|fps| fps.parse().ok())
+            && fps > 0.0
+        {
+            settings.fps = fps;
+        }
+        settings.frames =
+            assets::query_parameter("capture_frames").and_then(|frames| frames.parse().ok());
+        if let Some(scale) =
+            assets::query_parameter("screenshot_scale").and_then(|scale| scale.parse().ok())
+        {
+            settings.screenshot_scale = u32::max(scale, 1);
+        }
+        settings
+    }
+}
+
+// A running capture. Every frame advances the scene by the same timestep however long it took
+// to render, so the water and the sky move smoothly in the output
+pub struct Capture {
+    settings: CaptureSettings,
+    frames: u32,
+    last_frame: Instant,
+    video: Option<VideoRecorder>,
+    // stops when the camera path playback ends
+    pub follows_path: bool,
+}
+
+impl Capture {
+    pub fn start(
+        settings: CaptureSettings,
+        canvas: Option<&HtmlCanvasElement>,
+        follows_path: bool,
+    ) -> Option<Self> {
+        let video = match settings.format {
+            CaptureFormat::Video => {
+                let canvas = canvas?;
+                match VideoRecorder::start(canvas) {
+                    Ok(video) => Some(video),
+                    Err(e) => {
+                        log::warn!("Failed to start recording the canvas: {e:?}");
+                        return None;
+                    }
+                }
+            }
+            CaptureFormat::Png => None,
+        };
+        log::info!("Capture started: {settings:?}");
+        Some(Self {
+            settings,
+            frames: 0,
+            last_frame: Instant::now(),
+            video,
+            follows_path,
+        })
+    }
+
+    // simulated time between two frames
+    pub fn timestep(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.settings.fps)
+    }
+
+    pub fn wants_pixels(&self) -> bool {
+        self.settings.format == CaptureFormat::Png
+    }
+
+    // the recorder stamps frames with the wall clock, so video frames wait for their time
+    // to keep the output at the right speed. PNGs are taken as fast as they render
+    pub fn is_due(&self) -> bool {
+        self.video.is_none() || self.last_frame.elapsed() >= self.timestep()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.settings
+            .frames
+            .is_some_and(|frames| self.frames >= frames)
+    }
+
+    // called once the frame was presented, with its pixels when `wants_pixels`
+    pub fn add_frame(&mut self, image: Option<&image::RgbaImage>) {
+        self.last_frame = Instant::now();
+        self.frames += 1;
+        if let Some(video) = &self.video {
+            video.track.request_frame();
+        }
+        if let Some(image) = image {
+            match encode_png(image, &[]) {
+                Ok(png) => {
+                    assets::download(&format!("frame_{:05}.png", self.frames), &png, "image/png")
+                }
+                Err(e) => log::warn!("Failed to encode frame {}: {e}", self.frames),
+            }
+        }
+    }
+
+    // the video is downloaded once the recorder has flushed it
+    pub fn stop(self) {
+        if let Some(video) = self.video
+            && let Err(e) = video.recorder.stop()
+        {
+            log::warn!("Failed to stop recording: {e:?}");
+        }
+        log::info!("Capture stopped after {} frames", self.frames);
+    }
+}
+
+// Saves the next frame as a PNG, from the page or the console. `scale` supersamples it, rendered
+// that many times larger and filtered back down, up to the largest texture the GPU allows:
+//   take_screenshot(2)
+#[wasm_bindgen]
+pub fn take_screenshot(scale: Option<u32>) {
+    SCREENSHOT_REQUEST.set(Some(scale.unwrap_or(1).max(1)));
+}
+
+// the scale of a screenshot asked for since the last call
+pub fn requested_screenshot() -> Option<u32> {
+    SCREENSHOT_REQUEST.take()
+}
+
+// downloads the image with `text` as PNG text chunks, so a bug report carries its scene
+pub fn save_screenshot(image: &image::RgbaImage, text: &[(String, String)]) {
+    match encode_png(image, text) {
+        Ok(png) => assets::download("screenshot.png", &png, "image/png"),
+        Err(e) => log::warn!("Failed to encode the screenshot: {e}"),
+    }
+}
+
+fn encode_png(
+    image: &image::RgbaImage,
+    text: &[(String, String)],
+) -> Result<Vec<u8>, png::EncodingError> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    for (keyword, value) in text {
+        encoder.add_text_chunk(keyword.clone(), value.clone())?;
+    }
+    encoder.write_header()?.write_image_data(image.as_raw())?;
+    Ok(png)
+}
+
+struct VideoRecorder {
+    recorder: MediaRecorder,
+    // frames are only taken when requested
+    track: CanvasCaptureMediaStreamTrack,
+}
+
+impl VideoRecorder {
+    fn start(canvas: &HtmlCanvasElement) -> Result<Self, JsValue> {
+        let mime = VIDEO_TYPES
+            .iter()
+            .copied()
+            .find(|mime| MediaRecorder::is_type_supported(mime))
+            .ok_or("no supported video type")?;
+        let stream = canvas.capture_stream_with_frame_request_rate(0.0)?;
+        let track: CanvasCaptureMediaStreamTrack = stream.get_video_tracks().get(0).dyn_into()?;
+        let options = MediaRecorderOptions::new();
+        options.set_mime_type(mime);
+        let recorder =
+            MediaRecorder::new_with_media_stream_and_media_recorder_options(&stream, &options)?;
+
+        // the callbacks outlive `self`, the last data arrives after `stop`
+        let chunks: Rc<RefCell<Vec<Blob>>> = Rc::default();
+        let collect = chunks.clone();
+        let on_data = Closure::<dyn FnMut(BlobEvent)>::new(move |event: BlobEvent| {
+            if let Some(data) = event.data() {
+                collect.borrow_mut().push(data);
+            }
+        });
+        recorder.set_ondataavailable(Some(on_data.into_js_value().unchecked_ref()));
+        let mime = mime.to_owned();
+        let on_stop = Closure::once_into_js(move || {
+            let parts: js_sys::Array = chunks.borrow().iter().collect();
+            let options = BlobPropertyBag::new();
+            options.set_type(&mime);
+            match Blob::new_with_blob_sequence_and_options(&parts, &options) {
+                Ok(video) => {
+                    let extension = if mime.starts_with("video/mp4") {
+                        "mp4"
+                    } else {
+                        "webm"
+                    };
+                    assets::download_blob(&format!("capture.{extension}"), &video);
+                }
+                Err(e) => log::warn!("Failed to assemble the video: {e:?}"),
+            }
+        });
+        recorder.set_onstop(Some(on_stop.unchecked_ref()));
+        recorder.start()?;
+        Ok(Self { recorder, track })
+    }
+}
@@ -1,106 +1,552 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
-use crate::render::Camera;
-use crate::render::Renderable;
-use winit::event::KeyEvent;
-use winit::keyboard::PhysicalKey;
+use crate::assets;
+use crate::capture::{self, Capture, CaptureSettings};
+use crate::gamepad::{GamepadSettings, Gamepads};
+use crate::input::{Action, ActionEvent, InputMap};
+use crate::overlay::TextOverlay;
+use crate::render::waves::GerstnerWave;
+use crate::render::{
+    AntiAliasing, Camera, Capabilities, Environment, Fog, FrameReadback, Heightmap, Lighting,
+    LookSettings, PostProcess, Resolution, ResolutionSettings, Supersample,
+};
+use crate::render::{RenderStage, RenderTargets, Renderable, capabilities};
+use crate::touch::TouchControls;
+use cgmath::Vector2;
+use web_sys::HtmlCanvasElement;
+use winit::event::MouseScrollDelta;
+use winit::platform::web::WindowExtWebSys;
 use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
+
+// seconds between updates of the resolution readout
+const READOUT_INTERVAL: f32 = 0.5;
+
+// Why the renderer could not start or replace a lost device, shown on the page
+#[derive(Debug)]
+pub enum ContextError {
+    // the page has no <canvas id="canvas">
+    MissingCanvas,
+    Window(String),
+    Surface(wgpu::CreateSurfaceError),
+    // none of these backends has an adapter
+    NoAdapter(wgpu::Backends),
+    Device {
+        adapter: wgpu::AdapterInfo,
+        error: wgpu::RequestDeviceError,
+        // the limits asked for that the adapter falls short of
+        missing: Vec<String>,
+    },
+    // the canvas can't present anything the adapter renders
+    NoSurfaceFormat(wgpu::AdapterInfo),
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContextError::MissingCanvas => {
+                write!(f, "The page has no <canvas id=\"canvas\"> to render into.")
+            }
+            ContextError::Window(e) => write!(f, "Failed to create the window: {e}"),
+            ContextError::Surface(e) => write!(
+                f,
+                "Failed to render into the canvas: {e}\n\
+                 The browser may lack WebGPU and WebGL2, or hardware acceleration is turned off."
+            ),
+            ContextError::NoAdapter(backends) => {
+                let names: Vec<_> = [
+                    (wgpu::Backends::BROWSER_WEBGPU, "WebGPU"),
+                    (wgpu::Backends::GL, "WebGL2"),
+                ]
+                .into_iter()
+                .filter(|(backend, _)| backends.contains(*backend))
+                .map(|(_, name)| name)
+                .collect();
+                write!(
+                    f,
+                    "No graphics adapter for {}.\n\
+                     Check that hardware acceleration is turned on in the browser settings.",
+                    names.join(" or ")
+                )
+            }
+            ContextError::Device {
+                adapter,
+                error,
+                missing,
+            } => {
+                writeln!(f, "The graphics adapter could not create a device: {error}")?;
+                write!(f, "Adapter: {}", adapter_text(adapter))?;
+                if !missing.is_empty() {
+                    write!(f, "\nMissing limits:")?;
+                    for limit in missing {
+                        write!(f, "\n  {limit}")?;
+                    }
+                }
+                Ok(())
+            }
+            ContextError::NoSurfaceFormat(adapter) => write!(
+                f,
+                "The canvas cannot show images from {}.",
+                adapter_text(adapter)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ContextError {}
+
+// name, backend and driver, as far as the browser reveals them
+fn adapter_text(adapter: &wgpu::AdapterInfo) -> String {
+    let mut text = format!(
+        "{} ({})",
+        adapter.name,
+        capabilities::backend_name(adapter.backend)
+    );
+    let driver = format!("{} {}", adapter.driver, adapter.driver_info);
+    if !driver.trim().is_empty() {
+        text += &format!(", driver {}", driver.trim());
+    }
+    text
+}
+
+// the GPU objects of one device, replaced together when it is lost
+struct Gpu {
+    surface: wgpu::Surface<'static>,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+type GpuRequest = Rc<RefCell<Option<Result<Gpu, ContextError>>>>;
+
+// replacing a lost device
+enum Recovery {
+    // filled once the new device is ready
+    Pending(GpuRequest),
+    Failed,
+}
+
+// makes a pass for a new device, see `Context::add_render_pass`
+type PassFactory = fn(
+    &wgpu::Device,
+    &wgpu::SurfaceConfiguration,
+    &Camera,
+    &RenderTargets,
+    &Capabilities,
+) -> Box<dyn Renderable>;
+
 pub struct ContextState {
     pub size: winit::dpi::PhysicalSize<u32>,
     pub new_terrain: bool,
     pub cursor_captured: bool,
-}
-
-fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
-    let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("Perlin Depth Texture"),
-        size: wgpu::Extent3d {
-            width: width,
-            height: height,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Depth32Float,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        view_formats: &[],
-    });
-    depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
+    // the right mouse button is held
+    pub panning: bool,
+    // seconds of simulated time, advanced by `Context::update`
+    pub time: f32,
+    // noise seed of the terrain, set by `PerlinPass`
+    pub seed: u32,
+    // terrain of the current frame, set by `PerlinPass`
+    pub heightmap: Option<Rc<Heightmap>>,
+    // world height of the water surface, set by `WaterPass`
+    pub water_level: f32,
+    // the Gerstner waves on it, none for the FFT ocean, set by `WaterPass`
+    pub waves: Vec<GerstnerWave>,
+    // sun and sky light, set by `SkyPass`
+    pub lighting: Lighting,
+    // height fog, adjusted by `SkyPass`
+    pub fog: Fog,
+    // sky environment map loaded at startup, drawn by `SkyPass`
+    pub environment: Option<Rc<Environment>>,
 }
 
 pub struct Context<'a> {
     context_data: ContextState,
-    surface: wgpu::Surface<'a>,
+    instance: wgpu::Instance,
+    surface: wgpu::Surface<'static>,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    // set when the browser or driver loses the device
+    device_lost: Arc<AtomicBool>,
+    recovery: Option<Recovery>,
     config: wgpu::SurfaceConfiguration,
     window: &'a Window,
     pipelines: Vec<Box<dyn Renderable>>,
-    depth_texture_view: wgpu::TextureView,
+    // the passes again, for a new device
+    pass_factories: Vec<PassFactory>,
+    targets: RenderTargets,
+    post: PostProcess,
+    antialiasing: AntiAliasing,
+    input_map: InputMap,
+    gamepads: Gamepads,
+    touch: TouchControls,
+    capture_settings: CaptureSettings,
+    frame_capture: Option<Capture>,
+    // created for the first capture that reads pixels
+    readback: Option<FrameReadback>,
+    // scale of the screenshot to take with the next frame
+    screenshot: Option<u32>,
+    // text chunks of the screenshot being read back
+    pending_screenshot: Option<Vec<(String, String)>>,
+    capabilities: Capabilities,
+    resolution: Resolution,
+    // surface and scene size with the frame time, shown with ?readout
+    readout: Option<TextOverlay>,
+    readout_timer: f32,
+    // shown while a lost device is replaced, or why that failed
+    status: Option<TextOverlay>,
     pub camera: Camera,
 }
 
+// mouse look from the page query, ?sensitivity=0.003&invert_y&smoothing=0.05
+fn look_settings() -> LookSettings {
+    let mut settings = LookSettings::default();
+    let number = |name: &str| assets::query_parameter(name).and_then(|value| value.parse().ok());
+    if let Some(sensitivity) = number("sensitivity") {
+        settings.sensitivity = sensitivity;
+    }
+    if let Some(smoothing) = number("smoothing") {
+        settings.smoothing = smoothing;
+    }
+    settings.invert_y = assets::query_parameter("invert_y").is_some();
+    settings
+}
+
+// gamepad from the page query, ?stick_dead_zone=0.2&look_speed=3&gamepad_invert_y
+fn gamepad_settings() -> GamepadSettings {
+    let mut settings = GamepadSettings::default();
+    let number = |name: &str| assets::query_parameter(name).and_then(|value| value.parse().ok());
+    // a dead zone of 1 would swallow the whole stick
+    let dead_zone = |name: &str| number(name).filter(|zone: &f32| (0.0..0.95).contains(zone));
+    if let Some(zone) = dead_zone("stick_dead_zone") {
+        settings.stick_dead_zone = zone;
+    }
+    if let Some(zone) = dead_zone("trigger_dead_zone") {
+        settings.trigger_dead_zone = zone;
+    }
+    if let Some(speed) = number("look_speed") {
+        settings.look_speed = speed;
+    }
+    settings.invert_y = assets::query_parameter("gamepad_invert_y").is_some();
+    settings
+}
+
+// walk mode's eye above the terrain from the page query, ?eye_height=1.7
+fn eye_height() -> Option<f32> {
+    assets::query_parameter("eye_height")
+        .and_then(|value| value.parse().ok())
+        .filter(|height: &f32| *height > 0.0)
+}
+
+// scene resolution from the page query, ?render_scale=0.75&max_resolution=1920x1080&target_fps=60
+fn resolution_settings() -> ResolutionSettings {
+    let mut settings = ResolutionSettings::default();
+    if let Some(scale) = assets::query_parameter("render_scale").and_then(|s| s.parse().ok()) {
+        settings.render_scale = scale;
+    }
+    settings.max_resolution = assets::query_parameter("max_resolution").and_then(|size| {
+        let (width, height) = size.split_once('x')?;
+        Some((width.parse().ok()?, height.parse().ok()?))
+    });
+    settings.target_frame_time = assets::query_parameter("target_fps")
+        .and_then(|fps| fps.parse::<f32>().ok())
+        .filter(|fps| *fps > 0.0)
+        .map(|fps| 1.0 / fps);
+    settings
+}
+
+// WebGPU where the browser has it and WebGL2 otherwise, ?backend=webgl forces WebGL2
+fn backends() -> wgpu::Backends {
+    match assets::query_parameter("backend").as_deref() {
+        Some("webgl") => wgpu::Backends::GL,
+        Some("webgpu") => wgpu::Backends::BROWSER_WEBGPU,
+        _ => wgpu::Backends::BROWSER_WEBGPU | wgpu::Backends::GL,
+    }
+}
+
+// the largest size with the aspect of `size` and no side over `largest`
+fn fit_size(size: PhysicalSize<u32>, largest: u32) -> PhysicalSize<u32> {
+    let scale = (largest as f32 / size.width.max(size.height) as f32).min(1.0);
+    let side = |side: u32| ((side as f32 * scale) as u32).clamp(1, largest);
+    PhysicalSize::new(side(size.width), side(size.height))
+}
+
+// a new surface on the canvas with an adapter and device for it
+async fn request_gpu(
+    instance: wgpu::Instance,
+    canvas: HtmlCanvasElement,
+) -> Result<Gpu, ContextError> {
+    let surface = instance
+        .create_surface(wgpu::SurfaceTarget::Canvas(canvas))
+        .map_err(ContextError::Surface)?;
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: Some(&surface),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+        })
+        .await
+        .ok_or(ContextError::NoAdapter(backends()))?;
+
+    let required_limits = capabilities::required_limits(&adapter);
+    let request = adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            required_features: wgpu::Features::empty(),
+            required_limits: required_limits.clone(),
+            memory_hints: Default::default(),
+        },
+        None,
+    );
+    let (device, queue) = match request.await {
+        Ok(device) => device,
+        Err(error) => {
+            let mut missing = Vec::new();
+            required_limits.check_limits_with_fail_fn(
+                &adapter.limits(),
+                false,
+                |name, required, allowed| {
+                    missing.push(format!(
+                        "{name}: needs {required}, the adapter allows {allowed}"
+                    ))
+                },
+            );
+            return Err(ContextError::Device {
+                adapter: adapter.get_info(),
+                error,
+                missing,
+            });
+        }
+    };
+    Ok(Gpu {
+        surface,
+        adapter,
+        device,
+        queue,
+    })
+}
+
+// sRGB where the surface has it
+fn surface_config(
+    surface: &wgpu::Surface,
+    adapter: &wgpu::Adapter,
+    width: u32,
+    height: u32,
+) -> Result<wgpu::SurfaceConfiguration, ContextError> {
+    let surface_caps = surface.get_capabilities(adapter);
+    let (Some(&first), Some(&present_mode), Some(&alpha_mode)) = (
+        surface_caps.formats.first(),
+        surface_caps.present_modes.first(),
+        surface_caps.alpha_modes.first(),
+    ) else {
+        return Err(ContextError::NoSurfaceFormat(adapter.get_info()));
+    };
+    let surface_format = surface_caps
+        .formats
+        .iter()
+        .copied()
+        .find(|f| f.is_srgb())
+        .unwrap_or(first);
+    Ok(wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface_format,
+        width: width,
+        height: height,
+        present_mode,
+        alpha_mode,
+        view_formats: vec![],
+        desired_maximum_frame_latency: 2,
+    })
+}
+
+// a flag the device sets once it is lost
+fn watch_device(device: &wgpu::Device) -> Arc<AtomicBool> {
+    let lost = Arc::new(AtomicBool::new(false));
+    let flag = lost.clone();
+    device.set_device_lost_callback(move |reason, message| {
+        // a replaced device is destroyed on purpose
+        if !matches!(reason, wgpu::DeviceLostReason::Destroyed) {
+            log::error!("Graphics device lost: {message}");
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+    lost
+}
+
+// the opaque, offscreen and transparent stages into the HDR target of `targets`
+fn render_scene(
+    encoder: &mut wgpu::CommandEncoder,
+    pipelines: &mut [Box<dyn Renderable>],
+    camera: &Camera,
+    targets: &RenderTargets,
+) {
+    // When multisampled, a depth prepass fills the single sampled depth the later stages read
+    if targets.multisampled.is_some() {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth Prepass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &targets.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        for pipeline in pipelines.iter_mut() {
+            if pipeline.stage() == RenderStage::Opaque {
+                pipeline.render_depth(&mut render_pass, camera);
+            }
+        }
+    }
+
+    {
+        // multisampled colour resolves into the HDR target at the end of the pass
+        let (color_view, resolve_target, depth_view, store) = match &targets.multisampled {
+            Some(multisampled) => (
+                &multisampled.color_view,
+                Some(&targets.hdr_view),
+                &multisampled.depth_view,
+                wgpu::StoreOp::Discard,
+            ),
+            None => (
+                &targets.hdr_view,
+                None,
+                &targets.depth_view,
+                wgpu::StoreOp::Store,
+            ),
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Opaque Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: color_view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        for pipeline in pipelines.iter_mut() {
+            if pipeline.stage() == RenderStage::Opaque {
+                pipeline.render(&mut render_pass, camera, targets);
+            }
+        }
+    }
+
+    for pipeline in pipelines.iter_mut() {
+        pipeline.render_offscreen(encoder, camera, targets);
+    }
+
+    // The depth buffer is bound as a texture here, so it cannot be attached as well
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Transparent Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &targets.hdr_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        for pipeline in pipelines.iter_mut() {
+            if pipeline.stage() == RenderStage::Transparent {
+                pipeline.render(&mut render_pass, camera, targets);
+            }
+        }
+    }
+}
+
 impl<'a> Context<'a> {
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: &'a Window) -> Context<'a> {
+    pub async fn new(window: &'a Window) -> Result<Context<'a>, ContextError> {
         let size = window.inner_size();
-        let width = size.width.max(1);
-        let height = size.height.max(1);
+        let canvas = window.canvas().ok_or(ContextError::MissingCanvas)?;
 
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::GL,
+        // falls back to WebGL2 when the browser lists WebGPU but has no adapter for it
+        let instance = wgpu::util::new_instance_with_webgpu_detection(&wgpu::InstanceDescriptor {
+            backends: backends(),
             ..Default::default()
-        });
-        let surface = instance
-            .create_surface(window)
-            .expect("Failed to create surface");
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                compatible_surface: Some(&surface),
-                power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-            })
-            .await
-            .expect("Failed to find an adapter");
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::downlevel_webgl2_defaults(),
-                    memory_hints: Default::default(),
-                },
-                None,
-            )
-            .await
-            .expect("Failed to create device");
+        })
+        .await;
+        let Gpu {
+            surface,
+            adapter,
+            device,
+            queue,
+        } = request_gpu(instance.clone(), canvas).await?;
+        let capabilities = Capabilities::new(&adapter, &device);
+        log::info!(
+            "Rendering with {} on {}",
+            capabilities.backend_name(),
+            capabilities.adapter
+        );
+        let largest = capabilities.limits.max_texture_dimension_2d;
+        let PhysicalSize { width, height } = fit_size(
+            PhysicalSize::new(size.width.max(1), size.height.max(1)),
+            largest,
+        );
+        let config = surface_config(&surface, &adapter, width, height)?;
+        let mut camera = Camera::new(&device, config.width, config.height);
+        camera.set_look_settings(look_settings());
+        if let Some(height) = eye_height() {
+            camera.set_eye_height(height);
+        }
 
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: width,
-            height: height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
-        };
-        let camera = Camera::new(&device, config.width, config.height);
+        // the anti-aliasing action cycles through the other modes
+        let antialiasing = AntiAliasing::Off;
+        let resolution = Resolution::new(resolution_settings());
+        let (scene_width, scene_height) = resolution.render_size(width, height, largest);
+        let targets = RenderTargets::new(
+            &device,
+            scene_width,
+            scene_height,
+            antialiasing.sample_count(),
+        );
+        let post = PostProcess::new(&device, &config, &targets, antialiasing, &capabilities);
 
-        let depth_texture_view = create_depth_texture(&device, width, height);
+        let readout = TextOverlay::new(&[
+            ("top", "8px"),
+            ("left", "8px"),
+            ("padding", "4px 6px"),
+            ("color", "white"),
+            ("background", "rgba(0, 0, 0, 0.5)"),
+        ]);
+        if let Some(readout) = &readout
+            && assets::query_parameter("readout").is_some()
+        {
+            readout.show("");
+        }
 
-        Context {
+        Ok(Context {
+            instance,
             surface: surface,
+            device_lost: watch_device(&device),
+            recovery: None,
             device: device,
             queue: queue,
             config: config,
@@ -108,12 +554,41 @@ impl<'a> Context<'a> {
                 size: PhysicalSize::new(width, height),
                 new_terrain: true,
                 cursor_captured: false,
+                panning: false,
+                time: 0.0,
+                seed: 0,
+                heightmap: None,
+                water_level: 0.0,
+                waves: Vec::new(),
+                lighting: Lighting::default(),
+                fog: Fog::default(),
+                environment: None,
             },
             window,
             pipelines: Vec::new(),
+            pass_factories: Vec::new(),
             camera,
-            depth_texture_view,
-        }
+            targets,
+            post,
+            antialiasing,
+            input_map: InputMap::load(),
+            gamepads: Gamepads::new(gamepad_settings()),
+            // ?joystick shows the virtual joystick
+            touch: TouchControls::new(
+                window.canvas(),
+                assets::query_parameter("joystick").is_some(),
+            ),
+            capture_settings: CaptureSettings::from_query(),
+            frame_capture: None,
+            readback: None,
+            screenshot: None,
+            pending_screenshot: None,
+            capabilities,
+            resolution,
+            readout,
+            readout_timer: 0.0,
+            status: TextOverlay::error(),
+        })
     }
 
     pub fn window(&self) -> &Window {
@@ -121,52 +596,255 @@ impl<'a> Context<'a> {
     }
 
     pub fn resize(&mut self, new_size: Option<winit::dpi::PhysicalSize<u32>>) {
-        let mut size = match new_size {
+        let size = match new_size {
             Some(size) => size,
             None => *self.size(),
         };
 
-        size.width = size.width.min(1280);
-        size.height = size.height.min(720);
+        // the full physical size of the canvas, so HiDPI displays get every pixel
         if size.width > 0 && size.height > 0 {
+            let size = fit_size(size, self.capabilities.limits.max_texture_dimension_2d);
             *self.size() = size;
             self.config.width = size.width;
             self.config.height = size.height;
             self.surface.configure(&self.device, &self.config);
             self.camera.resize(size.width, size.height);
-            self.depth_texture_view = create_depth_texture(&self.device, size.width, size.height);
+            self.resize_scene();
         }
         log::info!("Surface resize to {0:?}", self.size());
     }
 
+    // the targets at the render scale of the surface size
+    fn resize_scene(&mut self) {
+        let (width, height) = self.resolution.render_size(
+            self.config.width,
+            self.config.height,
+            self.capabilities.limits.max_texture_dimension_2d,
+        );
+        self.targets.resize(&self.device, width, height);
+        for pass in &mut self.pipelines {
+            pass.resize(&self.device, &self.targets);
+        }
+        self.post.resize(&self.device, &self.targets);
+    }
+
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        let mut res = match event {
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        state,
-                        physical_key: PhysicalKey::Code(code),
-                        ..
-                    },
-                ..
-            } => self.camera.process_key(state, code),
-            _ => false,
-        };
+        match event {
+            WindowEvent::Touch(touch) => {
+                self.touch.process(touch, &mut self.camera);
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
+                };
+                self.camera.process_scroll(lines);
+                true
+            }
+            _ => match self.input_map.process(event) {
+                Some(input) => self.process_action(&input),
+                None => false,
+            },
+        }
+    }
+
+    // actions from keys, mouse buttons and gamepad buttons alike
+    fn process_action(&mut self, input: &ActionEvent) -> bool {
+        // toggles ignore key repeat
+        let toggled = input.pressed && !input.repeat;
+        match input.action {
+            Action::AntiAliasing if toggled => self.set_antialiasing(self.antialiasing.next()),
+            Action::CameraMode if toggled => self.camera.set_mode(self.camera.mode().next()),
+            Action::FlyAboveGround if toggled => self.camera.toggle_fly_above_ground(),
+            Action::Regenerate if toggled => self.context_data.new_terrain = true,
+            Action::ToggleCapture if toggled => {
+                self.toggle_cursor_capture();
+            }
+            Action::ReleaseCapture if toggled && self.is_cursor_captured() => {
+                self.toggle_cursor_capture();
+            }
+            Action::Pan => self.context_data.panning = input.pressed,
+            Action::RecordKeyframe if toggled => self.camera.record_keyframe(),
+            Action::PlayPath if toggled => self.camera.toggle_playback(),
+            Action::ClearPath if toggled => self.camera.clear_path(),
+            Action::SavePath if toggled => {
+                assets::download(
+                    "camera_path.txt",
+                    self.camera.path().to_text().as_bytes(),
+                    "text/plain",
+                );
+            }
+            Action::CaptureFrames if toggled => self.toggle_frame_capture(),
+            Action::ResolutionReadout if toggled => {
+                if let Some(readout) = &self.readout {
+                    if readout.is_visible() {
+                        readout.hide();
+                    } else {
+                        readout.show(&self.readout_text());
+                    }
+                }
+            }
+            Action::Screenshot if toggled => {
+                self.screenshot = Some(self.capture_settings.screenshot_scale);
+            }
+            Action::SlowerPlayback if input.pressed => self.camera.change_playback_speed(-1),
+            Action::FasterPlayback if input.pressed => self.camera.change_playback_speed(1),
+            _ => {
+                let mut res = self.camera.process_action(input);
+                for pass in &mut self.pipelines {
+                    res |= pass.input(input, &self.context_data);
+                }
+                res |= self.post.input(input);
+                return res;
+            }
+        }
+        true
+    }
+
+    // raw mouse motion, looks around while captured and pans while the right button is held
+    pub fn process_mouse(&mut self, delta: Vector2<f32>) {
+        if self.context_data.cursor_captured {
+            self.camera.process_mouse(delta);
+        }
+        if self.context_data.panning {
+            self.camera.process_pan(delta);
+        }
+    }
+
+    // recreates the targets and every pipeline that depends on the sample count
+    pub fn set_antialiasing(&mut self, antialiasing: AntiAliasing) {
+        self.antialiasing = antialiasing;
+        self.targets
+            .set_sample_count(&self.device, antialiasing.sample_count());
         for pass in &mut self.pipelines {
-            res |= pass.input(event, &self.context_data);
+            pass.resize(&self.device, &self.targets);
+        }
+        self.post
+            .set_antialiasing(&self.device, &self.config, &self.targets, antialiasing);
+        log::info!("Anti-aliasing: {:?}", antialiasing);
+    }
+
+    // Starts or stops a fixed timestep capture. With a recorded camera path the capture
+    // plays it from the start and stops at its end
+    pub fn toggle_frame_capture(&mut self) {
+        if let Some(capture) = self.frame_capture.take() {
+            capture.stop();
+            return;
+        }
+        let follows_path = self.camera.path().len() > 1 && self.camera.play_path();
+        self.frame_capture = Capture::start(
+            self.capture_settings,
+            self.window.canvas().as_ref(),
+            follows_path,
+        );
+    }
+
+    // replaces the measured frame time while capturing
+    pub fn capture_timestep(&self) -> Option<Duration> {
+        self.frame_capture.as_ref().map(Capture::timestep)
+    }
+
+    // false while a capture waits for its next frame
+    pub fn frame_due(&self) -> bool {
+        let reading = self.readback.as_ref().is_some_and(FrameReadback::is_busy);
+        self.frame_capture
+            .as_ref()
+            .is_none_or(|capture| capture.is_due() && !reading)
+    }
+
+    // finishes the frame read back in the background, called every animation frame
+    pub fn poll_capture(&mut self) {
+        let Some(readback) = &mut self.readback else {
+            return;
+        };
+        if !readback.is_busy() {
+            return;
+        }
+        let Some(image) = readback.try_read(&self.device) else {
+            if !readback.is_busy() {
+                // the read failed
+                self.pending_screenshot = None;
+            }
+            return;
+        };
+        if let Some(text) = self.pending_screenshot.take() {
+            capture::save_screenshot(&image, &text);
+        }
+        if let Some(capture) = &mut self.frame_capture
+            && capture.wants_pixels()
+        {
+            capture.add_frame(Some(&image));
+            self.end_capture_when_done();
+        }
+    }
+
+    // after the frame was presented
+    fn capture_frame(&mut self, read_back: bool) {
+        if read_back {
+            if let Some(readback) = &mut self.readback {
+                readback.start();
+            }
+            self.poll_capture();
+        } else if let Some(capture) = &mut self.frame_capture
+            && !capture.wants_pixels()
+        {
+            capture.add_frame(None);
+            self.end_capture_when_done();
+        }
+    }
+
+    fn end_capture_when_done(&mut self) {
+        let Some(capture) = &self.frame_capture else {
+            return;
+        };
+        let path_done = capture.follows_path && !self.camera.is_playing();
+        if capture.is_finished() || path_done {
+            self.toggle_frame_capture();
         }
-        res
     }
 
     pub fn update(&mut self, dt: &Duration) {
-        self.camera.update(dt, &self.queue);
+        if let Some(scale) = capture::requested_screenshot() {
+            self.screenshot = Some(scale);
+        }
+        // a capture's fixed timestep says nothing about how long frames take
+        if self.frame_capture.is_none() && self.resolution.update(dt.as_secs_f32()) {
+            self.resize_scene();
+        }
+        self.update_readout(dt.as_secs_f32());
+        self.context_data.time += dt.as_secs_f32();
         for pass in &mut self.pipelines {
             pass.update(&mut self.context_data, &self.queue);
         }
+        let (mut analog, buttons) = self.gamepads.poll(&mut self.input_map);
+        for input in &buttons {
+            self.process_action(input);
+        }
+        self.touch.apply(&mut analog);
+        self.camera.process_analog(&analog);
+        self.camera
+            .set_heightmap(self.context_data.heightmap.clone());
+        self.camera
+            .set_water(self.context_data.water_level, &self.context_data.waves);
+        self.camera.set_lighting(&self.context_data.lighting);
+        self.camera.set_fog(&self.context_data.fog);
+        let jitter = self.post.jitter(&self.targets);
+        self.camera.set_jitter(jitter);
+        self.camera.update(dt, &self.queue);
+        self.camera.update_environment(
+            self.context_data.environment.as_ref(),
+            &self.context_data.lighting,
+            &self.queue,
+        );
+        self.post
+            .update(&self.queue, dt.as_secs_f32(), &self.targets, &self.camera);
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
+        // a supersampled screenshot renders the frame a second time into larger targets
+        let mut supersample = self.begin_screenshot();
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -177,42 +855,260 @@ impl<'a> Context<'a> {
                 label: Some("Main Encoder"),
             });
 
+        render_scene(
+            &mut encoder,
+            &mut self.pipelines,
+            &self.camera,
+            &self.targets,
+        );
+
+        self.post.render(&mut encoder, &self.targets);
+        self.post.present(&mut encoder, &view);
+        let read_back = self.readback_frame(&mut encoder, supersample.as_mut());
+
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+        self.capture_frame(read_back);
+        Ok(())
+    }
+
+    fn readout_text(&self) -> String {
+        let scale = self.targets.width as f32 / self.config.width as f32;
+        let frame_time = self.resolution.frame_time().unwrap_or(0.0);
+        format!(
+            "{}\nsurface {}x{}, pixel ratio {:.2}\nscene {}x{}, {:.0}%\n{:.1} ms, {:.0} fps",
+            self.capabilities.backend_name(),
+            self.config.width,
+            self.config.height,
+            self.window.scale_factor(),
+            self.targets.width,
+            self.targets.height,
+            scale * 100.0,
+            frame_time * 1000.0,
+            1.0 / frame_time.max(f32::EPSILON),
+        )
+    }
+
+    fn update_readout(&mut self, dt: f32) {
+        self.readout_timer += dt;
+        if self.readout_timer < READOUT_INTERVAL {
+            return;
+        }
+        self.readout_timer = 0.0;
+        if let Some(readout) = &self.readout
+            && readout.is_visible()
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Perlin Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture_view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+            readout.show(&self.readout_text());
+        }
+    }
+
+    // targets of their own for a screenshot with a scale above 1, the frame's stay as they are
+    fn begin_screenshot(&self) -> Option<Supersample> {
+        let scale = self.screenshot?;
+        if self.readback.as_ref().is_some_and(FrameReadback::is_busy) {
+            return None;
+        }
+        let (width, height) = (self.targets.width, self.targets.height);
+        let largest = self.capabilities.limits.max_texture_dimension_2d;
+        let scale = scale.min(largest / width.max(height)).max(1);
+        (scale > 1)
+            .then(|| Supersample::new(&self.device, &self.config, &self.targets, &self.post, scale))
+    }
+
+    // what a bug report needs to rebuild the scene, stored in the screenshot
+    fn screenshot_text(&self) -> Vec<(String, String)> {
+        let mode = self.camera.mode();
+        vec![
+            ("Software".to_owned(), env!("CARGO_PKG_NAME").to_owned()),
+            ("Seed".to_owned(), self.context_data.seed.to_string()),
+            // x y z yaw pitch fovy, as a line of a camera path
+            ("Camera".to_owned(), self.camera.keyframe().pose_text()),
+            ("Camera mode".to_owned(), format!("{mode:?}")),
+        ]
+    }
 
-            for pipeline in &mut self.pipelines {
-                pipeline.render(&mut render_pass, &self.camera);
+    // renders the frame again into the readback texture for a screenshot,
+    // or when a capture needs its pixels
+    fn readback_frame(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        supersample: Option<&mut Supersample>,
+    ) -> bool {
+        let capture = self
+            .frame_capture
+            .as_ref()
+            .is_some_and(Capture::wants_pixels);
+        if self.screenshot.is_none() && !capture {
+            return false;
+        }
+        let (format, width, height) = (self.config.format, self.targets.width, self.targets.height);
+        if !self
+            .readback
+            .as_ref()
+            .is_some_and(|readback| readback.fits(format, width, height))
+        {
+            self.readback = Some(FrameReadback::new(&self.device, format, width, height));
+        }
+        let Some(readback) = &self.readback else {
+            return false;
+        };
+        if readback.is_busy() {
+            return false;
+        }
+        match supersample {
+            Some(supersample) => {
+                // the passes read the depth of the targets they are handed, so they draw into
+                // the large ones as they are
+                render_scene(
+                    encoder,
+                    &mut self.pipelines,
+                    &self.camera,
+                    &supersample.targets,
+                );
+                supersample
+                    .post
+                    .update(&self.queue, 0.0, &supersample.targets, &self.camera);
+                self.post.copy_exposure(encoder, &supersample.post);
+                supersample.post.render(encoder, &supersample.targets);
+                supersample.present(encoder, &readback.view);
             }
+            None => self.post.present(encoder, &readback.view),
+        }
+        readback.copy(encoder);
+        if self.screenshot.take().is_some() {
+            self.pending_screenshot = Some(self.screenshot_text());
         }
+        true
+    }
 
-        self.queue.submit(Some(encoder.finish()));
-        output.present();
+    // Asks for a new device after the old one was lost or ran out of memory. Frames stop
+    // until it arrives
+    pub fn recover_device(&mut self) {
+        if matches!(self.recovery, Some(Recovery::Pending(_))) {
+            return;
+        }
+        let Some(canvas) = self.window.canvas() else {
+            self.recovery = Some(Recovery::Failed);
+            return;
+        };
+        log::warn!("Recreating the graphics device");
+        if let Some(status) = &self.status {
+            status.show("The graphics device was lost, recreating it...");
+        }
+        let request: GpuRequest = Rc::default();
+        let result = request.clone();
+        let instance = self.instance.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let gpu = request_gpu(instance, canvas).await;
+            *result.borrow_mut() = Some(gpu);
+        });
+        self.recovery = Some(Recovery::Pending(request));
+    }
+
+    // false while there is no device to render with, called before every frame
+    pub fn device_ready(&mut self) -> bool {
+        if self.recovery.is_none() && self.device_lost.load(Ordering::Relaxed) {
+            self.recover_device();
+        }
+        let result = match &self.recovery {
+            None => return true,
+            Some(Recovery::Failed) => return false,
+            Some(Recovery::Pending(request)) => request.borrow_mut().take(),
+        };
+        let Some(result) = result else {
+            return false;
+        };
+        match result.and_then(|gpu| self.replace_gpu(gpu)) {
+            Ok(()) => {
+                self.recovery = None;
+                true
+            }
+            Err(e) => {
+                log::error!("Failed to recreate the graphics device: {e}");
+                if let Some(status) = &self.status {
+                    status.show(&format!(
+                        "The graphics device was lost and could not be recreated.\n\n{e}"
+                    ));
+                }
+                self.recovery = Some(Recovery::Failed);
+                false
+            }
+        }
+    }
+
+    // Rebuilds everything on the GPU for a new device. The camera, its path and the terrain
+    // stay, the settings of the passes go back to their defaults
+    fn replace_gpu(&mut self, gpu: Gpu) -> Result<(), ContextError> {
+        let capabilities = Capabilities::new(&gpu.adapter, &gpu.device);
+        let largest = capabilities.limits.max_texture_dimension_2d;
+        let PhysicalSize { width, height } = fit_size(self.context_data.size, largest);
+        self.config = surface_config(&gpu.surface, &gpu.adapter, width, height)?;
+        log::warn!(
+            "Recreated the graphics device with {} on {}",
+            capabilities.backend_name(),
+            capabilities.adapter
+        );
+        self.device_lost = watch_device(&gpu.device);
+        self.surface = gpu.surface;
+        self.device = gpu.device;
+        self.queue = gpu.queue;
+        self.capabilities = capabilities;
+        self.context_data.size = PhysicalSize::new(width, height);
+        self.surface.configure(&self.device, &self.config);
+
+        self.camera.recreate(&self.device);
+        let (scene_width, scene_height) = self.resolution.render_size(width, height, largest);
+        self.targets = RenderTargets::new(
+            &self.device,
+            scene_width,
+            scene_height,
+            self.antialiasing.sample_count(),
+        );
+        self.post = PostProcess::new(
+            &self.device,
+            &self.config,
+            &self.targets,
+            self.antialiasing,
+            &self.capabilities,
+        );
+        let pipelines = self
+            .pass_factories
+            .iter()
+            .map(|new_pass| {
+                new_pass(
+                    &self.device,
+                    &self.config,
+                    &self.camera,
+                    &self.targets,
+                    &self.capabilities,
+                )
+            })
+            .collect();
+        self.pipelines = pipelines;
+        self.readback = None;
+        self.pending_screenshot = None;
+        if let Some(status) = &self.status {
+            status.hide();
+        }
         Ok(())
     }
 
     pub fn add_render_pass<T: Renderable + 'static>(&mut self) {
-        let pass = T::new(&self.device, &self.config, &self.camera);
-        self.pipelines.push(Box::new(pass));
+        let new_pass: PassFactory = |device, config, camera, targets, capabilities| {
+            Box::new(T::new(device, config, camera, targets, capabilities))
+        };
+        self.pipelines.push(new_pass(
+            &self.device,
+            &self.config,
+            &self.camera,
+            &self.targets,
+            &self.capabilities,
+        ));
+        self.pass_factories.push(new_pass);
+    }
+
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.context_data.environment = Some(Rc::new(environment));
     }
 
     pub fn size(&mut self) -> &mut PhysicalSize<u32> {
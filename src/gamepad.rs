@@ -0,0 +1,204 @@
+use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+use wasm_bindgen::JsCast;
+use web_sys::{Gamepad, GamepadButton, GamepadMappingType};
+
+use crate::input::{ActionEvent, InputMap};
+use crate::render::AnalogInput;
+
+// Indices of the W3C standard gamepad layout
+const LEFT_STICK_X: u32 = 0;
+const LEFT_STICK_Y: u32 = 1;
+const RIGHT_STICK_X: u32 = 2;
+const RIGHT_STICK_Y: u32 = 3;
+const LEFT_TRIGGER: u32 = 6;
+const RIGHT_TRIGGER: u32 = 7;
+
+// Dead zones and look speed, the sticks move as in twin stick games
+#[derive(Clone, Copy, Debug)]
+pub struct GamepadSettings {
+    // stick deflection below this is ignored, the rest is rescaled to 0..1
+    pub stick_dead_zone: f32,
+    pub trigger_dead_zone: f32,
+    // radians per second at full deflection of the right stick
+    pub look_speed: f32,
+    pub invert_y: bool,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            stick_dead_zone: 0.15,
+            trigger_dead_zone: 0.05,
+            look_speed: 2.5,
+            invert_y: false,
+        }
+    }
+}
+
+// Camera input from the first connected gamepad, polled once a frame.
+// Sticks move and look, the right trigger rises and the left one sinks,
+// the buttons trigger actions through the `InputMap`
+pub struct Gamepads {
+    pub settings: GamepadSettings,
+    connected: Option<String>,
+    // button states of the last poll
+    pressed: Vec<bool>,
+}
+
+impl Gamepads {
+    pub fn new(settings: GamepadSettings) -> Self {
+        Self {
+            settings,
+            connected: None,
+            pressed: Vec::new(),
+        }
+    }
+
+    // neutral input when there is no gamepad, so releasing one stops the camera.
+    // Also returns the actions of buttons that went down or up since the last poll
+    pub fn poll(&mut self, map: &mut InputMap) -> (AnalogInput, Vec<ActionEvent>) {
+        let gamepad = first_gamepad();
+        let id = gamepad.as_ref().map(Gamepad::id);
+        if id != self.connected {
+            match &id {
+                Some(id) => log::info!("Gamepad connected: {id}"),
+                None => log::info!("Gamepad disconnected"),
+            }
+            self.connected = id;
+        }
+        let pressed: Vec<bool> = match &gamepad {
+            Some(gamepad) => gamepad
+                .buttons()
+                .iter()
+                .map(|button| {
+                    button
+                        .dyn_into::<GamepadButton>()
+                        .is_ok_and(|button| button.pressed())
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut events = Vec::new();
+        for button in 0..pressed.len().max(self.pressed.len()) {
+            let now = pressed.get(button).copied().unwrap_or(false);
+            let before = self.pressed.get(button).copied().unwrap_or(false);
+            if now != before
+                && let Some(event) = map.process_gamepad(button as u32, now)
+            {
+                events.push(event);
+            }
+        }
+        self.pressed = pressed;
+        let analog = match gamepad {
+            Some(gamepad) => self.read(&gamepad),
+            None => AnalogInput::default(),
+        };
+        (analog, events)
+    }
+
+    fn read(&self, gamepad: &Gamepad) -> AnalogInput {
+        let settings = &self.settings;
+        let axes = gamepad.axes();
+        let axis = |index: u32| axes.get(index).as_f64().unwrap_or(0.0) as f32;
+        let buttons = gamepad.buttons();
+        let button = |index: u32| {
+            buttons
+                .get(index)
+                .dyn_into::<GamepadButton>()
+                .map_or(0.0, |button| button.value() as f32)
+        };
+
+        // the standard layout has y pointing down
+        let dead_zone = settings.stick_dead_zone;
+        let movement = dead_zone_stick(
+            Vector2::new(axis(LEFT_STICK_X), -axis(LEFT_STICK_Y)),
+            dead_zone,
+        );
+        let look = dead_zone_stick(
+            Vector2::new(axis(RIGHT_STICK_X), -axis(RIGHT_STICK_Y)),
+            dead_zone,
+        );
+        let invert = if settings.invert_y { -1.0 } else { 1.0 };
+        let rise = dead_zone_trigger(button(RIGHT_TRIGGER), settings.trigger_dead_zone)
+            - dead_zone_trigger(button(LEFT_TRIGGER), settings.trigger_dead_zone);
+        AnalogInput {
+            movement: Vector3::new(movement.x, rise, movement.y),
+            look: Vector2::new(look.x, look.y * invert) * settings.look_speed,
+        }
+    }
+}
+
+// the Gamepad API lists disconnected slots as null
+fn first_gamepad() -> Option<Gamepad> {
+    let gamepads = web_sys::window()?.navigator().get_gamepads().ok()?;
+    gamepads
+        .iter()
+        .filter_map(|gamepad| gamepad.dyn_into::<Gamepad>().ok())
+        .find(|gamepad| gamepad.connected() && gamepad.mapping() == GamepadMappingType::Standard)
+}
+
+// radial, so diagonals are not cut short, and continuous at the edge of the dead zone
+pub fn dead_zone_stick(stick: Vector2<f32>, dead_zone: f32) -> Vector2<f32> {
+    let length = stick.magnitude();
+    if length <= dead_zone {
+        return Vector2::zero();
+    }
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick * (scaled / length)
+}
+
+pub fn dead_zone_trigger(value: f32, dead_zone: f32) -> f32 {
+    ((value - dead_zone) / (1.0 - dead_zone)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEAD_ZONE: f32 = 0.2;
+
+    #[test]
+    fn stick_inside_dead_zone_is_zero() {
+        for stick in [
+            Vector2::zero(),
+            Vector2::new(0.1, 0.0),
+            Vector2::new(-0.14, 0.14),
+            Vector2::new(0.0, -DEAD_ZONE),
+        ] {
+            assert_eq!(dead_zone_stick(stick, DEAD_ZONE), Vector2::zero());
+        }
+    }
+
+    #[test]
+    fn stick_full_deflection_is_one() {
+        for angle in [0.0_f32, 0.7, 2.0, 4.0] {
+            let stick = Vector2::new(angle.cos(), angle.sin());
+            let output = dead_zone_stick(stick, DEAD_ZONE);
+            assert!((output.magnitude() - 1.0).abs() < 1e-5, "{output:?}");
+            // the direction is kept
+            assert!((output - stick).magnitude() < 1e-5, "{output:?}");
+        }
+        // past the unit circle in the corners of square gates
+        let corner = dead_zone_stick(Vector2::new(1.0, 1.0), DEAD_ZONE);
+        assert!((corner.magnitude() - 1.0).abs() < 1e-5, "{corner:?}");
+    }
+
+    #[test]
+    fn stick_is_continuous_at_the_edge() {
+        let direction = Vector2::new(0.6, 0.8);
+        let inside = dead_zone_stick(direction * (DEAD_ZONE - 1e-4), DEAD_ZONE);
+        let outside = dead_zone_stick(direction * (DEAD_ZONE + 1e-4), DEAD_ZONE);
+        assert_eq!(inside, Vector2::zero());
+        assert!(outside.magnitude() < 1e-3, "{outside:?}");
+    }
+
+    #[test]
+    fn trigger_dead_zone() {
+        assert_eq!(dead_zone_trigger(0.0, DEAD_ZONE), 0.0);
+        assert_eq!(dead_zone_trigger(DEAD_ZONE * 0.5, DEAD_ZONE), 0.0);
+        assert_eq!(dead_zone_trigger(DEAD_ZONE, DEAD_ZONE), 0.0);
+        assert!((dead_zone_trigger(1.0, DEAD_ZONE) - 1.0).abs() < 1e-6);
+        assert!((dead_zone_trigger(0.6, DEAD_ZONE) - 0.5).abs() < 1e-6);
+        assert!(dead_zone_trigger(DEAD_ZONE + 1e-4, DEAD_ZONE) < 1e-3);
+    }
+}
@@ -0,0 +1,515 @@
+use wasm_bindgen::prelude::*;
+use winit::event::{ElementState, KeyEvent, MouseButton, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+// localStorage key of the bindings that differ from the defaults
+const STORAGE_KEY: &str = "input_map";
+
+// Everything a key, mouse button or gamepad button can do
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Boost,
+    CameraMode,
+    FlyAboveGround,
+    ToggleCapture,
+    ReleaseCapture,
+    Pan,
+    Regenerate,
+    AntiAliasing,
+    EarlierTime,
+    LaterTime,
+    DayCycle,
+    ThinnerFog,
+    ThickerFog,
+    FogColor,
+    CloudQuality,
+    FewerClouds,
+    MoreClouds,
+    WaterMode,
+    OceanSpectrum,
+    CalmerWater,
+    ChoppierWater,
+    LowerWater,
+    RaiseWater,
+    WaterLevelMode,
+    Tonemapper,
+    AutoExposure,
+    DarkerExposure,
+    BrighterExposure,
+    Bloom,
+    RecordKeyframe,
+    PlayPath,
+    ClearPath,
+    SavePath,
+    SlowerPlayback,
+    FasterPlayback,
+    CaptureFrames,
+    Screenshot,
+    ResolutionReadout,
+}
+
+// names used in the configuration, in declaration order
+const ACTION_NAMES: &[(Action, &str)] = &[
+    (Action::MoveForward, "move_forward"),
+    (Action::MoveBackward, "move_backward"),
+    (Action::MoveLeft, "move_left"),
+    (Action::MoveRight, "move_right"),
+    (Action::MoveUp, "move_up"),
+    (Action::MoveDown, "move_down"),
+    (Action::Boost, "boost"),
+    (Action::CameraMode, "camera_mode"),
+    (Action::FlyAboveGround, "fly_above_ground"),
+    (Action::ToggleCapture, "toggle_capture"),
+    (Action::ReleaseCapture, "release_capture"),
+    (Action::Pan, "pan"),
+    (Action::Regenerate, "regenerate"),
+    (Action::AntiAliasing, "anti_aliasing"),
+    (Action::EarlierTime, "earlier_time"),
+    (Action::LaterTime, "later_time"),
+    (Action::DayCycle, "day_cycle"),
+    (Action::ThinnerFog, "thinner_fog"),
+    (Action::ThickerFog, "thicker_fog"),
+    (Action::FogColor, "fog_color"),
+    (Action::CloudQuality, "cloud_quality"),
+    (Action::FewerClouds, "fewer_clouds"),
+    (Action::MoreClouds, "more_clouds"),
+    (Action::WaterMode, "water_mode"),
+    (Action::OceanSpectrum, "ocean_spectrum"),
+    (Action::CalmerWater, "calmer_water"),
+    (Action::ChoppierWater, "choppier_water"),
+    (Action::LowerWater, "lower_water"),
+    (Action::RaiseWater, "raise_water"),
+    (Action::WaterLevelMode, "water_level_mode"),
+    (Action::Tonemapper, "tonemapper"),
+    (Action::AutoExposure, "auto_exposure"),
+    (Action::DarkerExposure, "darker_exposure"),
+    (Action::BrighterExposure, "brighter_exposure"),
+    (Action::Bloom, "bloom"),
+    (Action::RecordKeyframe, "record_keyframe"),
+    (Action::PlayPath, "play_path"),
+    (Action::ClearPath, "clear_path"),
+    (Action::SavePath, "save_path"),
+    (Action::SlowerPlayback, "slower_playback"),
+    (Action::FasterPlayback, "faster_playback"),
+    (Action::CaptureFrames, "capture_frames"),
+    (Action::Screenshot, "screenshot"),
+    (Action::ResolutionReadout, "resolution_readout"),
+];
+
+impl Action {
+    pub const COUNT: usize = ACTION_NAMES.len();
+
+    pub fn name(self) -> &'static str {
+        ACTION_NAMES[self as usize].1
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTION_NAMES
+            .iter()
+            .find(|(_, action_name)| *action_name == name)
+            .map(|(action, _)| *action)
+    }
+}
+
+// A physical input, gamepad buttons are indices of the W3C standard layout
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(u32),
+}
+
+// buttons of the W3C standard layout
+const GAMEPAD_SOUTH: u32 = 0;
+const GAMEPAD_NORTH: u32 = 3;
+const GAMEPAD_LEFT_STICK: u32 = 10;
+
+const DEFAULT_BINDINGS: &[(Action, Binding)] = &[
+    (Action::MoveForward, Binding::Key(KeyCode::KeyW)),
+    (Action::MoveBackward, Binding::Key(KeyCode::KeyS)),
+    (Action::MoveLeft, Binding::Key(KeyCode::KeyA)),
+    (Action::MoveRight, Binding::Key(KeyCode::KeyD)),
+    (Action::MoveUp, Binding::Key(KeyCode::Space)),
+    (Action::MoveUp, Binding::Key(KeyCode::KeyE)),
+    (Action::MoveUp, Binding::Gamepad(GAMEPAD_SOUTH)),
+    (Action::MoveDown, Binding::Key(KeyCode::ControlLeft)),
+    (Action::MoveDown, Binding::Key(KeyCode::KeyQ)),
+    (Action::Boost, Binding::Key(KeyCode::ShiftLeft)),
+    (Action::Boost, Binding::Key(KeyCode::ShiftRight)),
+    (Action::Boost, Binding::Gamepad(GAMEPAD_LEFT_STICK)),
+    (Action::CameraMode, Binding::Key(KeyCode::KeyF)),
+    (Action::CameraMode, Binding::Gamepad(GAMEPAD_NORTH)),
+    (Action::FlyAboveGround, Binding::Key(KeyCode::KeyG)),
+    (Action::ToggleCapture, Binding::Mouse(MouseButton::Left)),
+    (Action::ReleaseCapture, Binding::Key(KeyCode::Escape)),
+    (Action::Pan, Binding::Mouse(MouseButton::Right)),
+    (Action::Regenerate, Binding::Key(KeyCode::KeyR)),
+    (Action::AntiAliasing, Binding::Key(KeyCode::KeyV)),
+    (Action::EarlierTime, Binding::Key(KeyCode::Minus)),
+    (Action::LaterTime, Binding::Key(KeyCode::Equal)),
+    (Action::DayCycle, Binding::Key(KeyCode::KeyL)),
+    (Action::ThinnerFog, Binding::Key(KeyCode::Digit9)),
+    (Action::ThickerFog, Binding::Key(KeyCode::Digit0)),
+    (Action::FogColor, Binding::Key(KeyCode::KeyH)),
+    (Action::CloudQuality, Binding::Key(KeyCode::KeyC)),
+    (Action::FewerClouds, Binding::Key(KeyCode::KeyN)),
+    (Action::MoreClouds, Binding::Key(KeyCode::KeyM)),
+    (Action::WaterMode, Binding::Key(KeyCode::KeyO)),
+    (Action::OceanSpectrum, Binding::Key(KeyCode::Slash)),
+    (Action::CalmerWater, Binding::Key(KeyCode::Comma)),
+    (Action::ChoppierWater, Binding::Key(KeyCode::Period)),
+    (Action::LowerWater, Binding::Key(KeyCode::BracketLeft)),
+    (Action::RaiseWater, Binding::Key(KeyCode::BracketRight)),
+    (Action::WaterLevelMode, Binding::Key(KeyCode::KeyP)),
+    (Action::Tonemapper, Binding::Key(KeyCode::KeyT)),
+    (Action::AutoExposure, Binding::Key(KeyCode::KeyY)),
+    (Action::DarkerExposure, Binding::Key(KeyCode::KeyU)),
+    (Action::BrighterExposure, Binding::Key(KeyCode::KeyI)),
+    (Action::Bloom, Binding::Key(KeyCode::KeyB)),
+    (Action::RecordKeyframe, Binding::Key(KeyCode::KeyK)),
+    (Action::PlayPath, Binding::Key(KeyCode::KeyJ)),
+    (Action::ClearPath, Binding::Key(KeyCode::Backspace)),
+    (Action::SavePath, Binding::Key(KeyCode::Backslash)),
+    (Action::SlowerPlayback, Binding::Key(KeyCode::Semicolon)),
+    (Action::FasterPlayback, Binding::Key(KeyCode::Quote)),
+    (Action::CaptureFrames, Binding::Key(KeyCode::F9)),
+    (Action::Screenshot, Binding::Key(KeyCode::F2)),
+    (Action::ResolutionReadout, Binding::Key(KeyCode::F3)),
+];
+
+// An action pressed, repeated or released
+#[derive(Clone, Copy, Debug)]
+pub struct ActionEvent {
+    pub action: Action,
+    // the action stays held while any of its bindings is
+    pub pressed: bool,
+    pub repeat: bool,
+}
+
+// Bindings of every action, several bindings may share an action.
+// Loaded from localStorage, where each line replaces the bindings of one action:
+//   move_forward = KeyZ, ArrowUp
+//   toggle_capture = Mouse:Left
+//   boost = ShiftLeft, Gamepad:10
+pub struct InputMap {
+    bindings: Vec<(Action, Binding)>,
+    // bindings currently down
+    held: Vec<Binding>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self {
+            bindings: DEFAULT_BINDINGS.to_vec(),
+            held: Vec::new(),
+        }
+    }
+}
+
+impl InputMap {
+    // the defaults with the stored changes applied
+    pub fn load() -> Self {
+        let mut map = Self::default();
+        if let Some(config) = storage().and_then(|storage| storage.get_item(STORAGE_KEY).ok()?) {
+            map.apply(&config);
+        }
+        map
+    }
+
+    // stores the bindings that differ from the defaults
+    pub fn save(&self) {
+        let defaults = Self::default();
+        let changed: Vec<Action> = ACTION_NAMES
+            .iter()
+            .map(|(action, _)| *action)
+            .filter(|action| self.bindings_of(*action).ne(defaults.bindings_of(*action)))
+            .collect();
+        let config = self.to_config(&changed);
+        if let Some(storage) = storage()
+            && let Err(e) = storage.set_item(STORAGE_KEY, &config)
+        {
+            log::warn!("Failed to save the input map: {e:?}");
+        }
+    }
+
+    // replaces the bindings of each action named in `config`, unknown names are skipped
+    pub fn apply(&mut self, config: &str) {
+        for line in config.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, bindings)) = line.split_once('=') else {
+                log::warn!("Input map line without '=': {line}");
+                continue;
+            };
+            let Some(action) = Action::from_name(name.trim()) else {
+                log::warn!("Unknown input action: {}", name.trim());
+                continue;
+            };
+            let bindings: Vec<Binding> = bindings
+                .split(',')
+                .map(str::trim)
+                .filter(|binding| !binding.is_empty())
+                .filter_map(|binding| {
+                    let parsed = parse_binding(binding);
+                    if parsed.is_none() {
+                        log::warn!("Unknown input binding: {binding}");
+                    }
+                    parsed
+                })
+                .collect();
+            self.bind(action, &bindings);
+        }
+    }
+
+    // the bindings are taken from any action that had them
+    pub fn bind(&mut self, action: Action, bindings: &[Binding]) {
+        self.bindings
+            .retain(|(bound, binding)| *bound != action && !bindings.contains(binding));
+        self.bindings
+            .extend(bindings.iter().map(|binding| (action, *binding)));
+    }
+
+    pub fn bindings_of(&self, action: Action) -> impl Iterator<Item = Binding> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(bound, _)| *bound == action)
+            .map(|(_, binding)| *binding)
+    }
+
+    // one line per action in the format `apply` reads
+    pub fn to_config(&self, actions: &[Action]) -> String {
+        let mut config = String::new();
+        for action in actions {
+            let bindings: Vec<String> = self.bindings_of(*action).map(binding_name).collect();
+            config += &format!("{} = {}\n", action.name(), bindings.join(", "));
+        }
+        config
+    }
+
+    // the action of a key or mouse button event
+    pub fn process(&mut self, event: &WindowEvent) -> Option<ActionEvent> {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state,
+                        physical_key: PhysicalKey::Code(code),
+                        repeat,
+                        ..
+                    },
+                ..
+            } => self.change(
+                Binding::Key(*code),
+                *state == ElementState::Pressed,
+                *repeat,
+            ),
+            WindowEvent::MouseInput { state, button, .. } => self.change(
+                Binding::Mouse(*button),
+                *state == ElementState::Pressed,
+                false,
+            ),
+            _ => None,
+        }
+    }
+
+    // the action of a gamepad button that went down or up
+    pub fn process_gamepad(&mut self, button: u32, pressed: bool) -> Option<ActionEvent> {
+        self.change(Binding::Gamepad(button), pressed, false)
+    }
+
+    fn change(&mut self, binding: Binding, pressed: bool, repeat: bool) -> Option<ActionEvent> {
+        self.held.retain(|held| *held != binding);
+        if pressed {
+            self.held.push(binding);
+        }
+        let action = self
+            .bindings
+            .iter()
+            .find(|(_, bound)| *bound == binding)
+            .map(|(action, _)| *action)?;
+        Some(ActionEvent {
+            action,
+            pressed: self
+                .bindings_of(action)
+                .any(|bound| self.held.contains(&bound)),
+            repeat,
+        })
+    }
+}
+
+// Rebinds from the page or the console and returns every binding, applied on the next load:
+//   set_input_bindings("move_forward = KeyZ\nmove_left = KeyQ")
+#[wasm_bindgen]
+pub fn set_input_bindings(config: &str) -> String {
+    let mut map = InputMap::load();
+    map.apply(config);
+    map.save();
+    let actions: Vec<Action> = ACTION_NAMES.iter().map(|(action, _)| *action).collect();
+    map.to_config(&actions)
+}
+
+// Back to the default bindings on the next load
+#[wasm_bindgen]
+pub fn reset_input_bindings() {
+    if let Some(storage) = storage() {
+        let _ = storage.remove_item(STORAGE_KEY);
+    }
+}
+
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+fn binding_name(binding: Binding) -> String {
+    match binding {
+        Binding::Key(code) => KEY_NAMES
+            .iter()
+            .find(|(key, _)| *key == code)
+            .map_or_else(|| format!("{code:?}"), |(_, name)| name.to_string()),
+        Binding::Mouse(MouseButton::Other(button)) => format!("Mouse:{button}"),
+        Binding::Mouse(button) => format!("Mouse:{button:?}"),
+        Binding::Gamepad(button) => format!("Gamepad:{button}"),
+    }
+}
+
+fn parse_binding(name: &str) -> Option<Binding> {
+    if let Some(button) = name.strip_prefix("Mouse:") {
+        let button = match button {
+            "Left" => MouseButton::Left,
+            "Right" => MouseButton::Right,
+            "Middle" => MouseButton::Middle,
+            "Back" => MouseButton::Back,
+            "Forward" => MouseButton::Forward,
+            other => MouseButton::Other(other.parse().ok()?),
+        };
+        return Some(Binding::Mouse(button));
+    }
+    if let Some(button) = name.strip_prefix("Gamepad:") {
+        return Some(Binding::Gamepad(button.parse().ok()?));
+    }
+    KEY_NAMES
+        .iter()
+        .find(|(_, key_name)| *key_name == name)
+        .map(|(key, _)| Binding::Key(*key))
+}
+
+// `KeyCode` names of the keys that can be bound, the physical US layout positions
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        &[$((KeyCode::$key, stringify!($key))),*]
+    };
+}
+
+const KEY_NAMES: &[(KeyCode, &str)] = key_names![
+    KeyA,
+    KeyB,
+    KeyC,
+    KeyD,
+    KeyE,
+    KeyF,
+    KeyG,
+    KeyH,
+    KeyI,
+    KeyJ,
+    KeyK,
+    KeyL,
+    KeyM,
+    KeyN,
+    KeyO,
+    KeyP,
+    KeyQ,
+    KeyR,
+    KeyS,
+    KeyT,
+    KeyU,
+    KeyV,
+    KeyW,
+    KeyX,
+    KeyY,
+    KeyZ,
+    Digit0,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Backquote,
+    Backslash,
+    BracketLeft,
+    BracketRight,
+    Comma,
+    Equal,
+    IntlBackslash,
+    IntlRo,
+    IntlYen,
+    Minus,
+    Period,
+    Quote,
+    Semicolon,
+    Slash,
+    AltLeft,
+    AltRight,
+    Backspace,
+    CapsLock,
+    ContextMenu,
+    ControlLeft,
+    ControlRight,
+    Enter,
+    SuperLeft,
+    SuperRight,
+    ShiftLeft,
+    ShiftRight,
+    Space,
+    Tab,
+    Delete,
+    End,
+    Home,
+    Insert,
+    PageDown,
+    PageUp,
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+    ArrowUp,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+    NumpadAdd,
+    NumpadDecimal,
+    NumpadDivide,
+    NumpadEnter,
+    NumpadMultiply,
+    NumpadSubtract,
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+];
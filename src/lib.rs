@@ -1,40 +1,55 @@
 use wasm_bindgen::prelude::*;
 use winit::{
-    error::EventLoopError,
     event::*,
     event_loop::EventLoop,
-    keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowBuilder},
 };
 
+mod assets;
+mod capture;
 mod context;
-use context::Context;
+use context::{Context, ContextError};
+mod gamepad;
+mod input;
+mod overlay;
+mod touch;
 mod render;
 
-fn create_window(title: &str) -> Result<(Window, EventLoop<()>), EventLoopError> {
-    let event_loop = EventLoop::new()?;
+fn create_window(title: &str) -> Result<(Window, EventLoop<()>), ContextError> {
+    let event_loop = EventLoop::new().map_err(|e| ContextError::Window(e.to_string()))?;
     use wasm_bindgen::JsCast;
     use winit::platform::web::WindowBuilderExtWebSys;
     let canvas = web_sys::window()
         .and_then(|win| win.document())
         .and_then(|doc| doc.get_element_by_id("canvas"))
         .and_then(|elem| elem.dyn_into::<web_sys::HtmlCanvasElement>().ok())
-        .expect("Failed to get canvas element");
+        .ok_or(ContextError::MissingCanvas)?;
     let window = WindowBuilder::new()
         .with_canvas(Some(canvas))
         .with_title(title)
-        .build(&event_loop)?;
+        .build(&event_loop)
+        .map_err(|e| ContextError::Window(e.to_string()))?;
     Ok((window, event_loop))
 }
 
+// logs the error and shows it on the page in place of the scene
+fn report_error(error: &ContextError) {
+    log::error!("{error}");
+    if let Some(overlay) = overlay::TextOverlay::error() {
+        overlay.show(&format!("The renderer could not start.\n\n{error}"));
+    }
+}
+
 #[allow(unused)]
 fn register_passes(context: &mut Context) {
     use render::PerlinPass;
     use render::SkyPass;
     use render::WaterPass;
+    use render::CloudPass;
     context.add_render_pass::<SkyPass>();
     context.add_render_pass::<PerlinPass>();
     context.add_render_pass::<WaterPass>();
+    context.add_render_pass::<CloudPass>();
 }
 
 #[wasm_bindgen(start)]
@@ -42,8 +57,24 @@ async fn run() {
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
     console_log::init_with_level(log::Level::Warn).expect("Couldn't initialize logger");
 
-    let (window, event_loop) = create_window("Hello Winit").expect("Failed to create window");
-    let mut context = Context::new(&window).await;
+    let (window, event_loop) = match create_window("Hello Winit") {
+        Ok(window) => window,
+        Err(e) => return report_error(&e),
+    };
+    let mut context = match Context::new(&window).await {
+        Ok(context) => context,
+        Err(e) => return report_error(&e),
+    };
+    if let Some(environment) = assets::load_environment().await {
+        context.set_environment(environment);
+    }
+    if let Some(path) = assets::load_camera_path().await {
+        context.camera.set_path(path);
+    }
+    // ?capture starts capturing right away, following the ?path flythrough if there is one
+    if assets::query_parameter("capture").is_some() {
+        context.toggle_frame_capture();
+    }
     register_passes(&mut context);
 
     let mut surface_configured = false;
@@ -56,11 +87,7 @@ async fn run() {
                     event: DeviceEvent::MouseMotion { delta },
                     ..
                 } => {
-                    if context.is_cursor_captured() {
-                        context
-                            .camera
-                            .process_mouse((delta.0 as f32, delta.1 as f32).into());
-                    }
+                    context.process_mouse((delta.0 as f32, delta.1 as f32).into());
                 }
                 Event::WindowEvent {
                     ref event,
@@ -73,26 +100,6 @@ async fn run() {
                                 log::info!("Close requested");
                                 control_flow.exit();
                             }
-                            WindowEvent::KeyboardInput {
-                                event:
-                                    KeyEvent {
-                                        state: ElementState::Pressed,
-                                        physical_key: PhysicalKey::Code(KeyCode::Escape),
-                                        ..
-                                    },
-                                ..
-                            } => {
-                                if context.is_cursor_captured() {
-                                    context.toggle_cursor_capture();
-                                }
-                            }
-                            WindowEvent::MouseInput {
-                                state: ElementState::Pressed,
-                                button: MouseButton::Left,
-                                ..
-                            } => {
-                                context.toggle_cursor_capture(); // click to switch
-                            }
                             WindowEvent::Resized(physical_size) => {
                                 log::info!("physical_size: {physical_size:?}");
                                 surface_configured = true;
@@ -102,12 +109,20 @@ async fn run() {
                                 // This tells winit that we want another frame after this one
                                 context.window().request_redraw();
 
-                                if !surface_configured {
+                                if !surface_configured || !context.device_ready() {
+                                    return;
+                                }
+
+                                context.poll_capture();
+                                if !context.frame_due() {
                                     return;
                                 }
 
                                 let now = instant::Instant::now();
-                                let dt = now - last_render_time;
+                                // a capture steps the scene by a fixed time per frame
+                                let dt = context
+                                    .capture_timestep()
+                                    .unwrap_or(now - last_render_time);
                                 last_render_time = now;
 
                                 log::info!("FPS: {:.2}", 1.0 / dt.as_secs_f32());
@@ -119,12 +134,13 @@ async fn run() {
                                     Err(
                                         wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated,
                                     ) => context.resize(None),
-                                    // The system is out of memory, we should probably quit
+                                    // Out of memory, start again on a new device
                                     Err(
-                                        wgpu::SurfaceError::OutOfMemory | wgpu::SurfaceError::Other,
+                                        e @ (wgpu::SurfaceError::OutOfMemory
+                                        | wgpu::SurfaceError::Other),
                                     ) => {
-                                        log::error!("OutOfMemory");
-                                        control_flow.exit();
+                                        log::error!("{e}");
+                                        context.recover_device();
                                     }
 
                                     // This happens when the a frame takes too long to present
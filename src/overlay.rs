@@ -0,0 +1,62 @@
+use wasm_bindgen::JsCast;
+use web_sys::HtmlElement;
+
+// A block of text laid over the page, for readouts and messages
+pub struct TextOverlay {
+    element: HtmlElement,
+}
+
+impl TextOverlay {
+    // hidden until `show`, `style` places and colours it
+    pub fn new(style: &[(&str, &str)]) -> Option<Self> {
+        let document = web_sys::window()?.document()?;
+        let element: HtmlElement = document.create_element("div").ok()?.dyn_into().ok()?;
+        let defaults = [
+            ("position", "fixed"),
+            ("font", "12px monospace"),
+            ("white-space", "pre"),
+            ("pointer-events", "none"),
+            ("display", "none"),
+        ];
+        let css = element.style();
+        for (property, value) in defaults.iter().chain(style) {
+            css.set_property(property, value).ok()?;
+        }
+        document.body()?.append_child(&element).ok()?;
+        Some(Self { element })
+    }
+
+    // in the middle of the page, for errors that stop the renderer
+    pub fn error() -> Option<Self> {
+        Self::new(&[
+            ("top", "50%"),
+            ("left", "50%"),
+            ("transform", "translate(-50%, -50%)"),
+            ("max-width", "80vw"),
+            ("padding", "16px 20px"),
+            ("font", "14px monospace"),
+            ("white-space", "pre-wrap"),
+            ("color", "#ffd0d0"),
+            ("background", "rgba(60, 0, 0, 0.9)"),
+            ("border", "1px solid #ff6060"),
+            // the message can be selected and copied into a bug report
+            ("pointer-events", "auto"),
+        ])
+    }
+
+    pub fn show(&self, text: &str) {
+        self.element.set_text_content(Some(text));
+        let _ = self.element.style().set_property("display", "block");
+    }
+
+    pub fn hide(&self) {
+        let _ = self.element.style().set_property("display", "none");
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.element
+            .style()
+            .get_property_value("display")
+            .is_ok_and(|display| display != "none")
+    }
+}
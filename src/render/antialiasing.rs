@@ -0,0 +1,399 @@
+use cgmath::{Matrix4, SquareMatrix, Vector2};
+use wgpu::util::DeviceExt;
+
+use super::Camera;
+use super::post::{fullscreen_pass, fullscreen_pipeline, sampler_entry, texture_entry};
+use super::targets::{self, RenderTargets};
+
+// FXAA runs on the tone mapped image, display encoded
+const LDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+// length of the Halton jitter sequence
+const JITTER_SAMPLES: u32 = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AntiAliasing {
+    Off,
+    Msaa4x,
+    Fxaa,
+    Taa,
+}
+
+impl AntiAliasing {
+    pub fn next(self) -> Self {
+        match self {
+            AntiAliasing::Off => AntiAliasing::Msaa4x,
+            AntiAliasing::Msaa4x => AntiAliasing::Fxaa,
+            AntiAliasing::Fxaa => AntiAliasing::Taa,
+            AntiAliasing::Taa => AntiAliasing::Off,
+        }
+    }
+
+    // of the opaque stage, the transparent stage always renders into the resolved target
+    pub fn sample_count(self) -> u32 {
+        match self {
+            AntiAliasing::Msaa4x => 4,
+            _ => 1,
+        }
+    }
+}
+
+// radical inverse of `index` in `base`, a low discrepancy sequence in [0, 1)
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+fn create_target(
+    device: &wgpu::Device,
+    label: &str,
+    targets: &RenderTargets,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: targets.width,
+            height: targets.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | usage,
+        view_formats: &[],
+    })
+}
+
+fn create_sampler(device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+// Tone mapping renders into `ldr_view`, FXAA filters it onto the surface
+pub struct Fxaa {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    pub ldr_view: wgpu::TextureView,
+}
+
+impl Fxaa {
+    pub const FORMAT: wgpu::TextureFormat = LDR_FORMAT;
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &RenderTargets,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("FXAA Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::ShaderStages::FRAGMENT),
+                sampler_entry(1),
+            ],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("fxaa.wgsl"));
+        // sRGB surfaces encode on write, so hand them linear values
+        let fragment_entry = if config.format.is_srgb() {
+            "fs_main"
+        } else {
+            "fs_encoded"
+        };
+        let pipeline = fullscreen_pipeline(
+            device,
+            "FXAA Pipeline",
+            &shader,
+            fragment_entry,
+            &bind_group_layout,
+            config.format,
+            None,
+        );
+        let sampler = create_sampler(device, "FXAA Sampler");
+        let (ldr_view, bind_group) =
+            Self::create_target(device, &bind_group_layout, &sampler, targets);
+        Self {
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            sampler,
+            ldr_view,
+        }
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        targets: &RenderTargets,
+    ) -> (wgpu::TextureView, wgpu::BindGroup) {
+        let ldr_view = create_target(
+            device,
+            "FXAA Input Texture",
+            targets,
+            LDR_FORMAT,
+            wgpu::TextureUsages::empty(),
+        )
+        .create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("FXAA Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&ldr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+        (ldr_view, bind_group)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        (self.ldr_view, self.bind_group) =
+            Self::create_target(device, &self.bind_group_layout, &self.sampler, targets);
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        fullscreen_pass(
+            encoder,
+            "FXAA Render Pass",
+            view,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &self.pipeline,
+            &self.bind_group,
+        );
+    }
+}
+
+// Mirrors `Taa` in taa.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    prev_view_proj: [[f32; 4]; 4],
+    reset: u32,
+    _padding: [u32; 3],
+}
+
+// Resolves the jittered HDR scene against the reprojected history into `output_view`
+pub struct Taa {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    output_texture: wgpu::Texture,
+    pub output_view: wgpu::TextureView,
+    history_texture: wgpu::Texture,
+    frame: u32,
+    prev_view_proj: Matrix4<f32>,
+    reset: bool,
+}
+
+impl Taa {
+    pub fn new(device: &wgpu::Device, targets: &RenderTargets) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TAA Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::ShaderStages::FRAGMENT),
+                texture_entry(1, wgpu::ShaderStages::FRAGMENT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                sampler_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("TAA Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[TaaUniform {
+                inv_view_proj: Matrix4::identity().into(),
+                prev_view_proj: Matrix4::identity().into(),
+                reset: 1,
+                _padding: Default::default(),
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("taa.wgsl"));
+        let pipeline = fullscreen_pipeline(
+            device,
+            "TAA Pipeline",
+            &shader,
+            "fs_main",
+            &bind_group_layout,
+            targets::HDR_FORMAT,
+            None,
+        );
+        let sampler = create_sampler(device, "TAA Sampler");
+        let (output_texture, history_texture) = Self::create_textures(device, targets);
+        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            targets,
+            &history_texture,
+            &sampler,
+            &uniform_buffer,
+        );
+        Self {
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            sampler,
+            uniform_buffer,
+            output_texture,
+            output_view,
+            history_texture,
+            frame: 0,
+            prev_view_proj: Matrix4::identity(),
+            reset: true,
+        }
+    }
+
+    fn create_textures(
+        device: &wgpu::Device,
+        targets: &RenderTargets,
+    ) -> (wgpu::Texture, wgpu::Texture) {
+        let output = create_target(
+            device,
+            "TAA Output Texture",
+            targets,
+            targets::HDR_FORMAT,
+            wgpu::TextureUsages::COPY_SRC,
+        );
+        let history = create_target(
+            device,
+            "TAA History Texture",
+            targets,
+            targets::HDR_FORMAT,
+            wgpu::TextureUsages::COPY_DST,
+        );
+        (output, history)
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        targets: &RenderTargets,
+        history_texture: &wgpu::Texture,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        let history_view = history_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TAA Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&targets.hdr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&history_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&targets.depth_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        (self.output_texture, self.history_texture) = Self::create_textures(device, targets);
+        self.output_view = self
+            .output_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            targets,
+            &self.history_texture,
+            &self.sampler,
+            &self.uniform_buffer,
+        );
+        self.reset = true;
+    }
+
+    // subpixel offset in NDC for the next frame
+    pub fn next_jitter(&mut self, targets: &RenderTargets) -> Vector2<f32> {
+        self.frame = (self.frame + 1) % JITTER_SAMPLES;
+        let index = self.frame + 1;
+        Vector2::new(
+            (halton(index, 2) - 0.5) * 2.0 / targets.width as f32,
+            (halton(index, 3) - 0.5) * 2.0 / targets.height as f32,
+        )
+    }
+
+    // called after the camera is updated for the frame
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let uniform = TaaUniform {
+            inv_view_proj: camera.inverse_view_projection().into(),
+            prev_view_proj: self.prev_view_proj.into(),
+            reset: self.reset as u32,
+            _padding: Default::default(),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        self.prev_view_proj = camera.view_projection();
+        self.reset = false;
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        fullscreen_pass(
+            encoder,
+            "TAA Render Pass",
+            &self.output_view,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &self.pipeline,
+            &self.bind_group,
+        );
+        encoder.copy_texture_to_texture(
+            self.output_texture.as_image_copy(),
+            self.history_texture.as_image_copy(),
+            self.output_texture.size(),
+        );
+    }
+}
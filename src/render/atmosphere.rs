@@ -0,0 +1,170 @@
+use cgmath::*;
+use std::f32::consts::PI;
+
+// Haze of the Preetham sky, 2 --> very clear, 10 --> hazy
+pub const TURBIDITY: f32 = 2.5;
+// kcd/m^2 --> linear render units, keeps the noon sky around 0.5
+pub const SKY_EXPOSURE: f32 = 0.05;
+// latitude of the viewer, sets how high the sun climbs at noon
+const LATITUDE: Deg<f32> = Deg(35.0);
+// extinction of direct sunlight per air mass
+const SUN_EXTINCTION: [f32; 3] = [0.05, 0.1, 0.22];
+const NIGHT_SKY: [f32; 3] = [0.004, 0.006, 0.015];
+const NIGHT_AMBIENT: [f32; 3] = [0.02, 0.025, 0.05];
+
+// Exponential height fog shared by the terrain, water and sky shaders
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    // extinction per world unit at `base_height`
+    pub density: f32,
+    // density falls off as exp(-height_falloff * (y - base_height))
+    pub height_falloff: f32,
+    pub base_height: f32,
+    // None follows the sky around the horizon
+    pub color: Option<Vector3<f32>>,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            density: 0.002,
+            height_falloff: 0.05,
+            base_height: 0.0,
+            color: None,
+        }
+    }
+}
+
+// Light shared by the terrain, water and sky shaders
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lighting {
+    // towards the sun
+    pub sun_direction: Vector3<f32>,
+    pub sun_color: Vector3<f32>,
+    pub ambient_color: Vector3<f32>,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        lighting(sun_direction(12.0))
+    }
+}
+
+// Direction towards the sun at `hours` past midnight, rising in -x and setting in +x
+pub fn sun_direction(hours: f32) -> Vector3<f32> {
+    let hour_angle = (hours - 12.0) / 24.0 * 2.0 * PI;
+    let latitude = Rad::from(LATITUDE).0;
+    Vector3::new(
+        hour_angle.sin(),
+        hour_angle.cos() * latitude.cos(),
+        hour_angle.cos() * latitude.sin(),
+    )
+    .normalize()
+}
+
+// 0 at night, 1 once the sun is clear of the horizon
+pub fn daylight(sun: Vector3<f32>) -> f32 {
+    smoothstep(-0.12, 0.05, sun.y)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Perez distribution, coefficients [A, B, C, D, E]
+fn perez(theta: f32, gamma: f32, c: [f32; 5]) -> f32 {
+    (1.0 + c[0] * (c[1] / theta.cos()).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vector3<f32> {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Vector3::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+}
+
+// Preetham et al. 1999 "A Practical Analytic Model for Daylight", mirrored in skybox.wgsl
+fn preetham(direction: Vector3<f32>, sun: Vector3<f32>) -> Vector3<f32> {
+    let t = TURBIDITY;
+    // the model is only valid with the sun above the horizon
+    let sun = Vector3::new(sun.x, sun.y.max(0.02), sun.z).normalize();
+    let theta_s = sun.y.acos();
+    let theta = direction.y.max(0.001).acos();
+    let gamma = direction.dot(sun).clamp(-1.0, 1.0).acos();
+
+    let coeff_y = [
+        0.1787 * t - 1.4630,
+        -0.3554 * t + 0.4275,
+        -0.0227 * t + 5.3251,
+        0.1206 * t - 2.5771,
+        -0.0670 * t + 0.3703,
+    ];
+    let coeff_x = [
+        -0.0193 * t - 0.2592,
+        -0.0665 * t + 0.0008,
+        -0.0004 * t + 0.2125,
+        -0.0641 * t - 0.8989,
+        -0.0033 * t + 0.0452,
+    ];
+    let coeff_yc = [
+        -0.0167 * t - 0.2608,
+        -0.0950 * t + 0.0092,
+        -0.0079 * t + 0.2102,
+        -0.0441 * t - 1.6537,
+        -0.0109 * t + 0.0529,
+    ];
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let (t1, t2, t3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+    let zenith_x = t * t * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * t1)
+        + t * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * t1 + 0.00394)
+        + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * t1 + 0.25886);
+    let zenith_yc = t * t * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * t1)
+        + t * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * t1 + 0.00516)
+        + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * t1 + 0.26688);
+
+    let luminance =
+        zenith_y * perez(theta, gamma, coeff_y) / perez(0.0, theta_s, coeff_y) * SKY_EXPOSURE;
+    let x = zenith_x * perez(theta, gamma, coeff_x) / perez(0.0, theta_s, coeff_x);
+    let y = zenith_yc * perez(theta, gamma, coeff_yc) / perez(0.0, theta_s, coeff_yc);
+    let rgb = xyy_to_rgb(x, y, luminance.max(0.0));
+    Vector3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+}
+
+// Sky colour seen along `direction`, without the sun disc
+pub fn sky_radiance(direction: Vector3<f32>, sun: Vector3<f32>) -> Vector3<f32> {
+    let day = daylight(sun);
+    Vector3::from(NIGHT_SKY).lerp(preetham(direction.normalize(), sun), day)
+}
+
+// Sky without the sun disc, darkened below the horizon where the terrain usually is.
+// Mirrors `environment` in skybox.wgsl
+pub fn environment_radiance(direction: Vector3<f32>, sun: Vector3<f32>) -> Vector3<f32> {
+    let direction = direction.normalize();
+    sky_radiance(direction, sun) * (0.4 + 0.6 * smoothstep(-0.2, 0.0, direction.y))
+}
+
+// Colour of direct sunlight after passing through the atmosphere
+pub fn sun_color(sun: Vector3<f32>) -> Vector3<f32> {
+    let zenith = Deg::from(Rad(sun.y.clamp(-1.0, 1.0).acos())).0.min(90.0);
+    // Kasten and Young 1989 relative air mass
+    let air_mass = 1.0 / (Rad::from(Deg(zenith)).0.cos() + 0.50572 * (96.07995 - zenith).powf(-1.6364));
+    let transmittance = Vector3::from(SUN_EXTINCTION).map(|beta| (-beta * air_mass).exp());
+    transmittance * smoothstep(-0.02, 0.05, sun.y)
+}
+
+pub fn lighting(sun: Vector3<f32>) -> Lighting {
+    // the zenith sky stands in for the whole dome
+    let sky = sky_radiance(Vector3::unit_y(), sun);
+    Lighting {
+        sun_direction: sun,
+        sun_color: sun_color(sun),
+        ambient_color: Vector3::from(NIGHT_AMBIENT) + sky,
+    }
+}
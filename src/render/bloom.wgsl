@@ -0,0 +1,83 @@
+// Bloom as a chain of half resolution downsamples blurred back up, Jimenez 2014
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+// radiance above this starts to bloom, eased in over the knee
+const THRESHOLD: f32 = 1.0;
+const KNEE: f32 = 0.5;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    out.position = vec4<f32>(pos, 0.0, 1.0);
+    out.uv = vec2<f32>(pos.x * 0.5 + 0.5, 0.5 - pos.y * 0.5);
+    return out;
+}
+
+fn tap(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(source_texture, source_sampler, uv, 0.0).rgb;
+}
+
+// 13 tap filter, a box of four overlapping 4x4 boxes without the aliasing of a plain 2x2
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    let a = tap(uv + texel * vec2<f32>(-2.0, -2.0));
+    let b = tap(uv + texel * vec2<f32>(0.0, -2.0));
+    let c = tap(uv + texel * vec2<f32>(2.0, -2.0));
+    let d = tap(uv + texel * vec2<f32>(-2.0, 0.0));
+    let e = tap(uv);
+    let f = tap(uv + texel * vec2<f32>(2.0, 0.0));
+    let g = tap(uv + texel * vec2<f32>(-2.0, 2.0));
+    let h = tap(uv + texel * vec2<f32>(0.0, 2.0));
+    let i = tap(uv + texel * vec2<f32>(2.0, 2.0));
+    let j = tap(uv + texel * vec2<f32>(-1.0, -1.0));
+    let k = tap(uv + texel * vec2<f32>(1.0, -1.0));
+    let l = tap(uv + texel * vec2<f32>(-1.0, 1.0));
+    let m = tap(uv + texel * vec2<f32>(1.0, 1.0));
+    return e * 0.125 + (a + c + g + i) * 0.03125 + (b + d + f + h) * 0.0625 + (j + k + l + m) * 0.125;
+}
+
+// soft threshold so the bloom doesn't switch on at a hard edge
+fn prefilter(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - THRESHOLD + KNEE, 0.0, 2.0 * KNEE);
+    soft = soft * soft / (4.0 * KNEE + 0.0001);
+    let contribution = max(soft, brightness - THRESHOLD) / max(brightness, 0.0001);
+    return color * contribution;
+}
+
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the sun disc can be far brighter than f16 bloom needs, keep it from flooding the chain
+    return vec4<f32>(min(prefilter(downsample(in.uv)), vec3<f32>(1000.0)), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// 3x3 tent, added onto the next larger level
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    var color = tap(uv_offset(in.uv, texel, -1.0, -1.0)) + tap(uv_offset(in.uv, texel, 1.0, -1.0))
+        + tap(uv_offset(in.uv, texel, -1.0, 1.0)) + tap(uv_offset(in.uv, texel, 1.0, 1.0));
+    color += (tap(uv_offset(in.uv, texel, 0.0, -1.0)) + tap(uv_offset(in.uv, texel, -1.0, 0.0))
+        + tap(uv_offset(in.uv, texel, 1.0, 0.0)) + tap(uv_offset(in.uv, texel, 0.0, 1.0))) * 2.0;
+    color += tap(in.uv) * 4.0;
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+fn uv_offset(uv: vec2<f32>, texel: vec2<f32>, x: f32, y: f32) -> vec2<f32> {
+    return uv + texel * vec2<f32>(x, y);
+}
@@ -1,8 +1,17 @@
 use cgmath::*;
-use std::f32::consts::FRAC_PI_2;
 use std::time::Duration;
-use winit::event::ElementState;
-use winit::keyboard::KeyCode;
+
+use super::atmosphere::{Fog, Lighting};
+use super::controller::{
+    AnalogInput, CameraController, CameraMode, CameraPose, Ground, LookSettings, MouseLook,
+};
+use super::environment::Environment;
+use super::heightmap::Heightmap;
+use super::ibl::Ibl;
+use super::path::{CameraPath, Keyframe};
+use super::waves::GerstnerWave;
+use crate::input::ActionEvent;
+use std::rc::Rc;
 
 #[rustfmt::skip]
 const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -15,13 +24,18 @@ const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 const YAW: Deg<f32> = Deg(-90.0); // 0 --> positive x-axis
 const PITCH: Deg<f32> = Deg(-00.0); // 90 --> positive y-axis
 
-const SPEED: f32 = 8.0;
-const SENSITIVITY: f32 = 1.0;
 const ZOOM: Deg<f32> = Deg(45.0);
 const ZNEAR: f32 = 0.1;
 const ZFAR: f32 = 100000.0;
-const WORLD_UP: Vector3<f32> = Vector3::new(0.0, 1.0, 0.0);
+// seconds to blend between the poses of two modes
+const TRANSITION_TIME: f32 = 0.6;
 const START_POSITION: Point3<f32> = Point3::new(0.0, 50.0, 0.0);
+// factor of each playback speed step
+const PLAYBACK_SPEED_STEP: f32 = 1.25;
+const MIN_PLAYBACK_SPEED: f32 = 1.0 / 16.0;
+const MAX_PLAYBACK_SPEED: f32 = 16.0;
+// recording onto a loaded path resumes this many seconds after its last keyframe
+const RESUME_SECONDS: f32 = 1.0;
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -30,6 +44,15 @@ struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
     pub time: f32,
     _padding: [f32; 3],
+    pub inv_view_proj: [[f32; 4]; 4],
+    // scene lighting, see `atmosphere::Lighting`
+    pub sun_direction: [f32; 4],
+    pub sun_color: [f32; 4],
+    pub ambient_color: [f32; 4],
+    // rgb fixed colour, w 1 --> follow the sky instead
+    pub fog_color: [f32; 4],
+    // density, height falloff, base height
+    pub fog_params: [f32; 4],
 }
 
 impl CameraUniform {
@@ -39,28 +62,52 @@ impl CameraUniform {
             view_proj: cgmath::Matrix4::identity().into(),
             time: 0.0,
             _padding: Default::default(),
+            inv_view_proj: cgmath::Matrix4::identity().into(),
+            sun_direction: [0.0, 1.0, 0.0, 0.0],
+            sun_color: [1.0; 4],
+            ambient_color: [0.0; 4],
+            fog_color: [0.0, 0.0, 0.0, 1.0],
+            fog_params: [0.0; 4],
         }
     }
 }
 
+fn start_pose() -> CameraPose {
+    CameraPose {
+        eye: START_POSITION,
+        yaw: YAW.into(),
+        pitch: PITCH.into(),
+    }
+}
+
 pub struct Camera {
     // state
-    eye: Point3<f32>,
-    yaw: Rad<f32>,
-    pitch: Rad<f32>,
+    pose: CameraPose,
+    mode: CameraMode,
+    controller: Box<dyn CameraController>,
+    // pose at the switch and seconds since, while blending into a new mode
+    transition: Option<(CameraPose, f32)>,
+    ground: Ground,
+    look: MouseLook,
+    look_settings: LookSettings,
+    // recorded keyframes, and the uniform time of the first one once recording has started
+    path: CameraPath,
+    path_start: Option<f32>,
+    // seconds along the path while playing it back
+    playback: Option<f32>,
+    playback_speed: f32,
     // projection
     aspect: f32,
     fovy: Rad<f32>,
     znear: f32,
     zfar: f32,
-    // movement
-    speed: f32,
-    sensitivity: f32,
-    movement: Vector4<f32>,
-    rotation: Vector2<f32>,
+    // subpixel offset in NDC, set each frame for temporal anti-aliasing
+    jitter: Vector2<f32>,
+    view_proj: Matrix4<f32>,
     // render
     buffer: wgpu::Buffer,
     uniform: CameraUniform,
+    ibl: Ibl,
     pub bind_group: wgpu::BindGroup,
     pub bind_group_layout: wgpu::BindGroupLayout,
 }
@@ -73,73 +120,195 @@ impl Camera {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+        let ibl = Ibl::new(device, &buffer);
+        let cube_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                multisampled: false,
+            },
+            count: None,
+        };
+        // 0: camera, 1: sky environment, 2: its sampler, 3: SH irradiance, 4: specular
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Camera Bind Group Layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                cube_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
                 },
-                count: None,
-            }],
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                cube_entry(4),
+            ],
         });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Camera Bind Group"),
             layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                    buffer: &buffer,
-                    offset: 0,
-                    size: None,
-                }),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&ibl.environment_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&ibl.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: ibl.irradiance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&ibl.specular_view),
+                },
+            ],
         });
+        let pose = start_pose();
         Self {
             // state
-            eye: START_POSITION,
-            yaw: YAW.into(),
-            pitch: PITCH.into(),
+            pose,
+            mode: CameraMode::Fly,
+            controller: CameraMode::Fly.controller(&pose),
+            transition: None,
+            ground: Ground::default(),
+            look: MouseLook::new(),
+            look_settings: LookSettings::default(),
+            path: CameraPath::default(),
+            path_start: None,
+            playback: None,
+            playback_speed: 1.0,
             // projection
             aspect: width as f32 / height as f32,
             fovy: ZOOM.into(),
             znear: ZNEAR,
             zfar: ZFAR,
-            // movement
-            speed: SPEED,
-            sensitivity: SENSITIVITY,
-            movement: Vector4::zero(),
-            rotation: Vector2::zero(),
+            jitter: Vector2::zero(),
+            view_proj: Matrix4::identity(),
             // render
             buffer: buffer,
             uniform: CameraUniform::new(),
+            ibl,
             bind_group: bind_group,
             bind_group_layout: bind_group_layout,
         }
     }
 
-    fn front_right_up(&self) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
-        let front = Vector3::new(
-            self.yaw.cos() * self.pitch.cos(),
-            self.pitch.sin(),
-            self.yaw.sin() * self.pitch.cos(),
-        )
-        .normalize();
-        let right = front.cross(WORLD_UP).normalize();
-        let up = right.cross(front).normalize();
-        (front, right, up)
+    // moves the buffers to a new device after the old one was lost, keeping the pose and the path
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        let fresh = Camera::new(device, 1, 1);
+        self.buffer = fresh.buffer;
+        self.ibl = fresh.ibl;
+        self.bind_group = fresh.bind_group;
+        self.bind_group_layout = fresh.bind_group_layout;
     }
 
-    fn update_uniform(&mut self, front: Vector3<f32>, up: Vector3<f32>) {
-        let center = self.eye + front;
-        let view = Matrix4::look_at_rh(self.eye, center, up);
+    fn update_uniform(&mut self) {
+        let (front, _, up) = self.pose.front_right_up();
+        let eye = self.pose.eye;
+        let view = Matrix4::look_at_rh(eye, eye + front, up);
         let proj = perspective(self.fovy, self.aspect, self.znear, self.zfar);
 
-        self.uniform.view_position = self.eye.to_homogeneous().into();
-        self.uniform.view_proj = (OPENGL_TO_WGPU_MATRIX * proj * view).into();
+        self.uniform.view_position = eye.to_homogeneous().into();
+        self.view_proj = OPENGL_TO_WGPU_MATRIX * proj * view;
+        let jitter = Matrix4::from_translation(self.jitter.extend(0.0));
+        let view_proj = jitter * self.view_proj;
+        self.uniform.view_proj = view_proj.into();
+        // used to reconstruct world positions from the depth buffer
+        self.uniform.inv_view_proj = view_proj.invert().unwrap_or(Matrix4::identity()).into();
+    }
+
+    // applied by the next `update`
+    pub fn set_jitter(&mut self, jitter: Vector2<f32>) {
+        self.jitter = jitter;
+    }
+
+    // without the jitter
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.view_proj
+    }
+
+    // as rendered, including the jitter
+    pub fn inverse_view_projection(&self) -> Matrix4<f32> {
+        self.uniform.inv_view_proj.into()
+    }
+
+    // called once per frame with the terrain walk mode and fly mode's ground clamp stand on
+    pub fn set_heightmap(&mut self, heightmap: Option<Rc<Heightmap>>) {
+        self.ground.heightmap = heightmap;
+    }
+
+    // called once per frame with the water, fly mode's ground clamp keeps above its waves too
+    pub fn set_water(&mut self, level: f32, waves: &[GerstnerWave]) {
+        self.ground.water_level = Some(level);
+        self.ground.waves.clear();
+        self.ground.waves.extend_from_slice(waves);
+    }
+
+    // walk mode's eye above the terrain, and fly mode's least clearance
+    pub fn set_eye_height(&mut self, height: f32) {
+        self.ground.eye_height = height;
+    }
+
+    pub fn toggle_fly_above_ground(&mut self) {
+        self.ground.fly_above_ground = !self.ground.fly_above_ground;
+        log::info!("Fly above ground: {}", self.ground.fly_above_ground);
+    }
+
+    // called once per frame with the lighting of the current time of day
+    pub fn set_lighting(&mut self, lighting: &Lighting) {
+        self.uniform.sun_direction = lighting.sun_direction.extend(0.0).into();
+        self.uniform.sun_color = lighting.sun_color.extend(1.0).into();
+        self.uniform.ambient_color = lighting.ambient_color.extend(1.0).into();
+    }
+
+    pub fn set_fog(&mut self, fog: &Fog) {
+        self.uniform.fog_color = match fog.color {
+            Some(color) => color.extend(0.0).into(),
+            None => [0.0, 0.0, 0.0, 1.0],
+        };
+        self.uniform.fog_params = [fog.density, fog.height_falloff, fog.base_height, 0.0];
+    }
+
+    // called after `update`, refreshes the image based lighting when the sky changed
+    pub fn update_environment(
+        &mut self,
+        environment: Option<&Rc<Environment>>,
+        lighting: &Lighting,
+        queue: &wgpu::Queue,
+    ) {
+        self.ibl.update(environment, lighting, queue);
     }
 
     // called when the surface is resized
@@ -148,54 +317,181 @@ impl Camera {
     }
 
     // called for input events
-    pub fn process_key(&mut self, state: &ElementState, key: &KeyCode) -> bool {
-        let pressed = if *state == ElementState::Pressed {
-            1.0
-        } else {
+    pub fn process_action(&mut self, input: &ActionEvent) -> bool {
+        self.controller.process_action(input)
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    // blends from the current pose into the new mode
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        self.mode = mode;
+        self.controller = mode.controller(&self.pose);
+        self.transition = Some((self.pose, 0.0));
+        log::info!("Camera mode: {:?}", mode);
+    }
+
+    // called before window event. This is device event
+    pub fn process_mouse(&mut self, delta: Vector2<f32>) {
+        self.look.add(delta, &self.look_settings);
+    }
+
+    pub fn set_look_settings(&mut self, settings: LookSettings) {
+        self.look_settings = settings;
+    }
+
+    // mouse motion with the right button held
+    pub fn process_pan(&mut self, delta: Vector2<f32>) {
+        self.controller.process_pan(delta);
+    }
+
+    pub fn process_scroll(&mut self, lines: f32) {
+        self.controller.process_scroll(lines);
+    }
+
+    pub fn process_dolly(&mut self, lines: f32) {
+        self.controller.process_dolly(lines);
+    }
+
+    // back to where the camera started, in the current mode
+    pub fn reset(&mut self) {
+        self.controller = self.mode.controller(&start_pose());
+        self.transition = Some((self.pose, 0.0));
+    }
+
+    pub fn process_analog(&mut self, input: &AnalogInput) {
+        self.controller.process_analog(input);
+    }
+
+    // adds the current view to the path, the first keyframe is at time 0
+    pub fn record_keyframe(&mut self) {
+        if self.path.is_empty() {
+            self.path_start = None;
+        }
+        let gap = if self.path.is_empty() {
             0.0
+        } else {
+            RESUME_SECONDS
         };
-        match key {
-            KeyCode::KeyW => {
-                self.movement.x = pressed;
-                true
-            }
-            KeyCode::KeyS => {
-                self.movement.y = pressed;
-                true
-            }
-            KeyCode::KeyA => {
-                self.movement.z = pressed;
-                true
-            }
-            KeyCode::KeyD => {
-                self.movement.w = pressed;
-                true
-            }
-            _ => false,
+        let start = *self
+            .path_start
+            .get_or_insert(self.uniform.time - self.path.duration() - gap);
+        let keyframe = Keyframe {
+            time: self.uniform.time - start,
+            ..self.keyframe()
+        };
+        if self.path.push(keyframe) {
+            log::info!("Camera path: {} keyframes", self.path.len());
+        } else {
+            log::warn!("Camera path: a keyframe was already recorded at this time");
         }
     }
 
-    // called before window event. This is device event
-    pub fn process_mouse(&mut self, delta: Vector2<f32>) {
-        self.rotation = delta;
+    // the current view at time 0
+    pub fn keyframe(&self) -> Keyframe {
+        Keyframe {
+            time: 0.0,
+            pose: self.pose,
+            fovy: self.fovy,
+        }
+    }
+
+    pub fn path(&self) -> &CameraPath {
+        &self.path
+    }
+
+    // replaces the recorded path, the next keyframe continues after its end
+    pub fn set_path(&mut self, path: CameraPath) {
+        self.path_start = None;
+        self.path = path;
+        self.playback = None;
+    }
+
+    pub fn clear_path(&mut self) {
+        self.path.clear();
+        self.stop_playback();
+        log::info!("Camera path cleared");
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    // plays the path from its start, false when there is nothing to play
+    pub fn play_path(&mut self) -> bool {
+        if self.path.len() < 2 {
+            log::warn!("Record at least two keyframes to play the camera path");
+            return false;
+        }
+        self.playback = Some(0.0);
+        self.transition = None;
+        true
+    }
+
+    pub fn toggle_playback(&mut self) {
+        if self.playback.is_some() {
+            self.stop_playback();
+        } else {
+            self.play_path();
+        }
+    }
+
+    // the controller takes over where playback left the camera
+    fn stop_playback(&mut self) {
+        if self.playback.take().is_some() {
+            self.fovy = ZOOM.into();
+            self.controller = self.mode.controller(&self.pose);
+        }
+    }
+
+    // multiplies the playback speed by `steps` powers of `PLAYBACK_SPEED_STEP`
+    pub fn change_playback_speed(&mut self, steps: i32) {
+        self.playback_speed = (self.playback_speed * PLAYBACK_SPEED_STEP.powi(steps))
+            .clamp(MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED);
+        log::info!("Playback speed: {:.2}x", self.playback_speed);
     }
 
     // update everything before rendering
     pub fn update(&mut self, dt: &Duration, queue: &wgpu::Queue) {
         let dt = dt.as_secs_f32();
-        self.yaw += Rad(self.rotation.x) * self.sensitivity * dt;
-        self.pitch -= Rad(self.rotation.y) * self.sensitivity * dt;
-        self.pitch = Rad(self.pitch.0.max(-FRAC_PI_2).min(FRAC_PI_2));
-        let velocity = self.speed * dt;
-        let (front, right, up) = self.front_right_up();
-        self.eye += front * velocity * self.movement.x
-            - front * velocity * self.movement.y
-            - right * velocity * self.movement.z
-            + right * velocity * self.movement.w;
-        self.rotation = Vector2::zero();
-        self.update_uniform(front, up);
+        let turn = self.look.take(dt, &self.look_settings);
+        if let Some(time) = &mut self.playback {
+            *time += dt * self.playback_speed;
+            let time = *time;
+            if let Some(keyframe) = self.path.sample(time) {
+                self.pose = keyframe.pose;
+                self.fovy = keyframe.fovy;
+            }
+            if time >= self.path.duration() {
+                self.stop_playback();
+            }
+        } else {
+            self.update_pose(dt, turn);
+        }
+        self.update_uniform();
         self.uniform.time += dt;
         // upload the uniform
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
     }
+
+    fn update_pose(&mut self, dt: f32, turn: Vector2<f32>) {
+        // the waves as drawn this frame, the uniform time advances after this
+        self.ground.time = self.uniform.time + dt;
+        self.controller.process_look(turn);
+        let target = self.controller.update(dt, &self.ground);
+        self.pose = match &mut self.transition {
+            Some((from, elapsed)) => {
+                *elapsed += dt;
+                let t = (*elapsed / TRANSITION_TIME).min(1.0);
+                let pose = from.lerp(&target, t * t * (3.0 - 2.0 * t));
+                if t >= 1.0 {
+                    self.transition = None;
+                }
+                pose
+            }
+            None => target,
+        };
+    }
 }
@@ -0,0 +1,52 @@
+// What the adapter and device can do. Passes turn on the features that need more than
+// WebGL2, like the compute shaders of the ocean and of automatic exposure, only when supported
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    pub backend: wgpu::Backend,
+    pub adapter: String,
+    // as granted to the device, not what the adapter could offer
+    pub limits: wgpu::Limits,
+}
+
+impl Capabilities {
+    pub fn new(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Self {
+        Self {
+            backend: adapter.get_info().backend,
+            adapter: adapter.get_info().name,
+            limits: device.limits(),
+        }
+    }
+
+    pub fn backend_name(&self) -> &'static str {
+        backend_name(self.backend)
+    }
+
+    // compute shaders binding this many storage buffers and storage textures in one stage
+    pub fn supports_compute(&self, storage_buffers: u32, storage_textures: u32) -> bool {
+        self.limits.max_compute_workgroups_per_dimension > 0
+            && self.limits.max_storage_buffers_per_shader_stage >= storage_buffers
+            && self.limits.max_storage_textures_per_shader_stage >= storage_textures
+    }
+}
+
+pub fn backend_name(backend: wgpu::Backend) -> &'static str {
+    match backend {
+        wgpu::Backend::BrowserWebGpu => "WebGPU",
+        wgpu::Backend::Gl => "WebGL2",
+        wgpu::Backend::Vulkan => "Vulkan",
+        wgpu::Backend::Metal => "Metal",
+        wgpu::Backend::Dx12 => "DirectX 12",
+        wgpu::Backend::Empty => "none",
+    }
+}
+
+// The largest limits the device is asked for. WebGPU gets everything the adapter offers,
+// WebGL2 its downlevel limits with textures as large as the adapter allows
+pub fn required_limits(adapter: &wgpu::Adapter) -> wgpu::Limits {
+    match adapter.get_info().backend {
+        wgpu::Backend::Gl => {
+            wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits())
+        }
+        _ => adapter.limits(),
+    }
+}
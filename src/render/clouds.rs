@@ -0,0 +1,529 @@
+use crate::input::{Action, ActionEvent};
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use wgpu::util::DeviceExt;
+
+use super::{Camera, Capabilities, RenderStage, RenderTargets, Renderable, targets};
+use crate::context::ContextState;
+use web_sys::js_sys::Math;
+
+// Tiling 3D noise, side length in texels
+const NOISE_SIZE: usize = 64;
+const NOISE_OCTAVES: usize = 3;
+// noise periods across the texture, fewer means larger cloud shapes
+const NOISE_FREQUENCY: f64 = 4.0;
+// Cloud layer in world units, above the highest terrain
+const CLOUD_BOTTOM: f32 = 150.0;
+const CLOUD_TOP: f32 = 260.0;
+// fraction of the sky covered
+const COVERAGE: f32 = 0.45;
+const COVERAGE_STEP: f32 = 0.05;
+// extinction per world unit inside a cloud
+const DENSITY: f32 = 0.04;
+// world units per noise texture repeat
+const NOISE_SCALE: f32 = 1200.0;
+// world units per second along x and z
+const WIND: [f32; 2] = [6.0, 2.0];
+const CLOUD_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CloudQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl CloudQuality {
+    fn steps(self) -> u32 {
+        match self {
+            CloudQuality::Low => 32,
+            CloudQuality::Medium => 64,
+            CloudQuality::High => 96,
+        }
+    }
+
+    fn light_steps(self) -> u32 {
+        match self {
+            CloudQuality::Low => 4,
+            CloudQuality::Medium => 6,
+            CloudQuality::High => 8,
+        }
+    }
+
+    // the clouds are marched at the screen size divided by this and upsampled
+    fn downsample(self) -> u32 {
+        match self {
+            CloudQuality::Low => 4,
+            CloudQuality::Medium => 2,
+            CloudQuality::High => 2,
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            CloudQuality::Low => CloudQuality::Medium,
+            CloudQuality::Medium => CloudQuality::High,
+            CloudQuality::High => CloudQuality::Low,
+        }
+    }
+}
+
+// Mirrors `Clouds` in clouds.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CloudUniform {
+    wind: [f32; 2],
+    bottom: f32,
+    top: f32,
+    coverage: f32,
+    density: f32,
+    noise_scale: f32,
+    steps: u32,
+    light_steps: u32,
+    _padding: [u32; 3],
+}
+
+impl CloudUniform {
+    fn new(quality: CloudQuality) -> Self {
+        Self {
+            wind: WIND,
+            bottom: CLOUD_BOTTOM,
+            top: CLOUD_TOP,
+            coverage: COVERAGE,
+            density: DENSITY,
+            noise_scale: NOISE_SCALE,
+            steps: quality.steps(),
+            light_steps: quality.light_steps(),
+            _padding: Default::default(),
+        }
+    }
+}
+
+// fBm noise in [0, 1] that repeats every NOISE_SIZE texels along each axis
+fn generate_noise(seed: u32) -> Vec<u8> {
+    let fbm: Fbm<Perlin> = Fbm::new(seed).set_octaves(NOISE_OCTAVES);
+    let sample = |x: f64, y: f64, z: f64| {
+        let scale = NOISE_FREQUENCY / NOISE_SIZE as f64;
+        fbm.get([x * scale, y * scale, z * scale])
+    };
+    let n = NOISE_SIZE as f64;
+    let mut texels = Vec::with_capacity(NOISE_SIZE * NOISE_SIZE * NOISE_SIZE);
+    for z in 0..NOISE_SIZE {
+        for y in 0..NOISE_SIZE {
+            for x in 0..NOISE_SIZE {
+                let (x, y, z) = (x as f64, y as f64, z as f64);
+                let (tx, ty, tz) = (x / n, y / n, z / n);
+                // blend with the copies one period back so opposite borders match
+                let mut value = 0.0;
+                for corner in 0..8 {
+                    let (bx, by, bz) = (corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+                    let weight = if bx == 1 { tx } else { 1.0 - tx }
+                        * if by == 1 { ty } else { 1.0 - ty }
+                        * if bz == 1 { tz } else { 1.0 - tz };
+                    value +=
+                        weight * sample(x - bx as f64 * n, y - by as f64 * n, z - bz as f64 * n);
+                }
+                let normalized = (value * 0.5 + 0.5).clamp(0.0, 1.0);
+                texels.push((normalized * 255.0) as u8);
+            }
+        }
+    }
+    texels
+}
+
+fn create_cloud_view(
+    device: &wgpu::Device,
+    (width, height): (u32, u32),
+    quality: CloudQuality,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Cloud Texture"),
+        size: wgpu::Extent3d {
+            width: (width / quality.downsample()).max(1),
+            height: (height / quality.downsample()).max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: CLOUD_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn uniform_entry() -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn sampler_entry() -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+fn texture_entry(
+    binding: u32,
+    view_dimension: wgpu::TextureViewDimension,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn composite_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    sampler: &wgpu::Sampler,
+    cloud_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Cloud Composite Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(cloud_view),
+            },
+        ],
+    })
+}
+
+pub struct CloudPass {
+    quality: CloudQuality,
+    uniform: CloudUniform,
+    uniform_changed: bool,
+    uniform_buffer: wgpu::Buffer,
+    noise_texture: wgpu::Texture,
+    // generated in `new`, uploaded on the first update
+    noise: Option<Vec<u8>>,
+    march_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    march_bind_group: wgpu::BindGroup,
+    composite_bind_group_layout: wgpu::BindGroupLayout,
+    composite_bind_group: wgpu::BindGroup,
+    composite_sampler: wgpu::Sampler,
+    // reduced resolution clouds, rgb scattered light and a transmittance
+    cloud_view: wgpu::TextureView,
+    // kept to recreate the cloud target when the quality changes
+    device: wgpu::Device,
+    screen_size: (u32, u32),
+}
+
+impl CloudPass {
+    fn recreate_cloud_target(&mut self) {
+        self.cloud_view = create_cloud_view(&self.device, self.screen_size, self.quality);
+        self.composite_bind_group = composite_bind_group(
+            &self.device,
+            &self.composite_bind_group_layout,
+            &self.uniform_buffer,
+            &self.composite_sampler,
+            &self.cloud_view,
+        );
+    }
+}
+
+impl Renderable for CloudPass {
+    fn render(&mut self, pass: &mut wgpu::RenderPass, camera: &Camera, targets: &RenderTargets) {
+        pass.set_pipeline(&self.composite_pipeline);
+        pass.set_bind_group(0, &camera.bind_group, &[]);
+        pass.set_bind_group(1, &targets.depth_bind_group, &[]);
+        pass.set_bind_group(2, &self.composite_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    // the reduced resolution target is read by uv, so it serves targets of any size
+    fn render_offscreen(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        targets: &RenderTargets,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Cloud March Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.cloud_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&self.march_pipeline);
+        pass.set_bind_group(0, &camera.bind_group, &[]);
+        pass.set_bind_group(1, &targets.depth_bind_group, &[]);
+        pass.set_bind_group(2, &self.march_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    fn input(&mut self, input: &ActionEvent, _context: &ContextState) -> bool {
+        if !input.pressed {
+            return false;
+        }
+        match input.action {
+            Action::CloudQuality => {
+                self.quality = self.quality.next();
+                self.uniform.steps = self.quality.steps();
+                self.uniform.light_steps = self.quality.light_steps();
+                self.recreate_cloud_target();
+                log::info!("Cloud quality: {:?}", self.quality);
+            }
+            Action::FewerClouds => {
+                self.uniform.coverage = (self.uniform.coverage - COVERAGE_STEP).max(0.0);
+            }
+            Action::MoreClouds => {
+                self.uniform.coverage = (self.uniform.coverage + COVERAGE_STEP).min(1.0);
+            }
+            _ => return false,
+        }
+        self.uniform_changed = true;
+        true
+    }
+
+    fn update(&mut self, _context: &mut ContextState, queue: &wgpu::Queue) {
+        if let Some(noise) = self.noise.take() {
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &self.noise_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &noise,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(NOISE_SIZE as u32),
+                    rows_per_image: Some(NOISE_SIZE as u32),
+                },
+                self.noise_texture.size(),
+            );
+        }
+        if self.uniform_changed {
+            queue.write_buffer(
+                &self.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[self.uniform]),
+            );
+            self.uniform_changed = false;
+        }
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        _config: &wgpu::SurfaceConfiguration,
+        camera: &Camera,
+        targets: &RenderTargets,
+        _capabilities: &Capabilities,
+    ) -> Self {
+        let quality = CloudQuality::Medium;
+        let uniform = CloudUniform::new(quality);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cloud Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let noise_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Cloud Noise Texture"),
+            size: wgpu::Extent3d {
+                width: NOISE_SIZE as u32,
+                height: NOISE_SIZE as u32,
+                depth_or_array_layers: NOISE_SIZE as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let noise_view = noise_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let noise_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Cloud Noise Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let composite_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Cloud Composite Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let march_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Cloud March Bind Group Layout"),
+                entries: &[
+                    uniform_entry(),
+                    sampler_entry(),
+                    texture_entry(2, wgpu::TextureViewDimension::D3),
+                ],
+            });
+        let composite_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Cloud Composite Bind Group Layout"),
+                entries: &[
+                    uniform_entry(),
+                    sampler_entry(),
+                    texture_entry(3, wgpu::TextureViewDimension::D2),
+                ],
+            });
+        let march_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cloud March Bind Group"),
+            layout: &march_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&noise_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&noise_view),
+                },
+            ],
+        });
+        let depth_bind_group_layout = RenderTargets::depth_bind_group_layout(device);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("clouds.wgsl"));
+        let create_pipeline = |label: &str,
+                               fragment_entry: &str,
+                               layout: &wgpu::BindGroupLayout,
+                               blend: Option<wgpu::BlendState>| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[&camera.bind_group_layout, &depth_bind_group_layout, layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(fragment_entry),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: match blend {
+                            Some(_) => targets::HDR_FORMAT,
+                            None => CLOUD_FORMAT,
+                        },
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let march_pipeline = create_pipeline(
+            "Cloud March Pipeline",
+            "fs_march",
+            &march_bind_group_layout,
+            None,
+        );
+        // colour + destination * transmittance
+        let composite_blend = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::SrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+        let composite_pipeline = create_pipeline(
+            "Cloud Composite Pipeline",
+            "fs_composite",
+            &composite_bind_group_layout,
+            Some(composite_blend),
+        );
+
+        let screen_size = (targets.width, targets.height);
+        let cloud_view = create_cloud_view(device, screen_size, quality);
+        let composite_bind_group = composite_bind_group(
+            device,
+            &composite_bind_group_layout,
+            &uniform_buffer,
+            &composite_sampler,
+            &cloud_view,
+        );
+
+        Self {
+            quality,
+            uniform,
+            uniform_changed: false,
+            uniform_buffer,
+            noise_texture,
+            noise: Some(generate_noise((Math::random() * u32::MAX as f64) as u32)),
+            march_pipeline,
+            composite_pipeline,
+            march_bind_group,
+            composite_bind_group_layout,
+            composite_bind_group,
+            composite_sampler,
+            cloud_view,
+            device: device.clone(),
+            screen_size,
+        }
+    }
+
+    fn resize(&mut self, _device: &wgpu::Device, targets: &RenderTargets) {
+        self.screen_size = (targets.width, targets.height);
+        self.recreate_cloud_target();
+    }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::Transparent
+    }
+}
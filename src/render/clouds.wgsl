@@ -0,0 +1,194 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    time: f32,
+    inv_view_proj: mat4x4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var scene_depth: texture_2d<f32>;
+
+// Mirrors `CloudUniform` in clouds.rs
+struct Clouds {
+    wind: vec2<f32>,
+    bottom: f32,
+    top: f32,
+    coverage: f32,
+    density: f32,
+    // world units per noise texture repeat
+    noise_scale: f32,
+    steps: u32,
+    light_steps: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+@group(2) @binding(0)
+var<uniform> clouds: Clouds;
+@group(2) @binding(1)
+var cloud_sampler: sampler;
+// tiling fBm noise, only bound while marching
+@group(2) @binding(2)
+var noise_texture: texture_3d<f32>;
+// the marched clouds, only bound while compositing
+@group(2) @binding(3)
+var cloud_texture: texture_2d<f32>;
+
+const PI: f32 = 3.14159265;
+// rays are cut off here, the layer is never seen further away
+const MAX_DISTANCE: f32 = 6000.0;
+// Henyey-Greenstein asymmetry, clouds scatter strongly forwards
+const FORWARD_SCATTERING: f32 = 0.6;
+const BACK_SCATTERING: f32 = -0.2;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // [0, 1] across the screen, y pointing down
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    out.position = vec4<f32>(pos, 0.0, 1.0);
+    out.uv = vec2<f32>(pos.x * 0.5 + 0.5, 0.5 - pos.y * 0.5);
+    return out;
+}
+
+fn world_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inv_view_proj * ndc;
+    return world.xyz / world.w;
+}
+
+fn view_ray(uv: vec2<f32>) -> vec3<f32> {
+    return normalize(world_position(uv, 1.0) - camera.view_pos.xyz);
+}
+
+// distance to the opaque scene along the ray through `uv`, the sky is infinitely far
+fn scene_distance(uv: vec2<f32>) -> f32 {
+    let size = vec2<f32>(textureDimensions(scene_depth));
+    let texel = vec2<i32>(min(uv * size, size - 1.0));
+    let depth = textureLoad(scene_depth, texel, 0).r;
+    if depth >= 1.0 {
+        return MAX_DISTANCE;
+    }
+    return distance(world_position(uv, depth), camera.view_pos.xyz);
+}
+
+// entry and exit distance of the ray through the cloud layer, empty when x > y
+fn layer_interval(origin: vec3<f32>, dir: vec3<f32>) -> vec2<f32> {
+    if abs(dir.y) < 0.0001 {
+        if origin.y > clouds.bottom && origin.y < clouds.top {
+            return vec2<f32>(0.0, MAX_DISTANCE);
+        }
+        return vec2<f32>(1.0, 0.0);
+    }
+    let t_bottom = (clouds.bottom - origin.y) / dir.y;
+    let t_top = (clouds.top - origin.y) / dir.y;
+    let t0 = max(min(t_bottom, t_top), 0.0);
+    let t1 = min(max(t_bottom, t_top), MAX_DISTANCE);
+    return vec2<f32>(t0, t1);
+}
+
+fn density(p: vec3<f32>) -> f32 {
+    let height = (p.y - clouds.bottom) / (clouds.top - clouds.bottom);
+    // rounded bottoms and wispy tops
+    let profile = smoothstep(0.0, 0.15, height) * (1.0 - smoothstep(0.6, 1.0, height));
+    let drift = vec3<f32>(clouds.wind.x, 0.0, clouds.wind.y) * camera.time;
+    let base = textureSampleLevel(noise_texture, cloud_sampler, (p + drift) / clouds.noise_scale, 0.0).r;
+    let detail = textureSampleLevel(noise_texture, cloud_sampler, (p + drift * 1.5) / clouds.noise_scale * 4.1, 0.0).r;
+    // erode the edges with the finer noise
+    let shape = base - detail * 0.25 * (1.0 - base);
+    let covered = saturate((shape - (1.0 - clouds.coverage)) / max(clouds.coverage, 0.001));
+    return covered * profile * clouds.density;
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let g2 = g * g;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * cos_theta, 1.5));
+}
+
+// optical depth towards the sun
+fn light_depth(p: vec3<f32>, sun: vec3<f32>) -> f32 {
+    let interval = layer_interval(p, sun);
+    let step = min(interval.y, (clouds.top - clouds.bottom) * 1.5) / f32(clouds.light_steps);
+    var depth = 0.0;
+    for (var i = 0u; i < clouds.light_steps; i++) {
+        depth += density(p + sun * step * (f32(i) + 0.5)) * step;
+    }
+    return depth;
+}
+
+// per pixel jitter so the reduced step count bands less
+fn interleaved_gradient_noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+// Raymarched at reduced resolution, rgb is the scattered light and a the transmittance
+@fragment
+fn fs_march(in: VertexOutput) -> @location(0) vec4<f32> {
+    let origin = camera.view_pos.xyz;
+    let dir = view_ray(in.uv);
+    var interval = layer_interval(origin, dir);
+    interval.y = min(interval.y, scene_distance(in.uv));
+    if interval.x >= interval.y {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let sun = normalize(camera.sun_direction.xyz);
+    let cos_theta = dot(dir, sun);
+    let phase = mix(
+        henyey_greenstein(cos_theta, BACK_SCATTERING),
+        henyey_greenstein(cos_theta, FORWARD_SCATTERING),
+        0.7,
+    );
+
+    let step = (interval.y - interval.x) / f32(clouds.steps);
+    var t = interval.x + step * interleaved_gradient_noise(in.position.xy);
+    var transmittance = 1.0;
+    var light = vec3<f32>(0.0);
+    for (var i = 0u; i < clouds.steps; i++) {
+        let p = origin + dir * t;
+        let d = density(p);
+        if d > 0.001 {
+            let to_sun = light_depth(p, sun);
+            // Beer's law with a powder term darkening the sunlit edges of thick clouds
+            let beer = exp(-to_sun);
+            let powder = 1.0 - exp(-to_sun * 2.0);
+            let direct = camera.sun_color.rgb * beer * mix(1.0, powder, 0.5) * phase * 4.0 * PI;
+            let height = (p.y - clouds.bottom) / (clouds.top - clouds.bottom);
+            let ambient = camera.ambient_color.rgb * mix(0.5, 1.0, height);
+            let sample_transmittance = exp(-d * step);
+            // energy conserving integration over the step, Hillaire 2015
+            let scattering = (direct + ambient) * (1.0 - sample_transmittance);
+            light += transmittance * scattering;
+            transmittance *= sample_transmittance;
+            if transmittance < 0.01 {
+                break;
+            }
+        }
+        t += step;
+    }
+
+    // fade into the haze towards the horizon
+    let fade = 1.0 - smoothstep(MAX_DISTANCE * 0.5, MAX_DISTANCE, interval.x);
+    return vec4<f32>(light * fade, mix(1.0, transmittance, fade));
+}
+
+// Full resolution, blended over the scene as colour + destination * transmittance
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the upsampled clouds bleed over terrain edges, hide them where the terrain is in front
+    let interval = layer_interval(camera.view_pos.xyz, view_ray(in.uv));
+    if interval.x >= interval.y || scene_distance(in.uv) < interval.x {
+        discard;
+    }
+    return textureSampleLevel(cloud_texture, cloud_sampler, in.uv, 0.0);
+}
@@ -0,0 +1,812 @@
+use cgmath::*;
+use std::f32::consts::{FRAC_PI_2, PI};
+use std::rc::Rc;
+
+use super::heightmap::Heightmap;
+use super::waves::{self, GerstnerWave};
+use crate::input::{Action, ActionEvent};
+
+const WORLD_UP: Vector3<f32> = Vector3::new(0.0, 1.0, 0.0);
+
+// fly mode, units per second
+const SPEED: f32 = 32.0;
+const MIN_SPEED: f32 = 0.5;
+const MAX_SPEED: f32 = 4096.0;
+// the speed changes by this factor per wheel line
+const SPEED_STEP: f32 = 1.25;
+// multiplier while boosting
+const BOOST: f32 = 4.0;
+// rates of the exponential approach to the wanted velocity, per second
+const ACCELERATION: f32 = 8.0;
+const DAMPING: f32 = 5.0;
+// seconds of travel at the current speed per dolly line, and per pixel panned
+const DOLLY_SECONDS: f32 = 0.5;
+const PAN_SECONDS: f32 = 0.005;
+// walk mode
+const EYE_HEIGHT: f32 = 1.7;
+const WALK_SPEED: f32 = 6.0;
+const GRAVITY: f32 = 20.0;
+const JUMP_SPEED: f32 = 8.0;
+// steepest rise per unit walked uphill, about 40 degrees
+const MAX_SLOPE: f32 = 0.84;
+// orbit mode, the target starts this far in front of the camera
+const ORBIT_DISTANCE: f32 = 60.0;
+const MIN_ORBIT_DISTANCE: f32 = 2.0;
+const MAX_ORBIT_DISTANCE: f32 = 2000.0;
+// top-down mode, height above the point under the camera
+const MAP_HEIGHT: f32 = 300.0;
+const MIN_MAP_HEIGHT: f32 = 20.0;
+const MAX_MAP_HEIGHT: f32 = 4000.0;
+// fraction of the view height covered per second by the keys
+const MAP_PAN_SPEED: f32 = 0.5;
+// the zoom changes by this factor per wheel line
+const ZOOM_STEP: f32 = 0.9;
+// wheel lines per second of a fully held analog zoom
+const ANALOG_ZOOM_RATE: f32 = 4.0;
+// panning moves the target this fraction of its distance per pixel
+const PAN_SENSITIVITY: f32 = 0.002;
+// straight down is singular with a fixed up vector
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.001;
+
+// The terrain the controllers walk on or stay above, kept by `Camera` across mode switches
+pub struct Ground {
+    pub heightmap: Option<Rc<Heightmap>>,
+    // eye above the terrain in walk mode, and the least clearance in fly mode
+    pub eye_height: f32,
+    // fly mode stops at the terrain instead of passing through it
+    pub fly_above_ground: bool,
+    // still level and waves of the water, fly mode stops at its surface too
+    pub water_level: Option<f32>,
+    pub waves: Vec<GerstnerWave>,
+    // seconds of the waves' animation
+    pub time: f32,
+}
+
+impl Default for Ground {
+    fn default() -> Self {
+        Self {
+            heightmap: None,
+            eye_height: EYE_HEIGHT,
+            fly_above_ground: false,
+            water_level: None,
+            waves: Vec::new(),
+            time: 0.0,
+        }
+    }
+}
+
+impl Ground {
+    // None off the terrain or before it exists
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        self.heightmap.as_ref()?.height_at(x, z)
+    }
+
+    // the terrain, or the water where it covers the terrain
+    pub fn surface_at(&self, x: f32, z: f32) -> Option<f32> {
+        let terrain = self.height_at(x, z)?;
+        Some(match self.water_level {
+            Some(level) => terrain.max(level + waves::wave_height(&self.waves, x, z, self.time)),
+            None => terrain,
+        })
+    }
+}
+
+// unit length at most, so keys move at full speed and sticks in proportion
+fn limit_length(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.magnitude2() > 1.0 {
+        direction.normalize()
+    } else {
+        direction
+    }
+}
+
+// How mouse motion turns the view
+#[derive(Clone, Copy, Debug)]
+pub struct LookSettings {
+    // radians per pixel
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    // seconds for the view to catch up with about two thirds of the motion, 0 turns at once
+    pub smoothing: f32,
+}
+
+impl Default for LookSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.003,
+            invert_y: false,
+            smoothing: 0.0,
+        }
+    }
+}
+
+// Mouse motion gathered between frames, handed out as turns that do not depend on the frame rate
+pub struct MouseLook {
+    // radians not turned yet, x yaw to the right and y pitch up
+    pending: Vector2<f32>,
+}
+
+impl MouseLook {
+    pub fn new() -> Self {
+        Self {
+            pending: Vector2::zero(),
+        }
+    }
+
+    // every event counts, however many arrive in a frame
+    pub fn add(&mut self, delta: Vector2<f32>, settings: &LookSettings) {
+        let y = if settings.invert_y { delta.y } else { -delta.y };
+        self.pending += Vector2::new(delta.x, y) * settings.sensitivity;
+    }
+
+    // this frame's share of the motion, all of it without smoothing.
+    // Smoothing only delays the turn, the total stays what the mouse moved
+    pub fn take(&mut self, dt: f32, settings: &LookSettings) -> Vector2<f32> {
+        let share = if settings.smoothing > 0.0 {
+            1.0 - (-dt / settings.smoothing).exp()
+        } else {
+            1.0
+        };
+        let turn = self.pending * share;
+        self.pending -= turn;
+        turn
+    }
+}
+
+// Analog movement and look, from a gamepad
+#[derive(Clone, Copy, Debug)]
+pub struct AnalogInput {
+    // x right, y up, z forward, each in -1..1
+    pub movement: Vector3<f32>,
+    // radians per second, x right, y up
+    pub look: Vector2<f32>,
+}
+
+impl Default for AnalogInput {
+    fn default() -> Self {
+        Self {
+            movement: Vector3::zero(),
+            look: Vector2::zero(),
+        }
+    }
+}
+
+// Which movement actions are held
+struct MoveInput {
+    held: [bool; Action::COUNT],
+    analog: AnalogInput,
+}
+
+impl Default for MoveInput {
+    fn default() -> Self {
+        Self {
+            held: [false; Action::COUNT],
+            analog: AnalogInput::default(),
+        }
+    }
+}
+
+impl MoveInput {
+    fn process_action(&mut self, input: &ActionEvent) -> bool {
+        match input.action {
+            Action::MoveForward
+            | Action::MoveBackward
+            | Action::MoveLeft
+            | Action::MoveRight
+            | Action::MoveUp
+            | Action::MoveDown
+            | Action::Boost => {
+                self.held[input.action as usize] = input.pressed;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn held(&self, action: Action) -> bool {
+        self.held[action as usize]
+    }
+
+    // how far the action is pushed, 0 to 1, keys count fully
+    fn amount(&self, action: Action) -> f32 {
+        let movement = self.analog.movement;
+        let analog = match action {
+            Action::MoveForward => movement.z,
+            Action::MoveBackward => -movement.z,
+            Action::MoveRight => movement.x,
+            Action::MoveLeft => -movement.x,
+            Action::MoveUp => movement.y,
+            Action::MoveDown => -movement.y,
+            _ => 0.0,
+        };
+        if self.held(action) {
+            1.0
+        } else {
+            analog.max(0.0)
+        }
+    }
+
+    // -1 to 1
+    fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.amount(positive) - self.amount(negative)
+    }
+
+    fn boost(&self) -> f32 {
+        if self.held(Action::Boost) { BOOST } else { 1.0 }
+    }
+}
+
+// Where the camera is and where it looks
+#[derive(Clone, Copy, Debug)]
+pub struct CameraPose {
+    pub eye: Point3<f32>,
+    // 0 --> positive x-axis
+    pub yaw: Rad<f32>,
+    // 0 --> horizontal, positive up
+    pub pitch: Rad<f32>,
+}
+
+impl CameraPose {
+    pub fn front(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.yaw.cos() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.sin() * self.pitch.cos(),
+        )
+        .normalize()
+    }
+
+    pub fn front_right_up(&self) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let front = self.front();
+        let right = front.cross(WORLD_UP).normalize();
+        let up = right.cross(front).normalize();
+        (front, right, up)
+    }
+
+    // yaw takes the shorter way around
+    pub fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        let turn = (other.yaw.0 - self.yaw.0 + PI).rem_euclid(2.0 * PI) - PI;
+        CameraPose {
+            eye: self.eye + (other.eye - self.eye) * t,
+            yaw: Rad(self.yaw.0 + turn * t),
+            pitch: Rad(self.pitch.0 + (other.pitch.0 - self.pitch.0) * t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraMode {
+    Fly,
+    Walk,
+    Orbit,
+    TopDown,
+}
+
+impl CameraMode {
+    pub fn next(self) -> Self {
+        match self {
+            CameraMode::Fly => CameraMode::Walk,
+            CameraMode::Walk => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::TopDown,
+            CameraMode::TopDown => CameraMode::Fly,
+        }
+    }
+
+    // a controller of this mode that starts out looking at what `pose` looks at
+    pub fn controller(self, pose: &CameraPose) -> Box<dyn CameraController> {
+        match self {
+            CameraMode::Fly => Box::new(FlyController::new(pose)),
+            CameraMode::Walk => Box::new(WalkController::new(pose)),
+            CameraMode::Orbit => Box::new(OrbitController::new(pose)),
+            CameraMode::TopDown => Box::new(TopDownController::new(pose)),
+        }
+    }
+}
+
+// Turns input into a camera pose, one implementation per `CameraMode`
+pub trait CameraController {
+    fn process_action(&mut self, input: &ActionEvent) -> bool;
+
+    // mouse look in radians, x yaw to the right and y pitch up
+    fn process_look(&mut self, _turn: Vector2<f32>) {}
+
+    // mouse motion while the right button is held, in pixels
+    fn process_pan(&mut self, _delta: Vector2<f32>) {}
+
+    // wheel lines, positive away from the user
+    fn process_scroll(&mut self, _lines: f32) {}
+
+    // moves along the view, or zooms, without changing the speed, in wheel lines
+    fn process_dolly(&mut self, _lines: f32) {}
+
+    // the latest gamepad state, replaced every frame
+    fn process_analog(&mut self, _input: &AnalogInput) {}
+
+    fn update(&mut self, dt: f32, ground: &Ground) -> CameraPose;
+}
+
+// First person flight along the view direction, the wheel scales the speed
+pub struct FlyController {
+    pose: CameraPose,
+    speed: f32,
+    input: MoveInput,
+    velocity: Vector3<f32>,
+    // radians from mouse look since the last update
+    turn: Vector2<f32>,
+    // world distance from gestures and right drags, moved in the next update so the
+    // ground clamp covers it
+    nudge: Vector3<f32>,
+}
+
+impl FlyController {
+    pub fn new(pose: &CameraPose) -> Self {
+        Self {
+            pose: *pose,
+            speed: SPEED,
+            input: MoveInput::default(),
+            velocity: Vector3::zero(),
+            turn: Vector2::zero(),
+            nudge: Vector3::zero(),
+        }
+    }
+}
+
+impl CameraController for FlyController {
+    fn process_action(&mut self, input: &ActionEvent) -> bool {
+        self.input.process_action(input)
+    }
+
+    fn process_look(&mut self, turn: Vector2<f32>) {
+        self.turn += turn;
+    }
+
+    fn process_analog(&mut self, input: &AnalogInput) {
+        self.input.analog = *input;
+    }
+
+    fn process_pan(&mut self, delta: Vector2<f32>) {
+        let (_, right, up) = self.pose.front_right_up();
+        self.nudge += (-right * delta.x + up * delta.y) * self.speed * PAN_SECONDS;
+    }
+
+    fn process_scroll(&mut self, lines: f32) {
+        self.speed = (self.speed * SPEED_STEP.powf(lines)).clamp(MIN_SPEED, MAX_SPEED);
+        log::info!("Camera speed: {:.1}", self.speed);
+    }
+
+    fn process_dolly(&mut self, lines: f32) {
+        self.nudge += self.pose.front() * lines * self.speed * DOLLY_SECONDS;
+    }
+
+    fn update(&mut self, dt: f32, ground: &Ground) -> CameraPose {
+        let pose = &mut self.pose;
+        let look = self.input.analog.look;
+        pose.yaw += Rad(self.turn.x + look.x * dt);
+        pose.pitch = Rad((pose.pitch.0 + self.turn.y + look.y * dt).clamp(-FRAC_PI_2, FRAC_PI_2));
+        self.turn = Vector2::zero();
+
+        // forward follows the view, up is always the world's
+        let (front, right, _) = pose.front_right_up();
+        let input = &self.input;
+        let direction = front * input.axis(Action::MoveForward, Action::MoveBackward)
+            + right * input.axis(Action::MoveRight, Action::MoveLeft)
+            + WORLD_UP * input.axis(Action::MoveUp, Action::MoveDown);
+        let (wanted, rate) = if direction.is_zero() {
+            (Vector3::zero(), DAMPING)
+        } else {
+            (
+                limit_length(direction) * self.speed * input.boost(),
+                ACCELERATION,
+            )
+        };
+        self.velocity += (wanted - self.velocity) * (1.0 - (-rate * dt).exp());
+        pose.eye += self.velocity * dt + self.nudge;
+        self.nudge = Vector3::zero();
+        let floor = ground
+            .surface_at(pose.eye.x, pose.eye.z)
+            .filter(|_| ground.fly_above_ground)
+            .map(|height| height + ground.eye_height);
+        if let Some(floor) = floor
+            && pose.eye.y < floor
+        {
+            pose.eye.y = floor;
+            self.velocity.y = self.velocity.y.max(0.0);
+        }
+        *pose
+    }
+}
+
+// On foot, held at eye height above the terrain with gravity, jumps and a slope limit
+pub struct WalkController {
+    pose: CameraPose,
+    input: MoveInput,
+    // upwards speed, only gravity and jumps change it
+    vertical_speed: f32,
+    grounded: bool,
+    // radians from mouse look since the last update
+    turn: Vector2<f32>,
+    // forward and right distance from gestures, walked in the next update
+    nudge: Vector2<f32>,
+}
+
+impl WalkController {
+    pub fn new(pose: &CameraPose) -> Self {
+        Self {
+            pose: *pose,
+            input: MoveInput::default(),
+            vertical_speed: 0.0,
+            grounded: false,
+            turn: Vector2::zero(),
+            nudge: Vector2::zero(),
+        }
+    }
+}
+
+impl CameraController for WalkController {
+    fn process_action(&mut self, input: &ActionEvent) -> bool {
+        self.input.process_action(input)
+    }
+
+    fn process_look(&mut self, turn: Vector2<f32>) {
+        self.turn += turn;
+    }
+
+    fn process_analog(&mut self, input: &AnalogInput) {
+        self.input.analog = *input;
+    }
+
+    fn process_pan(&mut self, delta: Vector2<f32>) {
+        self.nudge.y -= delta.x * WALK_SPEED * PAN_SECONDS;
+    }
+
+    fn process_dolly(&mut self, lines: f32) {
+        self.nudge.x += lines * WALK_SPEED * DOLLY_SECONDS;
+    }
+
+    fn update(&mut self, dt: f32, ground: &Ground) -> CameraPose {
+        let pose = &mut self.pose;
+        let look = self.input.analog.look;
+        pose.yaw += Rad(self.turn.x + look.x * dt);
+        pose.pitch = Rad((pose.pitch.0 + self.turn.y + look.y * dt).clamp(-FRAC_PI_2, FRAC_PI_2));
+        self.turn = Vector2::zero();
+
+        // without terrain there is nothing to stand on
+        let Some(heightmap) = &ground.heightmap else {
+            return *pose;
+        };
+
+        // walking stays horizontal whatever the pitch
+        let forward = Vector3::new(pose.yaw.cos(), 0.0, pose.yaw.sin());
+        let right = Vector3::new(-pose.yaw.sin(), 0.0, pose.yaw.cos());
+        let input = &self.input;
+        let direction = forward * input.axis(Action::MoveForward, Action::MoveBackward)
+            + right * input.axis(Action::MoveRight, Action::MoveLeft);
+        let step = limit_length(direction) * WALK_SPEED * input.boost() * dt
+            + forward * self.nudge.x
+            + right * self.nudge.y;
+        self.nudge = Vector2::zero();
+        if !step.is_zero() {
+            let (x, z) = (pose.eye.x + step.x, pose.eye.z + step.z);
+            // off the map and up too steep slopes are blocked, downhill is always fine
+            if let Some([dx, dz]) = heightmap.gradient_at(x, z) {
+                let rise = (dx * step.x + dz * step.z) / step.magnitude();
+                if !self.grounded || rise <= MAX_SLOPE {
+                    pose.eye.x = x;
+                    pose.eye.z = z;
+                }
+            }
+        }
+
+        if self.grounded && input.amount(Action::MoveUp) > 0.5 {
+            self.vertical_speed = JUMP_SPEED;
+        }
+        self.vertical_speed -= GRAVITY * dt;
+        pose.eye.y += self.vertical_speed * dt;
+        let standing = heightmap
+            .height_at(pose.eye.x, pose.eye.z)
+            .map(|height| height + ground.eye_height);
+        self.grounded = match standing {
+            Some(standing) if pose.eye.y <= standing => {
+                pose.eye.y = standing;
+                self.vertical_speed = 0.0;
+                true
+            }
+            _ => false,
+        };
+        *pose
+    }
+}
+
+// Rotates around a target point, the wheel zooms and a right drag pans
+pub struct OrbitController {
+    target: Point3<f32>,
+    distance: f32,
+    yaw: Rad<f32>,
+    pitch: Rad<f32>,
+    analog: AnalogInput,
+}
+
+impl OrbitController {
+    pub fn new(pose: &CameraPose) -> Self {
+        Self {
+            target: pose.eye + pose.front() * ORBIT_DISTANCE,
+            distance: ORBIT_DISTANCE,
+            yaw: pose.yaw,
+            pitch: pose.pitch,
+            analog: AnalogInput::default(),
+        }
+    }
+
+    fn rotate(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += Rad(yaw);
+        self.pitch = Rad((self.pitch.0 + pitch).clamp(-MAX_PITCH, MAX_PITCH));
+    }
+
+    fn pose(&self) -> CameraPose {
+        let mut pose = CameraPose {
+            eye: self.target,
+            yaw: self.yaw,
+            pitch: self.pitch,
+        };
+        pose.eye = self.target - pose.front() * self.distance;
+        pose
+    }
+}
+
+impl CameraController for OrbitController {
+    fn process_action(&mut self, _input: &ActionEvent) -> bool {
+        false
+    }
+
+    fn process_look(&mut self, turn: Vector2<f32>) {
+        self.rotate(turn.x, turn.y);
+    }
+
+    fn process_pan(&mut self, delta: Vector2<f32>) {
+        let (_, right, up) = self.pose().front_right_up();
+        let scale = self.distance * PAN_SENSITIVITY;
+        self.target += (-right * delta.x + up * delta.y) * scale;
+    }
+
+    fn process_scroll(&mut self, lines: f32) {
+        self.distance =
+            (self.distance * ZOOM_STEP.powf(lines)).clamp(MIN_ORBIT_DISTANCE, MAX_ORBIT_DISTANCE);
+    }
+
+    fn process_dolly(&mut self, lines: f32) {
+        self.process_scroll(lines);
+    }
+
+    fn process_analog(&mut self, input: &AnalogInput) {
+        self.analog = *input;
+    }
+
+    // the look stick orbits and vertical movement zooms
+    fn update(&mut self, dt: f32, _ground: &Ground) -> CameraPose {
+        let analog = self.analog;
+        self.rotate(analog.look.x * dt, analog.look.y * dt);
+        self.process_scroll(analog.movement.y * ANALOG_ZOOM_RATE * dt);
+        self.pose()
+    }
+}
+
+// Looks straight down, north up, WASD or a right drag pans and the wheel zooms
+pub struct TopDownController {
+    // the point under the view centre
+    center: Point3<f32>,
+    height: f32,
+    input: MoveInput,
+}
+
+impl TopDownController {
+    pub fn new(pose: &CameraPose) -> Self {
+        // centre on the point the old view was aimed at, roughly
+        let front = pose.front();
+        let reach = Vector3::new(front.x, 0.0, front.z) * ORBIT_DISTANCE;
+        Self {
+            center: Point3::new(pose.eye.x, 0.0, pose.eye.z) + reach,
+            height: MAP_HEIGHT,
+            input: MoveInput::default(),
+        }
+    }
+}
+
+impl CameraController for TopDownController {
+    fn process_action(&mut self, input: &ActionEvent) -> bool {
+        self.input.process_action(input)
+    }
+
+    fn process_analog(&mut self, input: &AnalogInput) {
+        self.input.analog = *input;
+    }
+
+    fn process_pan(&mut self, delta: Vector2<f32>) {
+        // screen right is +x and screen up is -z with the yaw below
+        let scale = self.height * PAN_SENSITIVITY;
+        self.center += Vector3::new(-delta.x, 0.0, -delta.y) * scale;
+    }
+
+    fn process_scroll(&mut self, lines: f32) {
+        self.height = (self.height * ZOOM_STEP.powf(lines)).clamp(MIN_MAP_HEIGHT, MAX_MAP_HEIGHT);
+    }
+
+    fn process_dolly(&mut self, lines: f32) {
+        self.process_scroll(lines);
+    }
+
+    fn update(&mut self, dt: f32, _ground: &Ground) -> CameraPose {
+        // up and down zoom, at the same relative rate as the panning
+        let input = &self.input;
+        let step = self.height * MAP_PAN_SPEED * input.boost() * dt;
+        self.center += Vector3::new(
+            input.axis(Action::MoveRight, Action::MoveLeft),
+            0.0,
+            input.axis(Action::MoveBackward, Action::MoveForward),
+        ) * step;
+        self.height = (self.height + input.axis(Action::MoveUp, Action::MoveDown) * step)
+            .clamp(MIN_MAP_HEIGHT, MAX_MAP_HEIGHT);
+        CameraPose {
+            eye: self.center + Vector3::new(0.0, self.height, 0.0),
+            yaw: Rad(-FRAC_PI_2),
+            pitch: Rad(-MAX_PITCH),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{actual} is not {expected}"
+        );
+    }
+
+    fn settings(smoothing: f32) -> LookSettings {
+        LookSettings {
+            smoothing,
+            ..LookSettings::default()
+        }
+    }
+
+    // the total turn of `frames` frames of `dt`, with all the motion added before the first
+    fn turn_over(
+        frames: usize,
+        dt: f32,
+        motion: &[Vector2<f32>],
+        settings: &LookSettings,
+    ) -> Vector2<f32> {
+        let mut look = MouseLook::new();
+        for &delta in motion {
+            look.add(delta, settings);
+        }
+        (0..frames).map(|_| look.take(dt, settings)).sum()
+    }
+
+    fn fly() -> FlyController {
+        FlyController::new(&CameraPose {
+            eye: Point3::new(0.0, 0.0, 0.0),
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+        })
+    }
+
+    // a gentle slope up along x, 33 samples one unit apart around the origin
+    fn slope(eye_height: f32) -> Ground {
+        let heights = (0..33 * 33)
+            .map(|k| 2.0 + 0.1 * ((k / 33) as f32 - 16.0))
+            .collect();
+        Ground {
+            heightmap: Some(Rc::new(Heightmap::new(33, 1.0, [-16.0, -16.0], heights))),
+            eye_height,
+            ..Ground::default()
+        }
+    }
+
+    #[test]
+    fn walk_holds_the_eye_height() {
+        let ground = slope(4.5);
+        let mut controller = WalkController::new(&CameraPose {
+            eye: Point3::new(0.0, 20.0, 0.0),
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+        });
+        // falls onto the terrain and stays there
+        let mut pose = controller.update(1.0 / 60.0, &ground);
+        for _ in 0..240 {
+            pose = controller.update(1.0 / 60.0, &ground);
+        }
+        assert_close(pose.eye.y, 2.0 + 4.5);
+        // and follows it uphill, yaw 0 walks along x
+        controller.process_dolly(2.0);
+        let pose = controller.update(1.0 / 60.0, &ground);
+        assert!(pose.eye.x > 5.0);
+        assert_close(pose.eye.y, 2.0 + 0.1 * pose.eye.x + 4.5);
+    }
+
+    #[test]
+    fn events_in_one_frame_accumulate() {
+        let settings = settings(0.0);
+        let motion = [
+            Vector2::new(3.0, 1.0),
+            Vector2::new(5.0, -4.0),
+            Vector2::new(-1.0, 2.0),
+        ];
+        let turn = turn_over(1, 1.0 / 60.0, &motion, &settings);
+        assert_close(turn.x, 7.0 * settings.sensitivity);
+        assert_close(turn.y, 1.0 * settings.sensitivity);
+    }
+
+    #[test]
+    fn turn_does_not_depend_on_dt() {
+        let settings = settings(0.0);
+        let motion = [Vector2::new(40.0, -25.0)];
+        for dt in [1.0 / 30.0, 1.0 / 60.0, 1.0 / 240.0] {
+            let turn = turn_over(1, dt, &motion, &settings);
+            assert_close(turn.x, 40.0 * settings.sensitivity);
+            assert_close(turn.y, 25.0 * settings.sensitivity);
+        }
+    }
+
+    #[test]
+    fn invert_y_flips_pitch() {
+        let normal = settings(0.0);
+        let inverted = LookSettings {
+            invert_y: true,
+            ..normal
+        };
+        let motion = [Vector2::new(10.0, 20.0)];
+        let turn = turn_over(1, 0.1, &motion, &normal);
+        let flipped = turn_over(1, 0.1, &motion, &inverted);
+        assert_close(flipped.x, turn.x);
+        assert_close(flipped.y, -turn.y);
+        // moving the mouse down looks down unless inverted
+        assert!(turn.y < 0.0);
+    }
+
+    #[test]
+    fn smoothing_keeps_the_total() {
+        let settings = settings(0.1);
+        let motion = [Vector2::new(100.0, 50.0)];
+        let first = turn_over(1, 1.0 / 60.0, &motion, &settings);
+        assert!(first.x < 100.0 * settings.sensitivity);
+        // the same share after the same time at any frame rate
+        let slow = turn_over(30, 1.0 / 30.0, &motion, &settings);
+        let fast = turn_over(144, 1.0 / 144.0, &motion, &settings);
+        assert_close(slow.x, fast.x);
+        assert_close(slow.y, fast.y);
+        let all = turn_over(600, 1.0 / 60.0, &motion, &settings);
+        assert_close(all.x, 100.0 * settings.sensitivity);
+        assert_close(all.y, -50.0 * settings.sensitivity);
+    }
+
+    #[test]
+    fn fly_turns_by_the_look() {
+        let mut controller = fly();
+        controller.process_look(Vector2::new(0.2, 0.1));
+        controller.process_look(Vector2::new(0.1, -0.3));
+        let pose = controller.update(1.0 / 60.0, &Ground::default());
+        assert_close(pose.yaw.0, 0.3);
+        assert_close(pose.pitch.0, -0.2);
+    }
+
+    #[test]
+    fn pitch_clamps_at_the_limits() {
+        let mut controller = fly();
+        controller.process_look(Vector2::new(0.0, 10.0));
+        let pose = controller.update(1.0 / 60.0, &Ground::default());
+        assert_close(pose.pitch.0, FRAC_PI_2);
+        controller.process_look(Vector2::new(0.0, -20.0));
+        let pose = controller.update(1.0 / 60.0, &Ground::default());
+        assert_close(pose.pitch.0, -FRAC_PI_2);
+
+        let mut orbit = OrbitController::new(&pose);
+        orbit.process_look(Vector2::new(0.0, 10.0));
+        assert_close(
+            orbit.update(1.0 / 60.0, &Ground::default()).pitch.0,
+            MAX_PITCH,
+        );
+    }
+}
@@ -0,0 +1,35 @@
+// Box filter from a supersampled screenshot down to its final size
+
+// Mirrors `DownsampleUniform` in supersample.rs
+struct Downsample {
+    // source pixels per output pixel along each axis
+    scale: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> downsample: Downsample;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    return vec4<f32>(pos, 0.0, 1.0);
+}
+
+// the mean of the block under the pixel, in the stored encoding, which is linear for sRGB formats
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let scale = downsample.scale;
+    let origin = vec2<u32>(position.xy) * scale;
+    var sum = vec3<f32>(0.0);
+    for (var y = 0u; y < scale; y += 1u) {
+        for (var x = 0u; x < scale; x += 1u) {
+            sum += textureLoad(source_texture, origin + vec2<u32>(x, y), 0).rgb;
+        }
+    }
+    return vec4<f32>(sum / f32(scale * scale), 1.0);
+}
@@ -0,0 +1,201 @@
+use cgmath::*;
+use half::f16;
+use image::{DynamicImage, ImageError};
+use std::f32::consts::PI;
+
+// Side length of every cubemap face, loaded images are resampled to it
+pub const FACE_SIZE: u32 = 512;
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Decoded image in linear colour
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Image {
+    fn decode(bytes: &[u8]) -> Result<Self, ImageError> {
+        let image = image::load_from_memory(bytes)?;
+        // float formats (.hdr) are already linear, 8 bit ones are sRGB encoded
+        let linear = matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+        let image = image.to_rgba32f();
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                if linear {
+                    p.0
+                } else {
+                    [
+                        srgb_to_linear(p[0]),
+                        srgb_to_linear(p[1]),
+                        srgb_to_linear(p[2]),
+                        p[3],
+                    ]
+                }
+            })
+            .collect();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels,
+        })
+    }
+
+    fn pixel(&self, x: i64, y: i64, wrap_x: bool) -> Vector4<f32> {
+        let w = self.width as i64;
+        let h = self.height as i64;
+        let x = if wrap_x {
+            x.rem_euclid(w)
+        } else {
+            x.clamp(0, w - 1)
+        };
+        let y = y.clamp(0, h - 1);
+        Vector4::from(self.pixels[(y * w + x) as usize])
+    }
+
+    // (u, v) in [0, 1], pixel centres at (i + 0.5) / size
+    fn bilinear(&self, u: f32, v: f32, wrap_x: bool) -> Vector4<f32> {
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self
+            .pixel(x0, y0, wrap_x)
+            .lerp(self.pixel(x0 + 1, y0, wrap_x), fx);
+        let bottom = self
+            .pixel(x0, y0 + 1, wrap_x)
+            .lerp(self.pixel(x0 + 1, y0 + 1, wrap_x), fx);
+        top.lerp(bottom, fy)
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Direction through (u, v) in [-1, 1] of a cube face, v pointing down.
+// Faces are in layer order +X, -X, +Y, -Y, +Z, -Z
+pub fn face_direction(face: usize, u: f32, v: f32) -> Vector3<f32> {
+    let direction = match face {
+        0 => Vector3::new(1.0, -v, -u),
+        1 => Vector3::new(-1.0, -v, u),
+        2 => Vector3::new(u, 1.0, v),
+        3 => Vector3::new(u, -1.0, -v),
+        4 => Vector3::new(u, -v, 1.0),
+        _ => Vector3::new(-u, -v, -1.0),
+    };
+    direction.normalize()
+}
+
+// Direction through the centre of texel (x, y) of a face `size` texels wide
+pub fn texel_direction(face: usize, x: u32, y: u32, size: u32) -> Vector3<f32> {
+    let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    face_direction(face, u, v)
+}
+
+// Environment map for the sky, six faces of `FACE_SIZE` linear colour texels
+pub struct Environment {
+    faces: [Vec<[f32; 4]>; 6],
+}
+
+impl Environment {
+    // six encoded images, ordered +X, -X, +Y, -Y, +Z, -Z
+    pub fn from_faces(faces: [&[u8]; 6]) -> Result<Self, ImageError> {
+        let mut decoded = Vec::with_capacity(6);
+        for bytes in faces {
+            decoded.push(Image::decode(bytes)?);
+        }
+        Ok(Self::build(|face, u, v| {
+            decoded[face].bilinear(u * 0.5 + 0.5, v * 0.5 + 0.5, false)
+        }))
+    }
+
+    // a single equirectangular (latitude-longitude) image, usually an .hdr
+    pub fn from_equirect(bytes: &[u8]) -> Result<Self, ImageError> {
+        let image = Image::decode(bytes)?;
+        Ok(Self::build(|face, u, v| {
+            let d = face_direction(face, u, v);
+            // longitude 0 looks down -z, the image centre
+            let longitude = d.x.atan2(-d.z);
+            let latitude = d.y.clamp(-1.0, 1.0).asin();
+            image.bilinear(0.5 + longitude / (2.0 * PI), 0.5 - latitude / PI, true)
+        }))
+    }
+
+    pub fn texel(&self, face: usize, x: u32, y: u32) -> Vector3<f32> {
+        let [r, g, b, _] = self.faces[face][(y * FACE_SIZE + x) as usize];
+        Vector3::new(r, g, b)
+    }
+
+    fn build(sample: impl Fn(usize, f32, f32) -> Vector4<f32>) -> Self {
+        let faces = std::array::from_fn(|face| {
+            let mut texels = Vec::with_capacity((FACE_SIZE * FACE_SIZE) as usize);
+            for y in 0..FACE_SIZE {
+                for x in 0..FACE_SIZE {
+                    let u = (x as f32 + 0.5) / FACE_SIZE as f32 * 2.0 - 1.0;
+                    let v = (y as f32 + 0.5) / FACE_SIZE as f32 * 2.0 - 1.0;
+                    texels.push(sample(face, u, v).into());
+                }
+            }
+            texels
+        });
+        Self { faces }
+    }
+
+    // texel data of every face in `FORMAT`, ready for `Queue::write_texture`
+    pub fn texture_data(&self) -> Vec<f16> {
+        self.faces
+            .iter()
+            .flatten()
+            .flat_map(|texel| texel.map(f16::from_f32))
+            .collect()
+    }
+}
+
+pub fn create_texture(device: &wgpu::Device) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Environment Cubemap"),
+        size: wgpu::Extent3d {
+            width: FACE_SIZE,
+            height: FACE_SIZE,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        // the procedural sky is rendered into it when no image is loaded
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+pub fn write_texture(queue: &wgpu::Queue, texture: &wgpu::Texture, environment: &Environment) {
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        bytemuck::cast_slice(&environment.texture_data()),
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(FACE_SIZE * 8),
+            rows_per_image: Some(FACE_SIZE),
+        },
+        texture.size(),
+    );
+}
@@ -0,0 +1,82 @@
+// Automatic exposure, a log luminance histogram of the HDR scene reduced to an adapted average
+
+// Mirrors `ExposureUniform` in post.rs
+struct Exposure {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    // fraction of the way to the measured luminance covered this frame
+    adaptation: f32,
+    pixel_count: u32,
+}
+
+const BINS: u32 = 256u;
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+// adapted average luminance, copied into the tone mapping uniform each frame
+@group(0) @binding(2)
+var<storage, read_write> luminance: array<f32, 1>;
+@group(0) @binding(3)
+var<uniform> exposure: Exposure;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> weighted: array<f32, 256>;
+var<workgroup> black_pixels: u32;
+
+// bin 0 is reserved for near black pixels so they don't drag the average down
+fn luminance_bin(color: vec3<f32>) -> u32 {
+    let lum = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if lum < 0.0001 {
+        return 0u;
+    }
+    let t = saturate((log2(lum) - exposure.min_log_luminance) / exposure.log_luminance_range);
+    return u32(t * 254.0 + 1.0);
+}
+
+@compute @workgroup_size(16, 16)
+fn build_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(scene);
+    if id.x < size.x && id.y < size.y {
+        let color = textureLoad(scene, vec2<i32>(id.xy), 0).rgb;
+        atomicAdd(&local_bins[luminance_bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[index], atomicLoad(&local_bins[index]));
+}
+
+@compute @workgroup_size(256)
+fn average(@builtin(local_invocation_index) index: u32) {
+    // read and clear for the next frame
+    let count = atomicExchange(&histogram[index], 0u);
+    if index == 0u {
+        black_pixels = count;
+    }
+    weighted[index] = f32(count) * f32(index);
+    workgroupBarrier();
+
+    for (var stride = BINS / 2u; stride > 0u; stride >>= 1u) {
+        if index < stride {
+            weighted[index] += weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if index == 0u {
+        let lit = max(f32(exposure.pixel_count - black_pixels), 1.0);
+        let mean_bin = weighted[0] / lit - 1.0;
+        let measured = exp2(mean_bin / 254.0 * exposure.log_luminance_range + exposure.min_log_luminance);
+        let previous = luminance[0];
+        // the first frame, or a NaN from an earlier frame, jumps straight to the measurement
+        let adapted = select(previous + (measured - previous) * exposure.adaptation, measured, !(previous > 0.0));
+        luminance[0] = adapted;
+    }
+}
@@ -0,0 +1,33 @@
+// ===================
+// HEIGHT FOG, see `Fog` in atmosphere.rs
+// ===================
+
+// Prepended to terrain.wgsl, water.wgsl and skybox.wgsl, which declare `camera`,
+// `specular_texture` and `environment_sampler`
+
+// specular mip the sky coloured fog is read from, the blurriest one
+const FOG_LOD: f32 = 4.0;
+// strength of the glow around the sun
+const FOG_SUN_SCATTER: f32 = 0.5;
+
+// Exponential height fog over `distance` along `dir`, with aerial perspective from the sky
+fn height_fog(color: vec3<f32>, origin: vec3<f32>, dir: vec3<f32>, distance: f32) -> vec3<f32> {
+    let density = camera.fog_params.x;
+    let falloff = max(camera.fog_params.y, 0.00001);
+    let start = density * exp(-falloff * (origin.y - camera.fog_params.z));
+    // density integrated along the ray
+    var optical_depth = start * distance;
+    if abs(dir.y) > 0.0001 {
+        let exponent = min(-falloff * dir.y * distance, 80.0);
+        optical_depth = start * (1.0 - exp(exponent)) / (falloff * dir.y);
+    }
+    let transmittance = exp(-optical_depth);
+
+    // the sky around the horizon in the direction of the ray
+    let horizon = normalize(vec3<f32>(dir.x, max(dir.y, 0.0) * 0.5 + 0.001, dir.z));
+    let sky = textureSampleLevel(specular_texture, environment_sampler, horizon, FOG_LOD).rgb;
+    let sun = camera.sun_color.rgb * FOG_SUN_SCATTER
+        * pow(max(dot(dir, camera.sun_direction.xyz), 0.0), 8.0);
+    let fog_color = mix(camera.fog_color.rgb, sky, camera.fog_color.w) + sun;
+    return mix(fog_color, color, transmittance);
+}
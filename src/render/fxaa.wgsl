@@ -0,0 +1,175 @@
+// FXAA over the tone mapped image, after Timothy Lottes' FXAA 3.11 quality preset
+
+@group(0) @binding(0)
+var ldr_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var ldr_sampler: sampler;
+
+// contrast below max(EDGE_THRESHOLD_MIN, local maximum * EDGE_THRESHOLD_MAX) is left alone
+const EDGE_THRESHOLD_MIN: f32 = 0.0312;
+const EDGE_THRESHOLD_MAX: f32 = 0.125;
+const SUBPIXEL_QUALITY: f32 = 0.75;
+const ITERATIONS: u32 = 12u;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    out.position = vec4<f32>(pos, 0.0, 1.0);
+    out.uv = vec2<f32>(pos.x * 0.5 + 0.5, 0.5 - pos.y * 0.5);
+    return out;
+}
+
+fn sample_ldr(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(ldr_texture, ldr_sampler, uv, 0.0).rgb;
+}
+
+// the image is already display encoded, so this is close to perceived brightness
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+fn luma_at(uv: vec2<f32>, texel: vec2<f32>, x: f32, y: f32) -> f32 {
+    return luma(sample_ldr(uv + texel * vec2<f32>(x, y)));
+}
+
+// edge search step, in texels, growing the further the search goes
+fn search_step(i: u32) -> f32 {
+    if i < 5u {
+        return 1.0;
+    } else if i == 5u {
+        return 1.5;
+    } else if i < 10u {
+        return 2.0;
+    } else if i == 10u {
+        return 4.0;
+    }
+    return 8.0;
+}
+
+fn fxaa(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(ldr_texture));
+    let center = sample_ldr(uv);
+    let luma_center = luma(center);
+    let luma_up = luma_at(uv, texel, 0.0, -1.0);
+    let luma_down = luma_at(uv, texel, 0.0, 1.0);
+    let luma_left = luma_at(uv, texel, -1.0, 0.0);
+    let luma_right = luma_at(uv, texel, 1.0, 0.0);
+
+    let luma_min = min(luma_center, min(min(luma_up, luma_down), min(luma_left, luma_right)));
+    let luma_max = max(luma_center, max(max(luma_up, luma_down), max(luma_left, luma_right)));
+    let luma_range = luma_max - luma_min;
+    if luma_range < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD_MAX) {
+        return center;
+    }
+
+    let luma_up_left = luma_at(uv, texel, -1.0, -1.0);
+    let luma_up_right = luma_at(uv, texel, 1.0, -1.0);
+    let luma_down_left = luma_at(uv, texel, -1.0, 1.0);
+    let luma_down_right = luma_at(uv, texel, 1.0, 1.0);
+    let luma_up_down = luma_up + luma_down;
+    let luma_left_right = luma_left + luma_right;
+    let luma_left_corners = luma_up_left + luma_down_left;
+    let luma_right_corners = luma_up_right + luma_down_right;
+    let luma_up_corners = luma_up_left + luma_up_right;
+    let luma_down_corners = luma_down_left + luma_down_right;
+
+    let edge_horizontal = abs(-2.0 * luma_left + luma_left_corners)
+        + abs(-2.0 * luma_center + luma_up_down) * 2.0
+        + abs(-2.0 * luma_right + luma_right_corners);
+    let edge_vertical = abs(-2.0 * luma_up + luma_up_corners)
+        + abs(-2.0 * luma_center + luma_left_right) * 2.0
+        + abs(-2.0 * luma_down + luma_down_corners);
+    let horizontal = edge_horizontal >= edge_vertical;
+
+    // the neighbours across the edge, towards negative and positive uv
+    let luma_negative = select(luma_left, luma_up, horizontal);
+    let luma_positive = select(luma_right, luma_down, horizontal);
+    let gradient_negative = luma_negative - luma_center;
+    let gradient_positive = luma_positive - luma_center;
+    let negative_steepest = abs(gradient_negative) >= abs(gradient_positive);
+    let gradient_scaled = 0.25 * max(abs(gradient_negative), abs(gradient_positive));
+
+    var step_length = select(texel.x, texel.y, horizontal);
+    var luma_local_average: f32;
+    if negative_steepest {
+        step_length = -step_length;
+        luma_local_average = 0.5 * (luma_negative + luma_center);
+    } else {
+        luma_local_average = 0.5 * (luma_positive + luma_center);
+    }
+
+    // walk along the edge, half a texel across it, until the contrast ends on both sides
+    var edge_uv = uv;
+    if horizontal {
+        edge_uv.y += step_length * 0.5;
+    } else {
+        edge_uv.x += step_length * 0.5;
+    }
+    let offset = select(vec2<f32>(0.0, texel.y), vec2<f32>(texel.x, 0.0), horizontal);
+    var uv1 = edge_uv - offset;
+    var uv2 = edge_uv + offset;
+    var luma_end1 = luma(sample_ldr(uv1)) - luma_local_average;
+    var luma_end2 = luma(sample_ldr(uv2)) - luma_local_average;
+    var reached1 = abs(luma_end1) >= gradient_scaled;
+    var reached2 = abs(luma_end2) >= gradient_scaled;
+    for (var i = 1u; i < ITERATIONS && !(reached1 && reached2); i++) {
+        if !reached1 {
+            uv1 -= offset * search_step(i);
+            luma_end1 = luma(sample_ldr(uv1)) - luma_local_average;
+            reached1 = abs(luma_end1) >= gradient_scaled;
+        }
+        if !reached2 {
+            uv2 += offset * search_step(i);
+            luma_end2 = luma(sample_ldr(uv2)) - luma_local_average;
+            reached2 = abs(luma_end2) >= gradient_scaled;
+        }
+    }
+
+    let distance1 = select(uv.y - uv1.y, uv.x - uv1.x, horizontal);
+    let distance2 = select(uv2.y - uv.y, uv2.x - uv.x, horizontal);
+    let closer1 = distance1 < distance2;
+    let distance_final = min(distance1, distance2);
+    let edge_length = distance1 + distance2;
+    let pixel_offset = 0.5 - distance_final / edge_length;
+    // only blend when the closer end moves away from the centre's side of the edge
+    let center_smaller = luma_center < luma_local_average;
+    let correct_variation = (select(luma_end2, luma_end1, closer1) < 0.0) != center_smaller;
+    var final_offset = select(0.0, pixel_offset, correct_variation);
+
+    // subpixel aliasing, thin lines and single pixels
+    let luma_average = (2.0 * (luma_up_down + luma_left_right) + luma_left_corners + luma_right_corners) / 12.0;
+    let subpixel1 = saturate(abs(luma_average - luma_center) / luma_range);
+    let subpixel2 = (-2.0 * subpixel1 + 3.0) * subpixel1 * subpixel1;
+    final_offset = max(final_offset, subpixel2 * subpixel2 * SUBPIXEL_QUALITY);
+
+    var final_uv = uv;
+    if horizontal {
+        final_uv.y += final_offset * step_length;
+    } else {
+        final_uv.x += final_offset * step_length;
+    }
+    return sample_ldr(final_uv);
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// for sRGB surfaces, which encode again on write
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(srgb_to_linear(fxaa(in.uv)), 1.0);
+}
+
+@fragment
+fn fs_encoded(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(fxaa(in.uv), 1.0);
+}
@@ -0,0 +1,264 @@
+// Horizon search for ambient occlusion, offsets in samples along each direction
+const HORIZON_STEPS: [usize; 10] = [1, 2, 3, 4, 6, 8, 12, 16, 24, 32];
+const HORIZON_DIRECTIONS: [(isize, isize); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+// World-space heights of the terrain grid, shared with the other passes through `ContextState`
+pub struct Heightmap {
+    size: usize,
+    // distance between neighbouring samples in world units
+    spacing: f32,
+    // world position of sample (0, 0)
+    origin: [f32; 2],
+    // heights[i * size + j] lies at x = origin.x + i * spacing, z = origin.z + j * spacing
+    heights: Vec<f32>,
+    sorted: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn new(size: usize, spacing: f32, origin: [f32; 2], heights: Vec<f32>) -> Self {
+        assert_eq!(heights.len(), size * size);
+        let mut sorted = heights.clone();
+        sorted.sort_by(f32::total_cmp);
+        Self {
+            size,
+            spacing,
+            origin,
+            heights,
+            sorted,
+        }
+    }
+
+    pub fn heights(&self) -> &[f32] {
+        &self.heights
+    }
+
+    // world (x, z) of the first sample and the side length covered
+    pub fn extent(&self) -> ([f32; 2], f32) {
+        (self.origin, (self.size - 1) as f32 * self.spacing)
+    }
+
+    pub fn min_height(&self) -> f32 {
+        self.sorted[0]
+    }
+
+    pub fn max_height(&self) -> f32 {
+        self.sorted[self.sorted.len() - 1]
+    }
+
+    // height below which `fraction` of the samples lie
+    pub fn percentile(&self, fraction: f32) -> f32 {
+        let last = self.sorted.len() - 1;
+        let index = (fraction.clamp(0.0, 1.0) * last as f32).round() as usize;
+        self.sorted[index]
+    }
+
+    // inverse of `percentile`
+    pub fn fraction_below(&self, height: f32) -> f32 {
+        let below = self.sorted.partition_point(|&h| h < height);
+        below as f32 / self.sorted.len() as f32
+    }
+
+    // heights at the corners of the grid cell under world (x, z) and the position inside it
+    fn cell(&self, x: f32, z: f32) -> Option<([f32; 4], f32, f32)> {
+        let u = (x - self.origin[0]) / self.spacing;
+        let v = (z - self.origin[1]) / self.spacing;
+        let last = (self.size - 1) as f32;
+        if !(0.0..=last).contains(&u) || !(0.0..=last).contains(&v) {
+            return None;
+        }
+        // the far edge belongs to the last cell
+        let i = (u as usize).min(self.size - 2);
+        let j = (v as usize).min(self.size - 2);
+        let height = |i: usize, j: usize| self.heights[i * self.size + j];
+        let corners = [
+            height(i, j),
+            height(i + 1, j),
+            height(i, j + 1),
+            height(i + 1, j + 1),
+        ];
+        Some((corners, u - i as f32, v - j as f32))
+    }
+
+    // terrain height under world (x, z), bilinear between the four surrounding samples
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let ([h00, h10, h01, h11], fu, fv) = self.cell(x, z)?;
+        let near = h00 + (h10 - h00) * fu;
+        let far = h01 + (h11 - h01) * fu;
+        Some(near + (far - near) * fv)
+    }
+
+    // rise per world unit along x and z of the same bilinear surface
+    pub fn gradient_at(&self, x: f32, z: f32) -> Option<[f32; 2]> {
+        let ([h00, h10, h01, h11], fu, fv) = self.cell(x, z)?;
+        let dx = (h10 - h00) * (1.0 - fv) + (h11 - h01) * fv;
+        let dz = (h01 - h00) * (1.0 - fu) + (h11 - h10) * fu;
+        Some([dx / self.spacing, dz / self.spacing])
+    }
+
+    // Fraction of the sky visible from each sample, in the same order as `heights`.
+    // Each direction is open above the highest horizon, cos^2 of its elevation weighted by the cosine lobe
+    pub fn horizon_occlusion(&self) -> Vec<f32> {
+        let size = self.size as isize;
+        let mut occlusion = Vec::with_capacity(self.heights.len());
+        for i in 0..size {
+            for j in 0..size {
+                let height = self.heights[(i * size + j) as usize];
+                let mut visible = 0.0;
+                for (di, dj) in HORIZON_DIRECTIONS {
+                    let unit = ((di * di + dj * dj) as f32).sqrt() * self.spacing;
+                    // tangent of the horizon elevation
+                    let mut horizon: f32 = 0.0;
+                    for step in HORIZON_STEPS {
+                        let (si, sj) = (i + di * step as isize, j + dj * step as isize);
+                        if si < 0 || sj < 0 || si >= size || sj >= size {
+                            break;
+                        }
+                        let rise = self.heights[(si * size + sj) as usize] - height;
+                        horizon = horizon.max(rise / (step as f32 * unit));
+                    }
+                    // cos^2 of atan(horizon)
+                    visible += 1.0 / (1.0 + horizon * horizon);
+                }
+                occlusion.push(visible / HORIZON_DIRECTIONS.len() as f32);
+            }
+        }
+        occlusion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 33;
+
+    // heights from the world position of each sample, one unit apart around the origin
+    fn heightmap(height: impl Fn(f32, f32) -> f32) -> Heightmap {
+        let half = (SIZE / 2) as f32;
+        let mut heights = Vec::with_capacity(SIZE * SIZE);
+        for i in 0..SIZE {
+            for j in 0..SIZE {
+                heights.push(height(i as f32 - half, j as f32 - half));
+            }
+        }
+        Heightmap::new(SIZE, 1.0, [-half, -half], heights)
+    }
+
+    // 3 x 3 samples two units apart from (10, 20), heights[i * 3 + j] at x = 10 + 2i, z = 20 + 2j
+    fn grid() -> Heightmap {
+        let heights = vec![
+            0.0, 1.0, 2.0, //
+            4.0, 8.0, 6.0, //
+            -2.0, 3.0, 5.0,
+        ];
+        Heightmap::new(3, 2.0, [10.0, 20.0], heights)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    fn centre(occlusion: &[f32]) -> f32 {
+        occlusion[SIZE / 2 * SIZE + SIZE / 2]
+    }
+
+    #[test]
+    fn flat_terrain_is_unoccluded() {
+        let occlusion = heightmap(|_, _| 5.0).horizon_occlusion();
+        assert_eq!(occlusion.len(), SIZE * SIZE);
+        for visible in occlusion {
+            assert!((visible - 1.0).abs() < 1e-6, "{visible}");
+        }
+    }
+
+    #[test]
+    fn valley_floor_is_occluded() {
+        let pit = heightmap(|x, z| (x * x + z * z).sqrt());
+        let visible = centre(&pit.horizon_occlusion());
+        // walls rising at 45 degrees hide half of the cosine weighted sky
+        assert!(visible < 0.6, "{visible}");
+    }
+
+    #[test]
+    fn peak_is_unoccluded() {
+        let peak = heightmap(|x, z| -(x * x + z * z).sqrt());
+        let visible = centre(&peak.horizon_occlusion());
+        assert!((visible - 1.0).abs() < 1e-6, "{visible}");
+    }
+
+    #[test]
+    fn height_at_samples() {
+        let grid = grid();
+        for i in 0..3 {
+            for j in 0..3 {
+                let (x, z) = (10.0 + 2.0 * i as f32, 20.0 + 2.0 * j as f32);
+                assert_eq!(grid.height_at(x, z), Some(grid.heights()[i * 3 + j]));
+            }
+        }
+    }
+
+    #[test]
+    fn height_at_cell_midpoints() {
+        let grid = grid();
+        // the mean of the four corners
+        assert_close(
+            grid.height_at(11.0, 21.0).unwrap(),
+            (0.0 + 1.0 + 4.0 + 8.0) / 4.0,
+        );
+        assert_close(
+            grid.height_at(13.0, 23.0).unwrap(),
+            (8.0 + 6.0 + 3.0 + 5.0) / 4.0,
+        );
+        // halfway along an edge
+        assert_close(grid.height_at(11.0, 20.0).unwrap(), 2.0);
+        assert_close(grid.height_at(14.0, 21.0).unwrap(), 0.5);
+        // a quarter into the cell along x and three quarters along z
+        assert_close(grid.height_at(10.5, 21.5).unwrap(), 2.3125);
+    }
+
+    #[test]
+    fn height_at_far_edge() {
+        let grid = grid();
+        assert_close(grid.height_at(14.0, 24.0).unwrap(), 5.0);
+        assert_close(grid.height_at(14.0, 23.0).unwrap(), 4.0);
+        assert_close(grid.height_at(13.0, 24.0).unwrap(), 5.5);
+    }
+
+    #[test]
+    fn height_at_outside_is_none() {
+        let grid = grid();
+        for (x, z) in [
+            (9.99, 22.0),
+            (14.01, 22.0),
+            (12.0, 19.99),
+            (12.0, 24.01),
+            (-1e9, 1e9),
+        ] {
+            assert_eq!(grid.height_at(x, z), None);
+            assert_eq!(grid.gradient_at(x, z), None);
+        }
+        assert_eq!(grid.height_at(f32::NAN, 22.0), None);
+    }
+
+    #[test]
+    fn gradient_of_a_plane() {
+        let plane = heightmap(|x, z| 2.0 * x - 0.5 * z + 3.0);
+        let (origin, side) = plane.extent();
+        for (u, v) in [(0.0, 0.0), (0.3, 0.7), (0.5, 0.5), (1.0, 1.0), (1.0, 0.2)] {
+            let (x, z) = (origin[0] + u * side, origin[1] + v * side);
+            let [dx, dz] = plane.gradient_at(x, z).unwrap();
+            assert_close(dx, 2.0);
+            assert_close(dz, -0.5);
+            assert_close(plane.height_at(x, z).unwrap(), 2.0 * x - 0.5 * z + 3.0);
+        }
+    }
+}
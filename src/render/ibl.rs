@@ -0,0 +1,513 @@
+use cgmath::*;
+use std::rc::Rc;
+use wgpu::util::DeviceExt;
+
+use super::atmosphere::{self, Lighting};
+use super::environment::{self, Environment};
+
+// Texels per face side when projecting the procedural sky
+const SH_RESOLUTION: u32 = 16;
+// Glossy reflections, roughness goes from 0 at mip 0 to 1 at the last mip
+pub const SPECULAR_SIZE: u32 = 128;
+pub const SPECULAR_MIPS: u32 = 5;
+// The procedural sky is re-rendered once the sun has moved this far
+const SUN_TOLERANCE: Deg<f32> = Deg(0.25);
+
+// Order 2 spherical harmonics of the incoming light, nine RGB coefficients
+#[derive(Clone, Copy, Debug)]
+pub struct SphericalHarmonics {
+    pub coefficients: [Vector3<f32>; 9],
+}
+
+// real SH basis up to order 2, in the same order as `coefficients`
+fn sh_basis(d: Vector3<f32>) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+impl SphericalHarmonics {
+    // Projects the radiance of every texel of a cubemap `size` texels wide,
+    // `radiance(face, x, y)` with faces ordered +X, -X, +Y, -Y, +Z, -Z
+    pub fn project(size: u32, radiance: impl Fn(usize, u32, u32) -> Vector3<f32>) -> Self {
+        let mut coefficients = [Vector3::zero(); 9];
+        let mut total_weight = 0.0;
+        for face in 0..6 {
+            for y in 0..size {
+                for x in 0..size {
+                    let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                    let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                    // solid angle of the texel, up to a constant factor
+                    let weight = 1.0 / (1.0 + u * u + v * v).powf(1.5);
+                    let direction = environment::face_direction(face, u, v);
+                    let color = radiance(face, x, y);
+                    for (c, basis) in coefficients.iter_mut().zip(sh_basis(direction)) {
+                        *c += color * basis * weight;
+                    }
+                    total_weight += weight;
+                }
+            }
+        }
+        // normalise so the weights sum to the full sphere
+        let scale = 4.0 * std::f32::consts::PI / total_weight;
+        Self {
+            coefficients: coefficients.map(|c| c * scale),
+        }
+    }
+
+    // Irradiance at a surface facing `normal`, pi L under a constant sky of radiance L.
+    // `sh_irradiance` in irradiance.wgsl returns it divided by pi
+    #[cfg(test)]
+    fn irradiance(&self, normal: Vector3<f32>) -> Vector3<f32> {
+        let uniform = self.uniform();
+        let basis = sh_basis(normal.normalize());
+        let sum: Vector3<f32> = uniform
+            .coefficients
+            .iter()
+            .zip(basis)
+            .map(|(c, b)| Vector3::new(c[0], c[1], c[2]) * b)
+            .sum();
+        sum * std::f32::consts::PI
+    }
+
+    // coefficients convolved with the clamped cosine lobe and divided by pi
+    // (Ramamoorthi and Hanrahan 2001), ready for the shaders
+    fn uniform(&self) -> IrradianceUniform {
+        const BAND_SCALE: [f32; 9] = [
+            1.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+        ];
+        let mut coefficients = [[0.0; 4]; 9];
+        for ((out, c), scale) in coefficients
+            .iter_mut()
+            .zip(self.coefficients)
+            .zip(BAND_SCALE)
+        {
+            *out = (c * scale).extend(0.0).into();
+        }
+        IrradianceUniform { coefficients }
+    }
+}
+
+// Mirrors `Irradiance` in the terrain and water shaders
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct IrradianceUniform {
+    coefficients: [[f32; 4]; 9],
+}
+
+// Mirrors `Face` in ibl.wgsl and skybox.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FaceUniform {
+    index: u32,
+    roughness: f32,
+    _padding: [f32; 2],
+}
+
+fn face_view(texture: &wgpu::Texture, face: u32, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        base_array_layer: face,
+        array_layer_count: Some(1),
+        ..Default::default()
+    })
+}
+
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn face_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layouts: &[&wgpu::BindGroupLayout],
+    module: &wgpu::ShaderModule,
+    vertex_entry: &str,
+    fragment_entry: &str,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: layouts,
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: Some(vertex_entry),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: Some(fragment_entry),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: environment::FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+// Image based lighting from the sky: SH irradiance for diffuse light and a prefiltered
+// cubemap for reflections. Bound with the camera uniform, see `Camera::new`
+pub struct Ibl {
+    device: wgpu::Device,
+    // sky radiance, either the loaded environment or the procedural sky
+    environment_texture: wgpu::Texture,
+    pub environment_view: wgpu::TextureView,
+    pub specular_view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub irradiance_buffer: wgpu::Buffer,
+    // procedural sky into the environment faces
+    sky_pipeline: wgpu::RenderPipeline,
+    sky_camera_bind_group: wgpu::BindGroup,
+    sky_faces: Vec<(wgpu::TextureView, wgpu::BindGroup)>,
+    // environment into every face and mip of the specular cubemap
+    prefilter_pipeline: wgpu::RenderPipeline,
+    prefilter_faces: Vec<(wgpu::TextureView, wgpu::BindGroup)>,
+    // what the maps currently hold
+    environment: Option<Rc<Environment>>,
+    sun: Option<Vector3<f32>>,
+}
+
+impl Ibl {
+    // `camera_buffer` holds the sun used to render the procedural sky
+    pub fn new(device: &wgpu::Device, camera_buffer: &wgpu::Buffer) -> Self {
+        let environment_texture = environment::create_texture(device);
+        let specular_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Specular Cubemap"),
+            size: wgpu::Extent3d {
+                width: SPECULAR_SIZE,
+                height: SPECULAR_SIZE,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: SPECULAR_MIPS,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: environment::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let irradiance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Irradiance Buffer"),
+            contents: bytemuck::cast_slice(&[SphericalHarmonics {
+                coefficients: [Vector3::zero(); 9],
+            }
+            .uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let environment_view = cube_view(&environment_texture);
+
+        // one uniform per face and mip level, the sky only uses mip 0
+        let face_buffers: Vec<Vec<wgpu::Buffer>> = (0..6)
+            .map(|face| {
+                (0..SPECULAR_MIPS)
+                    .map(|mip| {
+                        let uniform = FaceUniform {
+                            index: face,
+                            roughness: mip as f32 / (SPECULAR_MIPS - 1) as f32,
+                            _padding: Default::default(),
+                        };
+                        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: Some("Cubemap Face Buffer"),
+                            contents: bytemuck::cast_slice(&[uniform]),
+                            usage: wgpu::BufferUsages::UNIFORM,
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // === Procedural sky ===
+        let sky_camera_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sky Face Camera Bind Group Layout"),
+            entries: &[uniform_entry(0)],
+        });
+        let sky_face_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sky Face Bind Group Layout"),
+            entries: &[uniform_entry(0)],
+        });
+        let sky_camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sky Face Camera Bind Group"),
+            layout: &sky_camera_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+        let sky_faces = (0..6)
+            .map(|face| {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Sky Face Bind Group"),
+                    layout: &sky_face_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: face_buffers[face][0].as_entire_binding(),
+                    }],
+                });
+                (face_view(&environment_texture, face as u32, 0), bind_group)
+            })
+            .collect();
+        let sky_pipeline = face_pipeline(
+            device,
+            "Sky Face Pipeline",
+            &[&sky_camera_layout, &sky_face_layout],
+            &device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("skybox.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(
+                    concat!(include_str!("fog.wgsl"), include_str!("skybox.wgsl")).into(),
+                ),
+            }),
+            "vs_face",
+            "fs_environment",
+        );
+
+        // === Prefilter ===
+        let prefilter_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Prefilter Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                uniform_entry(2),
+            ],
+        });
+        let mut prefilter_faces = Vec::new();
+        for (face, buffers) in face_buffers.iter().enumerate() {
+            for (mip, buffer) in buffers.iter().enumerate() {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Prefilter Bind Group"),
+                    layout: &prefilter_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&environment_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+                let view = face_view(&specular_texture, face as u32, mip as u32);
+                prefilter_faces.push((view, bind_group));
+            }
+        }
+        let prefilter_pipeline = face_pipeline(
+            device,
+            "Prefilter Pipeline",
+            &[&prefilter_layout],
+            &device.create_shader_module(wgpu::include_wgsl!("ibl.wgsl")),
+            "vs_main",
+            "fs_main",
+        );
+
+        Self {
+            device: device.clone(),
+            environment_texture,
+            environment_view,
+            specular_view: cube_view(&specular_texture),
+            sampler,
+            irradiance_buffer,
+            sky_pipeline,
+            sky_camera_bind_group,
+            sky_faces,
+            prefilter_pipeline,
+            prefilter_faces,
+            environment: None,
+            sun: None,
+        }
+    }
+
+    fn draw_faces(
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        shared: Option<&wgpu::BindGroup>,
+        faces: &[(wgpu::TextureView, wgpu::BindGroup)],
+    ) {
+        for (view, bind_group) in faces {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Cubemap Face Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(pipeline);
+            match shared {
+                Some(shared) => {
+                    pass.set_bind_group(0, shared, &[]);
+                    pass.set_bind_group(1, bind_group, &[]);
+                }
+                None => pass.set_bind_group(0, bind_group, &[]),
+            }
+            pass.draw(0..3, 0..1);
+        }
+    }
+
+    // Called after the camera uniform is written, refreshes the maps when the sky changed
+    pub fn update(
+        &mut self,
+        environment: Option<&Rc<Environment>>,
+        lighting: &Lighting,
+        queue: &wgpu::Queue,
+    ) {
+        let sun = lighting.sun_direction;
+        let changed = match (environment, &self.environment) {
+            (Some(new), Some(old)) => !Rc::ptr_eq(new, old),
+            (Some(_), None) => true,
+            (None, Some(_)) => true,
+            // the procedural sky follows the sun
+            (None, None) => self
+                .sun
+                .is_none_or(|old| old.angle(sun) > SUN_TOLERANCE.into()),
+        };
+        if !changed {
+            return;
+        }
+        self.environment = environment.cloned();
+        self.sun = Some(sun);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("IBL Encoder"),
+            });
+        let sh = match environment {
+            Some(environment) => {
+                environment::write_texture(queue, &self.environment_texture, environment);
+                SphericalHarmonics::project(environment::FACE_SIZE, |face, x, y| {
+                    environment.texel(face, x, y)
+                })
+            }
+            None => {
+                Self::draw_faces(
+                    &mut encoder,
+                    &self.sky_pipeline,
+                    Some(&self.sky_camera_bind_group),
+                    &self.sky_faces,
+                );
+                SphericalHarmonics::project(SH_RESOLUTION, |face, x, y| {
+                    let direction = environment::texel_direction(face, x, y, SH_RESOLUTION);
+                    atmosphere::environment_radiance(direction, sun)
+                })
+            }
+        };
+        Self::draw_faces(
+            &mut encoder,
+            &self.prefilter_pipeline,
+            None,
+            &self.prefilter_faces,
+        );
+        queue.write_buffer(
+            &self.irradiance_buffer,
+            0,
+            bytemuck::cast_slice(&[sh.uniform()]),
+        );
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn constant_sky_gives_pi_radiance() {
+        let radiance = Vector3::new(0.2, 0.5, 1.0);
+        let sh = SphericalHarmonics::project(16, |_, _, _| radiance);
+        let normals = [
+            Vector3::unit_x(),
+            -Vector3::unit_x(),
+            Vector3::unit_y(),
+            -Vector3::unit_y(),
+            Vector3::unit_z(),
+            -Vector3::unit_z(),
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::new(-0.3, 0.8, -0.5),
+        ];
+        for normal in normals {
+            let irradiance = sh.irradiance(normal);
+            let expected = radiance * PI;
+            assert!(
+                (irradiance - expected).magnitude() < 1e-3,
+                "{normal:?}: {irradiance:?} != {expected:?}"
+            );
+        }
+    }
+}
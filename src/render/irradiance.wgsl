@@ -0,0 +1,21 @@
+// Prepended to terrain.wgsl and water.wgsl
+
+// SH irradiance of the sky divided by pi, see `SphericalHarmonics` in ibl.rs
+struct Irradiance {
+    coefficients: array<vec4<f32>, 9>,
+}
+@group(0) @binding(3)
+var<uniform> irradiance: Irradiance;
+
+fn sh_irradiance(n: vec3<f32>) -> vec3<f32> {
+    let c = irradiance.coefficients;
+    return c[0].rgb * 0.282095
+        + c[1].rgb * 0.488603 * n.y
+        + c[2].rgb * 0.488603 * n.z
+        + c[3].rgb * 0.488603 * n.x
+        + c[4].rgb * 1.092548 * n.x * n.y
+        + c[5].rgb * 1.092548 * n.y * n.z
+        + c[6].rgb * 0.315392 * (3.0 * n.z * n.z - 1.0)
+        + c[7].rgb * 1.092548 * n.x * n.z
+        + c[8].rgb * 0.546274 * (n.x * n.x - n.y * n.y);
+}
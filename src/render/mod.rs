@@ -3,9 +3,38 @@ pub mod perlin;
 pub mod camera;
 pub mod sky;
 pub mod water;
+pub mod targets;
+pub mod waves;
+pub mod ocean;
+pub mod heightmap;
+pub mod atmosphere;
+pub mod environment;
+pub mod ibl;
+pub mod clouds;
+pub mod post;
+pub mod antialiasing;
+pub mod controller;
+pub mod path;
+pub mod readback;
+pub mod resolution;
+pub mod capabilities;
+pub mod supersample;
 
 pub use water::WaterPass;
 pub use sky::SkyPass;
+pub use clouds::CloudPass;
 pub use perlin::PerlinPass;
-pub use renderable::Renderable;
-pub use camera::Camera;
\ No newline at end of file
+pub use renderable::{RenderStage, Renderable};
+pub use targets::RenderTargets;
+pub use camera::Camera;
+pub use heightmap::Heightmap;
+pub use atmosphere::{Fog, Lighting};
+pub use environment::Environment;
+pub use post::PostProcess;
+pub use antialiasing::AntiAliasing;
+pub use controller::{AnalogInput, LookSettings};
+pub use path::CameraPath;
+pub use readback::FrameReadback;
+pub use resolution::{Resolution, ResolutionSettings};
+pub use capabilities::Capabilities;
+pub use supersample::Supersample;
\ No newline at end of file
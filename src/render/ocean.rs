@@ -0,0 +1,992 @@
+use cgmath::*;
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
+use wgpu::util::DeviceExt;
+
+use super::capabilities::Capabilities;
+
+const GRAVITY: f32 = 9.81;
+// Phillips constant, also the equilibrium range constant of the Phillips spectrum
+const PHILLIPS_ALPHA: f32 = 0.0081;
+// JONSWAP peak enhancement
+const JONSWAP_GAMMA: f32 = 3.3;
+// waves travelling against the wind are damped by this factor
+const UPWIND_DAMPING: f32 = 0.07;
+// largest grid simulated on the CPU when compute shaders are unavailable
+const CPU_RESOLUTION: usize = 64;
+const FIELDS: usize = 3;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub const fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    // e^(i * theta)
+    pub fn from_angle(theta: f32) -> Self {
+        let (sin, cos) = theta.sin_cos();
+        Self::new(cos, sin)
+    }
+
+    pub fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    // multiply by i
+    pub fn rotate(self) -> Self {
+        Self::new(-self.im, self.re)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Mul<f32> for Complex {
+    type Output = Self;
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.re * rhs, self.im * rhs)
+    }
+}
+
+// One radix-2 Stockham stage, the same butterfly as ocean_fft.wgsl
+fn fft_stage(src: &[Complex], dst: &mut [Complex], stage_size: usize, inverse: bool) {
+    let n = src.len();
+    let sign = if inverse { 1.0 } else { -1.0 };
+    for j in 0..n / 2 {
+        let k = j % stage_size;
+        let twiddle = Complex::from_angle(sign * PI * k as f32 / stage_size as f32);
+        let a = src[j];
+        let b = src[j + n / 2] * twiddle;
+        let out = (j - k) * 2 + k;
+        dst[out] = a + b;
+        dst[out + stage_size] = a - b;
+    }
+}
+
+// Unnormalised radix-2 FFT, `data.len()` must be a power of two
+pub fn fft(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    assert!(n.is_power_of_two(), "FFT size must be a power of two");
+    let mut scratch = vec![Complex::default(); n];
+    let mut stage_size = 1;
+    let mut in_data = true;
+    while stage_size < n {
+        if in_data {
+            fft_stage(data, &mut scratch, stage_size, inverse);
+        } else {
+            fft_stage(&scratch, data, stage_size, inverse);
+        }
+        in_data = !in_data;
+        stage_size *= 2;
+    }
+    if !in_data {
+        data.copy_from_slice(&scratch);
+    }
+}
+
+// Row major n x n transform, rows first then columns
+pub fn fft_2d(data: &mut [Complex], n: usize, inverse: bool) {
+    assert_eq!(data.len(), n * n);
+    for row in data.chunks_mut(n) {
+        fft(row, inverse);
+    }
+    let mut column = vec![Complex::default(); n];
+    for x in 0..n {
+        for z in 0..n {
+            column[z] = data[z * n + x];
+        }
+        fft(&mut column, inverse);
+        for z in 0..n {
+            data[z * n + x] = column[z];
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spectrum {
+    Phillips,
+    Jonswap,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OceanParams {
+    pub spectrum: Spectrum,
+    // metres per second, 10 m above the surface
+    pub wind_speed: f32,
+    pub wind_direction: [f32; 2],
+    // distance over which the wind has been blowing in metres, used by JONSWAP
+    pub fetch: f32,
+    // horizontal displacement scale, 0 --> rolling sine-like waves
+    pub choppiness: f32,
+    // world units covered by one tile of the simulation
+    pub patch_size: f32,
+    // grid size, a power of two
+    pub resolution: usize,
+    pub seed: u32,
+}
+
+impl Default for OceanParams {
+    fn default() -> Self {
+        Self {
+            spectrum: Spectrum::Jonswap,
+            wind_speed: 8.0,
+            wind_direction: [1.0, 0.4],
+            fetch: 100_000.0,
+            choppiness: 1.2,
+            patch_size: 128.0,
+            resolution: 128,
+            seed: 1,
+        }
+    }
+}
+
+// deep water dispersion relation
+pub fn dispersion(k: f32) -> f32 {
+    (GRAVITY * k).sqrt()
+}
+
+// cos^2 spreading around the wind, normalised over the half circle
+fn directional_spreading(params: &OceanParams, k: Vector2<f32>) -> f32 {
+    let wind = Vector2::from(params.wind_direction).normalize();
+    let cos = k.normalize().dot(wind);
+    let spread = 2.0 / PI * cos * cos;
+    if cos < 0.0 {
+        spread * UPWIND_DAMPING
+    } else {
+        spread
+    }
+}
+
+// Energy density of the wave vector k in m^4, so that summing over the grid gives the height variance
+pub fn spectrum(params: &OceanParams, k: Vector2<f32>) -> f32 {
+    let k_len = k.magnitude();
+    if k_len < 1e-6 {
+        return 0.0;
+    }
+    let directional = directional_spreading(params, k);
+    match params.spectrum {
+        Spectrum::Phillips => {
+            // largest wave that the wind speed supports
+            let l = params.wind_speed * params.wind_speed / GRAVITY;
+            // suppress ripples much smaller than a grid cell
+            let small = params.patch_size / params.resolution as f32 * 0.1;
+            PHILLIPS_ALPHA / (2.0 * k_len.powi(4))
+                * (-1.0 / (k_len * l).powi(2)).exp()
+                * (-(k_len * small).powi(2)).exp()
+                * directional
+        }
+        Spectrum::Jonswap => {
+            let u = params.wind_speed;
+            let f = params.fetch;
+            let alpha = 0.076 * (u * u / (f * GRAVITY)).powf(0.22);
+            let omega_p = 22.0 * (GRAVITY * GRAVITY / (u * f)).powf(1.0 / 3.0);
+            let omega = dispersion(k_len);
+            let sigma = if omega <= omega_p { 0.07 } else { 0.09 };
+            let r = (-(omega - omega_p).powi(2) / (2.0 * sigma * sigma * omega_p * omega_p)).exp();
+            let s_omega = alpha * GRAVITY * GRAVITY / omega.powi(5)
+                * (-1.25 * (omega_p / omega).powi(4)).exp()
+                * JONSWAP_GAMMA.powf(r);
+            // S(w) dw --> E(k) dk, with dk spread over the circle of radius k
+            let d_omega_dk = GRAVITY / (2.0 * omega);
+            s_omega * d_omega_dk / k_len * directional
+        }
+    }
+}
+
+// SplitMix32 style generator, enough for reproducible spectra
+struct Random(u32);
+
+impl Random {
+    fn next_f32(&mut self) -> f32 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9);
+        let mut z = self.0;
+        z = (z ^ (z >> 16)).wrapping_mul(0x85EB_CA6B);
+        z = (z ^ (z >> 13)).wrapping_mul(0xC2B2_AE35);
+        z ^= z >> 16;
+        (z >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    // standard normal pair, Box-Muller
+    fn gaussian(&mut self) -> Complex {
+        let u1 = self.next_f32().max(1e-7);
+        let u2 = self.next_f32();
+        let r = (-2.0 * u1.ln()).sqrt();
+        Complex::from_angle(2.0 * PI * u2) * r
+    }
+}
+
+// Signed wave index for FFT ordering, 0..n/2 are positive
+fn wave_index(i: usize, n: usize) -> f32 {
+    if i < n / 2 {
+        i as f32
+    } else {
+        i as f32 - n as f32
+    }
+}
+
+// CPU reference of the ocean simulation run by the compute shaders
+pub struct OceanSimulation {
+    params: OceanParams,
+    h0: Vec<Complex>,
+    omega: Vec<f32>,
+}
+
+impl OceanSimulation {
+    pub fn new(params: OceanParams) -> Self {
+        let n = params.resolution;
+        assert!(n.is_power_of_two(), "ocean resolution must be a power of two");
+        let dk = 2.0 * PI / params.patch_size;
+        let mut random = Random(params.seed);
+        let mut h0 = Vec::with_capacity(n * n);
+        let mut omega = Vec::with_capacity(n * n);
+        for z in 0..n {
+            for x in 0..n {
+                let k = Vector2::new(wave_index(x, n), wave_index(z, n)) * dk;
+                // h0(k) and h0(-k) both feed h(k), so each carries half the energy
+                let amplitude = (spectrum(&params, k) * dk * dk / 4.0).sqrt();
+                h0.push(random.gaussian() * amplitude);
+                omega.push(dispersion(k.magnitude()));
+            }
+        }
+        Self { params, h0, omega }
+    }
+
+    pub fn params(&self) -> &OceanParams {
+        &self.params
+    }
+
+    // Height spectrum at wave (x, z), h(-k) is the conjugate of h(k) so the heights are real
+    fn height(&self, x: usize, z: usize, time: f32) -> Complex {
+        let n = self.params.resolution;
+        let index = z * n + x;
+        let minus = ((n - z) % n) * n + (n - x) % n;
+        let e = Complex::from_angle(self.omega[index] * time);
+        self.h0[index] * e + self.h0[minus].conj() * e.conj()
+    }
+
+    // Packed spectra of (height + i dx), (dz + i slope_x) and (slope_z) at `time`
+    pub fn fields(&self, time: f32) -> Vec<Complex> {
+        let n = self.params.resolution;
+        let dk = 2.0 * PI / self.params.patch_size;
+        let mut fields = vec![Complex::default(); FIELDS * n * n];
+        for z in 0..n {
+            for x in 0..n {
+                let index = z * n + x;
+                let h = self.height(x, z, time);
+
+                let k = Vector2::new(wave_index(x, n), wave_index(z, n)) * dk;
+                let k_len = k.magnitude();
+                let unit = if k_len > 1e-6 { k / k_len } else { Vector2::zero() };
+                // -i k/|k| h, and i k h for the slopes
+                let dx = h.rotate() * -unit.x;
+                let dz = h.rotate() * -unit.y;
+                let sx = h.rotate() * k.x;
+                let sz = h.rotate() * k.y;
+
+                fields[index] = h + dx.rotate();
+                fields[n * n + index] = dz + sx.rotate();
+                fields[2 * n * n + index] = sz;
+            }
+        }
+        fields
+    }
+
+    // Displacement (dx, height, dz, 0) and normal textures at `time`
+    pub fn evaluate(&self, time: f32) -> (Vec<[f32; 4]>, Vec<[f32; 4]>) {
+        let n = self.params.resolution;
+        let mut fields = self.fields(time);
+        for field in fields.chunks_mut(n * n) {
+            fft_2d(field, n, true);
+        }
+        let lambda = self.params.choppiness;
+        let mut displacement = Vec::with_capacity(n * n);
+        let mut normals = Vec::with_capacity(n * n);
+        for i in 0..n * n {
+            let (f0, f1, f2) = (fields[i], fields[n * n + i], fields[2 * n * n + i]);
+            displacement.push([lambda * f0.im, f0.re, lambda * f1.re, 0.0]);
+            let normal = Vector3::new(-f1.im, 1.0, -f2.re).normalize();
+            normals.push([normal.x, normal.y, normal.z, 0.0]);
+        }
+        (displacement, normals)
+    }
+}
+
+// Mirrors `Params` in the ocean shaders
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct OceanUniform {
+    resolution: u32,
+    patch_size: f32,
+    time: f32,
+    choppiness: f32,
+}
+
+impl OceanUniform {
+    fn new(params: &OceanParams, time: f32) -> Self {
+        Self {
+            resolution: params.resolution as u32,
+            patch_size: params.patch_size,
+            time,
+            choppiness: params.choppiness,
+        }
+    }
+}
+
+// Mirrors `Stage` in ocean_fft.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FftStageUniform {
+    resolution: u32,
+    stage_size: u32,
+    // 0 --> rows, 1 --> columns
+    direction: u32,
+    _padding: u32,
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn uniform_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn compute_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::BindGroupLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::ComputePipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        module: shader,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    })
+}
+
+// Spectrum evolution, inverse FFT and texture output on the GPU
+struct OceanCompute {
+    device: wgpu::Device,
+    h0_buffer: wgpu::Buffer,
+    omega_buffer: wgpu::Buffer,
+    evolve_pipeline: wgpu::ComputePipeline,
+    evolve_bind_group: wgpu::BindGroup,
+    fft_pipeline: wgpu::ComputePipeline,
+    fft_bind_groups: Vec<wgpu::BindGroup>,
+    finalize_pipeline: wgpu::ComputePipeline,
+    finalize_bind_group: wgpu::BindGroup,
+}
+
+impl OceanCompute {
+    fn new(
+        device: &wgpu::Device,
+        simulation: &OceanSimulation,
+        uniform_buffer: &wgpu::Buffer,
+        displacement: &wgpu::TextureView,
+        normals: &wgpu::TextureView,
+    ) -> Self {
+        let n = simulation.params.resolution;
+        let h0_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ocean H0 Buffer"),
+            contents: bytemuck::cast_slice(&simulation.h0),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let omega_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ocean Omega Buffer"),
+            contents: bytemuck::cast_slice(&simulation.omega),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let field_buffers = ["Ocean Field Buffer A", "Ocean Field Buffer B"].map(|label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (FIELDS * n * n * std::mem::size_of::<Complex>()) as u64,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        });
+
+        // evolve h0 to the packed spectra at the current time
+        let evolve_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Ocean Evolve Bind Group Layout"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::COMPUTE),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, false),
+            ],
+        });
+        let evolve_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ocean Evolve Bind Group"),
+            layout: &evolve_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: h0_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: omega_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: field_buffers[0].as_entire_binding(),
+                },
+            ],
+        });
+        let evolve_pipeline = compute_pipeline(
+            device,
+            "Ocean Evolve Pipeline",
+            &evolve_layout,
+            &device.create_shader_module(wgpu::include_wgsl!("ocean_evolve.wgsl")),
+        );
+
+        // one bind group per butterfly stage, ping-ponging between the field buffers
+        let fft_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Ocean FFT Bind Group Layout"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::COMPUTE),
+                storage_entry(1, true),
+                storage_entry(2, false),
+            ],
+        });
+        let mut fft_bind_groups = Vec::new();
+        for direction in 0..2 {
+            let mut stage_size = 1;
+            while stage_size < n {
+                let src = &field_buffers[fft_bind_groups.len() % 2];
+                let dst = &field_buffers[(fft_bind_groups.len() + 1) % 2];
+                let stage = FftStageUniform {
+                    resolution: n as u32,
+                    stage_size: stage_size as u32,
+                    direction,
+                    _padding: 0,
+                };
+                let stage_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Ocean FFT Stage Buffer"),
+                    contents: bytemuck::cast_slice(&[stage]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                fft_bind_groups.push(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Ocean FFT Bind Group"),
+                    layout: &fft_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: stage_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: src.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: dst.as_entire_binding(),
+                        },
+                    ],
+                }));
+                stage_size *= 2;
+            }
+        }
+        let fft_pipeline = compute_pipeline(
+            device,
+            "Ocean FFT Pipeline",
+            &fft_layout,
+            &device.create_shader_module(wgpu::include_wgsl!("ocean_fft.wgsl")),
+        );
+
+        // an even number of stages leaves the result back in buffer A
+        let storage_texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::Rgba32Float,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let finalize_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Ocean Finalize Bind Group Layout"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::COMPUTE),
+                storage_entry(1, true),
+                storage_texture(2),
+                storage_texture(3),
+            ],
+        });
+        let finalize_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ocean Finalize Bind Group"),
+            layout: &finalize_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: field_buffers[0].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(displacement),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(normals),
+                },
+            ],
+        });
+        let finalize_pipeline = compute_pipeline(
+            device,
+            "Ocean Finalize Pipeline",
+            &finalize_layout,
+            &device.create_shader_module(wgpu::include_wgsl!("ocean_finalize.wgsl")),
+        );
+
+        Self {
+            device: device.clone(),
+            h0_buffer,
+            omega_buffer,
+            evolve_pipeline,
+            evolve_bind_group,
+            fft_pipeline,
+            fft_bind_groups,
+            finalize_pipeline,
+            finalize_bind_group,
+        }
+    }
+
+    fn dispatch(&self, queue: &wgpu::Queue, n: u32) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Ocean Encoder"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Ocean Compute Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.evolve_pipeline);
+            pass.set_bind_group(0, &self.evolve_bind_group, &[]);
+            pass.dispatch_workgroups(n.div_ceil(8), n.div_ceil(8), 1);
+
+            pass.set_pipeline(&self.fft_pipeline);
+            for bind_group in &self.fft_bind_groups {
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.dispatch_workgroups((n / 2).div_ceil(64), n, FIELDS as u32);
+            }
+
+            pass.set_pipeline(&self.finalize_pipeline);
+            pass.set_bind_group(0, &self.finalize_bind_group, &[]);
+            pass.dispatch_workgroups(n.div_ceil(8), n.div_ceil(8), 1);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+// FFT ocean surface, simulated with compute shaders when available and on the CPU otherwise
+pub struct Ocean {
+    simulation: OceanSimulation,
+    compute: Option<OceanCompute>,
+    uniform_buffer: wgpu::Buffer,
+    displacement_texture: wgpu::Texture,
+    normal_texture: wgpu::Texture,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Ocean {
+    pub fn new(
+        device: &wgpu::Device,
+        capabilities: &Capabilities,
+        mut params: OceanParams,
+    ) -> Self {
+        let gpu = capabilities.supports_compute(4, 2);
+        if !gpu {
+            params.resolution = params.resolution.min(CPU_RESOLUTION);
+        }
+        let simulation = OceanSimulation::new(params);
+        let n = params.resolution as u32;
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ocean Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[OceanUniform::new(&params, 0.0)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let usage = if gpu {
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING
+        } else {
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST
+        };
+        let create_texture = |label| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: n,
+                    height: n,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let displacement_texture = create_texture("Ocean Displacement Texture");
+        let normal_texture = create_texture("Ocean Normal Texture");
+        let displacement_view = displacement_texture.create_view(&Default::default());
+        let normal_view = normal_texture.create_view(&Default::default());
+
+        // rgba32float is not filterable everywhere, the water shader filters by hand
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Ocean Bind Group Layout"),
+            entries: &[
+                uniform_entry(
+                    0,
+                    wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ),
+                texture_entry(1),
+                texture_entry(2),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ocean Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&displacement_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_view),
+                },
+            ],
+        });
+
+        let compute = gpu.then(|| {
+            OceanCompute::new(
+                device,
+                &simulation,
+                &uniform_buffer,
+                &displacement_view,
+                &normal_view,
+            )
+        });
+        if compute.is_none() {
+            log::warn!("Compute shaders unavailable, simulating the ocean on the CPU");
+        }
+
+        Self {
+            simulation,
+            compute,
+            uniform_buffer,
+            displacement_texture,
+            normal_texture,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn params(&self) -> &OceanParams {
+        self.simulation.params()
+    }
+
+    // Regenerates the initial spectrum, the resolution stays fixed
+    pub fn set_params(&mut self, queue: &wgpu::Queue, mut params: OceanParams) {
+        params.resolution = self.simulation.params.resolution;
+        self.simulation = OceanSimulation::new(params);
+        if let Some(compute) = &self.compute {
+            queue.write_buffer(&compute.h0_buffer, 0, bytemuck::cast_slice(&self.simulation.h0));
+            queue.write_buffer(
+                &compute.omega_buffer,
+                0,
+                bytemuck::cast_slice(&self.simulation.omega),
+            );
+        }
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, time: f32) {
+        let params = self.simulation.params;
+        let uniform = OceanUniform::new(&params, time);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let n = params.resolution as u32;
+        match &self.compute {
+            Some(compute) => compute.dispatch(queue, n),
+            None => {
+                let (displacement, normals) = self.simulation.evaluate(time);
+                for (texture, data) in [
+                    (&self.displacement_texture, displacement),
+                    (&self.normal_texture, normals),
+                ] {
+                    queue.write_texture(
+                        texture.as_image_copy(),
+                        bytemuck::cast_slice(&data),
+                        wgpu::TexelCopyBufferLayout {
+                            offset: 0,
+                            bytes_per_row: Some(n * std::mem::size_of::<[f32; 4]>() as u32),
+                            rows_per_image: Some(n),
+                        },
+                        texture.size(),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Complex, b: Complex, tolerance: f32) {
+        assert!(
+            (a.re - b.re).abs() < tolerance && (a.im - b.im).abs() < tolerance,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    fn naive_dft(data: &[Complex], inverse: bool) -> Vec<Complex> {
+        let n = data.len();
+        let sign = if inverse { 1.0 } else { -1.0 };
+        (0..n)
+            .map(|k| {
+                data.iter()
+                    .enumerate()
+                    .fold(Complex::default(), |sum, (j, &x)| {
+                        let theta = sign * 2.0 * PI * (j * k % n) as f32 / n as f32;
+                        sum + x * Complex::from_angle(theta)
+                    })
+            })
+            .collect()
+    }
+
+    fn test_signal(len: usize) -> Vec<Complex> {
+        let mut random = Random(7);
+        (0..len).map(|_| random.gaussian()).collect()
+    }
+
+    #[test]
+    fn fft_matches_naive_dft() {
+        for n in [1, 2, 4, 16, 64] {
+            for inverse in [false, true] {
+                let input = test_signal(n);
+                let mut output = input.clone();
+                fft(&mut output, inverse);
+                for (a, b) in output.iter().zip(naive_dft(&input, inverse)) {
+                    assert_close(*a, b, 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fft_round_trip() {
+        let input = test_signal(32);
+        let mut data = input.clone();
+        fft(&mut data, false);
+        fft(&mut data, true);
+        for (a, b) in data.iter().zip(&input) {
+            assert_close(*a * (1.0 / 32.0), *b, 1e-5);
+        }
+    }
+
+    #[test]
+    fn fft_2d_matches_naive_dft() {
+        let n = 8;
+        let input = test_signal(n * n);
+        let mut output = input.clone();
+        fft_2d(&mut output, n, true);
+
+        // rows, then columns
+        let mut expected: Vec<Complex> = input
+            .chunks(n)
+            .flat_map(|row| naive_dft(row, true))
+            .collect();
+        for x in 0..n {
+            let column: Vec<_> = (0..n).map(|z| expected[z * n + x]).collect();
+            for (z, value) in naive_dft(&column, true).into_iter().enumerate() {
+                expected[z * n + x] = value;
+            }
+        }
+        for (a, b) in output.iter().zip(&expected) {
+            assert_close(*a, *b, 1e-3);
+        }
+    }
+
+    fn simulation() -> OceanSimulation {
+        OceanSimulation::new(OceanParams {
+            resolution: 16,
+            ..OceanParams::default()
+        })
+    }
+
+    #[test]
+    fn height_spectrum_is_hermitian() {
+        let ocean = simulation();
+        let n = ocean.params.resolution;
+        for time in [0.0, 1.3] {
+            for z in 0..n {
+                for x in 0..n {
+                    let h = ocean.height(x, z, time);
+                    let minus = ocean.height((n - x) % n, (n - z) % n, time);
+                    assert_close(h, minus.conj(), 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn heights_are_real() {
+        let ocean = simulation();
+        let n = ocean.params.resolution;
+        let mut heights: Vec<_> = (0..n * n)
+            .map(|i| ocean.height(i % n, i / n, 0.0))
+            .collect();
+        fft_2d(&mut heights, n, true);
+        let largest = heights.iter().map(|h| h.re.abs()).fold(0.0, f32::max);
+        assert!(largest > 0.0);
+        for h in &heights {
+            assert!(h.im.abs() < largest * 1e-4, "{h:?}");
+        }
+    }
+
+    // wave number of the largest energy along the wind, searched on a log scale
+    fn peak_wave_number(params: &OceanParams) -> f32 {
+        let wind = Vector2::from(params.wind_direction).normalize();
+        (0..4000)
+            .map(|i| 10f32.powf(-3.0 + i as f32 / 1000.0))
+            .max_by(|a, b| spectrum(params, wind * *a).total_cmp(&spectrum(params, wind * *b)))
+            .unwrap()
+    }
+
+    #[test]
+    fn phillips_peaks_at_the_wind_wave_number() {
+        for wind_speed in [4.0, 8.0, 16.0] {
+            let params = OceanParams {
+                spectrum: Spectrum::Phillips,
+                wind_speed,
+                ..OceanParams::default()
+            };
+            // exp(-1/(kL)^2) / k^4 is largest at k = 1 / (sqrt(2) L), L = V^2 / g
+            let expected = GRAVITY / (2f32.sqrt() * wind_speed * wind_speed);
+            let peak = peak_wave_number(&params);
+            assert!(
+                (peak / expected - 1.0).abs() < 0.02,
+                "{wind_speed}: {peak} vs {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn jonswap_peaks_near_the_wind_wave_number() {
+        for wind_speed in [4.0, 8.0, 16.0] {
+            let params = OceanParams {
+                spectrum: Spectrum::Jonswap,
+                wind_speed,
+                ..OceanParams::default()
+            };
+            // the waves at the peak frequency, the density per k is a little below it
+            let omega_p = 22.0 * (GRAVITY * GRAVITY / (wind_speed * params.fetch)).powf(1.0 / 3.0);
+            let expected = omega_p * omega_p / GRAVITY;
+            let peak = peak_wave_number(&params);
+            assert!(
+                peak < expected && peak > expected * 0.8,
+                "{wind_speed}: {peak} vs {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn waves_against_the_wind_are_suppressed() {
+        for kind in [Spectrum::Phillips, Spectrum::Jonswap] {
+            let params = OceanParams {
+                spectrum: kind,
+                ..OceanParams::default()
+            };
+            let wind = Vector2::from(params.wind_direction).normalize();
+            let k = wind * peak_wave_number(&params);
+            let along = spectrum(&params, k);
+            let against = spectrum(&params, -k);
+            let across = spectrum(&params, Vector2::new(-k.y, k.x));
+            assert!(along > 0.0);
+            assert!((against / along - UPWIND_DAMPING).abs() < 1e-4, "{kind:?}");
+            assert!(across < along * 1e-6, "{kind:?}");
+        }
+    }
+}
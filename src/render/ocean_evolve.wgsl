@@ -0,0 +1,66 @@
+// Evolves the initial ocean spectrum to the current time, see `OceanSimulation::fields`
+
+struct Params {
+    resolution: u32,
+    patch_size: f32,
+    time: f32,
+    choppiness: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> h0: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> omega: array<f32>;
+// (height + i dx), (dz + i slope_x), (slope_z) one after the other
+@group(0) @binding(3) var<storage, read_write> fields: array<vec2<f32>>;
+
+const PI: f32 = 3.14159265;
+
+fn complex_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+fn conj(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x, -a.y);
+}
+
+// multiply by i
+fn rotate(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(-a.y, a.x);
+}
+
+// signed wave index for FFT ordering
+fn wave_index(i: u32, n: u32) -> f32 {
+    if (i < n / 2u) {
+        return f32(i);
+    }
+    return f32(i) - f32(n);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let n = params.resolution;
+    if (id.x >= n || id.y >= n) {
+        return;
+    }
+    let index = id.y * n + id.x;
+    let minus = ((n - id.y) % n) * n + (n - id.x) % n;
+
+    let phase = omega[index] * params.time;
+    let e = vec2<f32>(cos(phase), sin(phase));
+    let h = complex_mul(h0[index], e) + complex_mul(conj(h0[minus]), conj(e));
+
+    let k = vec2<f32>(wave_index(id.x, n), wave_index(id.y, n)) * (2.0 * PI / params.patch_size);
+    let k_len = length(k);
+    var unit = vec2<f32>(0.0);
+    if (k_len > 1e-6) {
+        unit = k / k_len;
+    }
+    let dx = rotate(h) * -unit.x;
+    let dz = rotate(h) * -unit.y;
+    let sx = rotate(h) * k.x;
+    let sz = rotate(h) * k.y;
+
+    fields[index] = h + rotate(dx);
+    fields[n * n + index] = dz + rotate(sx);
+    fields[2u * n * n + index] = sz;
+}
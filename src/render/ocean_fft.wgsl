@@ -0,0 +1,47 @@
+// One radix-2 Stockham stage of an inverse FFT over every row or column, see `ocean::fft`
+
+struct Stage {
+    resolution: u32,
+    stage_size: u32,
+    // 0 --> rows, 1 --> columns
+    direction: u32,
+}
+
+@group(0) @binding(0) var<uniform> stage: Stage;
+@group(0) @binding(1) var<storage, read> src: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> dst: array<vec2<f32>>;
+
+const PI: f32 = 3.14159265;
+
+fn complex_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+// element `i` of line `line` in field `field`
+fn element(field: u32, line: u32, i: u32) -> u32 {
+    let n = stage.resolution;
+    if (stage.direction == 0u) {
+        return field * n * n + line * n + i;
+    }
+    return field * n * n + i * n + line;
+}
+
+// x: butterfly, y: row or column, z: field
+@compute @workgroup_size(64, 1, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let n = stage.resolution;
+    let half = n / 2u;
+    let j = id.x;
+    if (j >= half || id.y >= n) {
+        return;
+    }
+    let k = j % stage.stage_size;
+    let angle = PI * f32(k) / f32(stage.stage_size);
+    let twiddle = vec2<f32>(cos(angle), sin(angle));
+
+    let a = src[element(id.z, id.y, j)];
+    let b = complex_mul(src[element(id.z, id.y, j + half)], twiddle);
+    let out = (j - k) * 2u + k;
+    dst[element(id.z, id.y, out)] = a + b;
+    dst[element(id.z, id.y, out + stage.stage_size)] = a - b;
+}
@@ -0,0 +1,29 @@
+// Unpacks the transformed ocean fields into displacement and normal maps
+
+struct Params {
+    resolution: u32,
+    patch_size: f32,
+    time: f32,
+    choppiness: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> fields: array<vec2<f32>>;
+@group(0) @binding(2) var displacement: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var normals: texture_storage_2d<rgba32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let n = params.resolution;
+    if (id.x >= n || id.y >= n) {
+        return;
+    }
+    let index = id.y * n + id.x;
+    let f0 = fields[index];
+    let f1 = fields[n * n + index];
+    let f2 = fields[2u * n * n + index];
+
+    let lambda = params.choppiness;
+    textureStore(displacement, id.xy, vec4<f32>(lambda * f0.y, f0.x, lambda * f1.x, 0.0));
+    textureStore(normals, id.xy, vec4<f32>(normalize(vec3<f32>(-f1.y, 1.0, -f2.x)), 0.0));
+}
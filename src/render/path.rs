@@ -0,0 +1,339 @@
+use cgmath::*;
+use std::f32::consts::PI;
+
+use super::controller::CameraPose;
+
+// A recorded camera state, `time` in seconds from the first keyframe
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub pose: CameraPose,
+    pub fovy: Rad<f32>,
+}
+
+impl Keyframe {
+    fn to_array(self) -> [f32; 6] {
+        let eye = self.pose.eye;
+        [
+            eye.x,
+            eye.y,
+            eye.z,
+            self.pose.yaw.0,
+            self.pose.pitch.0,
+            self.fovy.0,
+        ]
+    }
+
+    // "x y z yaw pitch fovy" in world units and degrees, as in a saved path
+    pub fn pose_text(&self) -> String {
+        let eye = self.pose.eye;
+        format!(
+            "{} {} {} {} {} {}",
+            eye.x,
+            eye.y,
+            eye.z,
+            Deg::from(self.pose.yaw).0,
+            Deg::from(self.pose.pitch).0,
+            Deg::from(self.fovy).0,
+        )
+    }
+
+    fn from_array(time: f32, [x, y, z, yaw, pitch, fovy]: [f32; 6]) -> Self {
+        Self {
+            time,
+            pose: CameraPose {
+                eye: Point3::new(x, y, z),
+                yaw: Rad(yaw),
+                pitch: Rad(pitch),
+            },
+            fovy: Rad(fovy),
+        }
+    }
+}
+
+// Keyframes in time order, played back along a Catmull-Rom spline.
+// Saved as text, one keyframe per line in seconds, world units and degrees:
+//   time x y z yaw pitch fovy
+#[derive(Clone, Default, Debug)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+
+    // keyframes must come in time order, false and not added otherwise.
+    // The yaw is unwrapped so playback turns the short way
+    #[must_use]
+    pub fn push(&mut self, mut keyframe: Keyframe) -> bool {
+        if !keyframe.time.is_finite() {
+            return false;
+        }
+        if let Some(last) = self.keyframes.last() {
+            if keyframe.time <= last.time {
+                return false;
+            }
+            let turn = (keyframe.pose.yaw.0 - last.pose.yaw.0 + PI).rem_euclid(2.0 * PI) - PI;
+            keyframe.pose.yaw = last.pose.yaw + Rad(turn);
+        }
+        self.keyframes.push(keyframe);
+        true
+    }
+
+    // the camera at `time`, clamped to the ends of the path
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len().checked_sub(1)?;
+        if last == 0 {
+            return Some(keyframes[0]);
+        }
+        let time = time.clamp(keyframes[0].time, keyframes[last].time);
+        // the segment from keyframe i to i + 1 holds `time`
+        let i = keyframes
+            .partition_point(|keyframe| keyframe.time <= time)
+            .saturating_sub(1)
+            .min(last - 1);
+        let (start, end) = (keyframes[i], keyframes[i + 1]);
+        let h = end.time - start.time;
+        let s = (time - start.time) / h;
+        let (m0, m1) = (self.tangent(i), self.tangent(i + 1));
+        let (p0, p1) = (start.to_array(), end.to_array());
+        // cubic Hermite basis
+        let h00 = 2.0 * s * s * s - 3.0 * s * s + 1.0;
+        let h10 = s * s * s - 2.0 * s * s + s;
+        let h01 = -2.0 * s * s * s + 3.0 * s * s;
+        let h11 = s * s * s - s * s;
+        let value =
+            std::array::from_fn(|k| h00 * p0[k] + h10 * h * m0[k] + h01 * p1[k] + h11 * h * m1[k]);
+        Some(Keyframe::from_array(time, value))
+    }
+
+    // Catmull-Rom tangent for uneven spacing, the slope between the neighbours, one-sided at the ends
+    fn tangent(&self, i: usize) -> [f32; 6] {
+        let keyframes = &self.keyframes;
+        let before = keyframes[i.saturating_sub(1)];
+        let after = keyframes[(i + 1).min(keyframes.len() - 1)];
+        let (a, b) = (before.to_array(), after.to_array());
+        let dt = after.time - before.time;
+        std::array::from_fn(|k| (b[k] - a[k]) / dt)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# time x y z yaw pitch fovy\n");
+        for keyframe in &self.keyframes {
+            text += &format!("{} {}\n", keyframe.time, keyframe.pose_text());
+        }
+        text
+    }
+
+    // times are moved to start at 0, so playback begins at the first keyframe
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut path = Self::default();
+        let mut start = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values: Vec<f32> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("line {}: {e}", number + 1))?;
+            let [time, x, y, z, yaw, pitch, fovy] = values[..] else {
+                return Err(format!("line {}: expected 7 numbers", number + 1));
+            };
+            if values.iter().any(|value| !value.is_finite()) {
+                return Err(format!("line {}: expected finite numbers", number + 1));
+            }
+            let keyframe = Keyframe {
+                time: time - *start.get_or_insert(time),
+                pose: CameraPose {
+                    eye: Point3::new(x, y, z),
+                    yaw: Deg(yaw).into(),
+                    pitch: Deg(pitch).into(),
+                },
+                fovy: Deg(fovy).into(),
+            };
+            if !path.push(keyframe) {
+                return Err(format!(
+                    "line {}: time {time} is not after the previous keyframe",
+                    number + 1
+                ));
+            }
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{actual} is not {expected}"
+        );
+    }
+
+    fn keyframe(time: f32) -> Keyframe {
+        Keyframe {
+            time,
+            pose: CameraPose {
+                eye: Point3::new(time, 2.0 * time, 0.0),
+                yaw: Rad(0.0),
+                pitch: Rad(0.0),
+            },
+            fovy: Deg(45.0).into(),
+        }
+    }
+
+    #[test]
+    fn push_rejects_times_that_do_not_increase() {
+        let mut path = CameraPath::default();
+        assert!(path.push(keyframe(0.0)));
+        assert!(path.push(keyframe(1.5)));
+        assert!(!path.push(keyframe(1.5)));
+        assert!(!path.push(keyframe(1.0)));
+        assert_eq!(path.len(), 2);
+        assert_eq!(path.duration(), 1.5);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut path = CameraPath::default();
+        for time in [0.0, 0.5, 2.0] {
+            assert!(path.push(keyframe(time)));
+        }
+        let parsed = CameraPath::parse(&path.to_text()).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed.duration(), 2.0);
+        let middle = parsed.sample(0.5).unwrap();
+        assert!((middle.pose.eye.y - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn parse_names_the_out_of_order_line() {
+        let text = "# time x y z yaw pitch fovy\n\
+                    0 0 0 0 0 0 45\n\
+                    2 1 0 0 0 0 45\n\
+                    \n\
+                    2 2 0 0 0 0 45\n";
+        let error = CameraPath::parse(text).unwrap_err();
+        assert!(error.starts_with("line 5:"), "{error}");
+    }
+
+    #[test]
+    fn push_rejects_times_that_are_not_finite() {
+        let mut path = CameraPath::default();
+        assert!(!path.push(keyframe(f32::NAN)));
+        assert!(path.push(keyframe(0.0)));
+        assert!(!path.push(keyframe(f32::NAN)));
+        assert!(!path.push(keyframe(f32::INFINITY)));
+        assert_eq!(path.len(), 1);
+    }
+
+    #[test]
+    fn parse_starts_at_the_first_keyframe() {
+        let path =
+            CameraPath::parse("10 1 0 0 0 0 45\n12 3 0 0 0 0 45\n13 4 0 0 0 0 45\n").unwrap();
+        assert_eq!(path.duration(), 3.0);
+        assert_close(path.sample(0.0).unwrap().pose.eye.x, 1.0);
+        assert_close(path.sample(3.0).unwrap().pose.eye.x, 4.0);
+    }
+
+    #[test]
+    fn sample_clamps_to_the_first_keyframe() {
+        let mut path = CameraPath::default();
+        for time in [2.0, 3.0, 5.0] {
+            assert!(path.push(keyframe(time)));
+        }
+        assert_eq!(path.duration(), 3.0);
+        // before the first keyframe is the first keyframe, not extrapolated back
+        assert_close(path.sample(0.0).unwrap().pose.eye.x, 2.0);
+        assert_close(path.sample(9.0).unwrap().pose.eye.x, 5.0);
+    }
+
+    // x is the square of the time, y a sine, at uneven spacing
+    fn curve() -> CameraPath {
+        let mut path = CameraPath::default();
+        for time in [0.0, 0.7, 1.5, 2.0, 3.2] {
+            assert!(path.push(Keyframe {
+                time,
+                pose: CameraPose {
+                    eye: Point3::new(time * time, time.sin() * 4.0, 1.0),
+                    yaw: Rad(0.3 * time),
+                    pitch: Rad(0.0),
+                },
+                fovy: Deg(45.0).into(),
+            }));
+        }
+        path
+    }
+
+    #[test]
+    fn spline_passes_through_the_keyframes() {
+        let path = curve();
+        for keyframe in &path.keyframes {
+            let sample = path.sample(keyframe.time).unwrap();
+            assert_close(sample.pose.eye.x, keyframe.pose.eye.x);
+            assert_close(sample.pose.eye.y, keyframe.pose.eye.y);
+            assert_close(sample.pose.yaw.0, keyframe.pose.yaw.0);
+        }
+        // and bends between them
+        let (a, b) = (path.keyframes[1], path.keyframes[2]);
+        let middle = path.sample((a.time + b.time) / 2.0).unwrap();
+        assert!((middle.pose.eye.x - (a.pose.eye.x + b.pose.eye.x) / 2.0).abs() > 0.01);
+    }
+
+    #[test]
+    fn spline_tangents_are_continuous() {
+        let path = curve();
+        let step = 1e-3;
+        for keyframe in &path.keyframes[1..path.len() - 1] {
+            let at = |time: f32| path.sample(time).unwrap().to_array();
+            let (before, middle, after) = (
+                at(keyframe.time - step),
+                at(keyframe.time),
+                at(keyframe.time + step),
+            );
+            for k in 0..6 {
+                let left = (middle[k] - before[k]) / step;
+                let right = (after[k] - middle[k]) / step;
+                assert!(
+                    (left - right).abs() < 0.05 * (1.0 + left.abs()),
+                    "value {k} at {}: {left} then {right}",
+                    keyframe.time
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parse_names_the_malformed_line() {
+        let error = CameraPath::parse("0 0 0 0 0 0 45\n1 2 3\n").unwrap_err();
+        assert!(error.starts_with("line 2:"), "{error}");
+        let error = CameraPath::parse("0 0 0 zero 0 0 45\n").unwrap_err();
+        assert!(error.starts_with("line 1:"), "{error}");
+        let error = CameraPath::parse("0 0 0 0 0 0 45\nNaN 0 0 0 0 0 45\n").unwrap_err();
+        assert!(error.starts_with("line 2:"), "{error}");
+    }
+}
@@ -1,11 +1,15 @@
+use cgmath::InnerSpace;
+use std::rc::Rc;
 use std::u32;
 
-use super::{Camera, renderable::Renderable};
+use super::{Camera, Capabilities, Heightmap, RenderTargets, renderable::Renderable, targets};
+use crate::assets;
 use crate::context::ContextState;
+use crate::input::ActionEvent;
 use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
 use web_sys::js_sys::Math;
 use wgpu::BufferUsages;
-use winit::event::WindowEvent;
+use wgpu::util::DeviceExt;
 
 const SIZE: usize = 512;
 const SCALE: f32 = 32.0;
@@ -14,9 +18,10 @@ const LACUNARITY: f64 = 2.0;
 const PERSISTENCE: f64 = 0.5;
 const OCTAVES: usize = 6;
 const NOISE_SCALE: f64 = 10.0;
+// position, normal and baked ambient occlusion
+const VERTEX_FLOATS: usize = 7;
 
-fn generate_heightmap() -> Vec<f32> {
-    let seed = Math::random() as u32;
+fn generate_heightmap(seed: u32) -> Vec<f32> {
     let fbm: Fbm<Perlin> = Fbm::new(seed)
         .set_lacunarity(LACUNARITY)
         .set_persistence(PERSISTENCE)
@@ -37,15 +42,33 @@ fn generate_heightmap() -> Vec<f32> {
     heightmap
 }
 
-fn tessellation(height_map: &Vec<f32>) -> (Vec<f32>, Vec<u32>) {
+// Terrain heights in world units, sample (i, j) sits at x = i - SIZE / 2, z = j - SIZE / 2
+fn world_heightmap(height_map: &[f32]) -> Heightmap {
+    let heights = height_map.iter().map(|h| h * SCALE - SHIFT).collect();
+    let origin = -(SIZE as f32) / 2.0;
+    Heightmap::new(SIZE, 1.0, [origin, origin], heights)
+}
+
+fn tessellation(height_map: &Heightmap) -> (Vec<f32>, Vec<u32>) {
     // vertex generation
-    let mut vertices = Vec::with_capacity(SIZE * SIZE * 3);
+    let ([x0, z0], _) = height_map.extent();
+    let heights = height_map.heights();
+    let occlusion = height_map.horizon_occlusion();
+    let height = |i: usize, j: usize| heights[i.min(SIZE - 1) * SIZE + j.min(SIZE - 1)];
+    let mut vertices = Vec::with_capacity(SIZE * SIZE * VERTEX_FLOATS);
     for i in 0..SIZE {
         for j in 0..SIZE {
-            let h = height_map[i * SIZE + j];
-            vertices.push(-(SIZE as f32) / 2.0 + i as f32); // v.x
-            vertices.push(h * SCALE - SHIFT); // v.y
-            vertices.push(-(SIZE as f32) / 2.0 + j as f32); // v.z
+            vertices.push(x0 + i as f32); // v.x
+            vertices.push(heights[i * SIZE + j]); // v.y
+            vertices.push(z0 + j as f32); // v.z
+            // central differences, one sided at the borders
+            let (i0, i1) = (i.saturating_sub(1), i + 1);
+            let (j0, j1) = (j.saturating_sub(1), j + 1);
+            let dx = (height(i1, j) - height(i0, j)) / (i1.min(SIZE - 1) - i0) as f32;
+            let dz = (height(i, j1) - height(i, j0)) / (j1.min(SIZE - 1) - j0) as f32;
+            let normal = cgmath::Vector3::new(-dx, 1.0, -dz).normalize();
+            vertices.extend_from_slice(&[normal.x, normal.y, normal.z]); // n
+            vertices.push(occlusion[i * SIZE + j]); // ambient occlusion
         }
     }
     // indices generation
@@ -78,23 +101,132 @@ fn tessellation(height_map: &Vec<f32>) -> (Vec<f32>, Vec<u32>) {
     (vertices, indices)
 }
 
+// Mirrors `Terrain` in terrain.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct TerrainUniform {
+    water_level: f32,
+    min_height: f32,
+    max_height: f32,
+    _padding: f32,
+}
+
+// the depth only variant fills the single sampled depth buffer when the opaque stage is multisampled
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    sample_count: u32,
+    depth_only: bool,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(if depth_only {
+            "Perlin Depth Pipeline"
+        } else {
+            "Perlin Render Pipeline"
+        }),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: (VERTEX_FLOATS * std::mem::size_of::<f32>()) as u64,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &[
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 3 * std::mem::size_of::<f32>() as u64,
+                        shader_location: 1,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32,
+                        offset: 6 * std::mem::size_of::<f32>() as u64,
+                        shader_location: 2,
+                    },
+                ],
+            }],
+            compilation_options: Default::default(),
+        },
+        fragment: (!depth_only).then_some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: targets::HDR_FORMAT,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            strip_index_format: Some(wgpu::IndexFormat::Uint32),
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
 pub struct PerlinPass {
-    new_terrain: bool,
+    // the terrain of the context is in the buffers, a pass made for a new device uploads it again
+    uploaded: bool,
+    first_seed: Option<u32>,
     render_pipeline: wgpu::RenderPipeline,
+    depth_pipeline: wgpu::RenderPipeline,
+    // kept to rebuild the render pipeline when the sample count changes
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    sample_count: u32,
+    uniform: TerrainUniform,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
     terrain_index_buffer: wgpu::Buffer,
     terrain_vertex_buffer: wgpu::Buffer,
 }
 
 impl Renderable for PerlinPass {
-    fn input(&mut self, _event: &WindowEvent, _context: &ContextState) -> bool {
+    fn input(&mut self, _input: &ActionEvent, _context: &ContextState) -> bool {
         false
     }
 
     fn update(&mut self, context: &mut ContextState, queue: &wgpu::Queue) {
-        self.new_terrain = context.new_terrain;
-        if self.new_terrain {
-            let heightmap = generate_heightmap();
-            let (vertices, indices) = tessellation(&heightmap);
+        if context.new_terrain {
+            // ?seed=N picks the first terrain, to rebuild the scene of a screenshot,
+            // the others are drawn from the whole u32 range
+            let seed = self
+                .first_seed
+                .take()
+                .unwrap_or_else(|| (Math::random() * u32::MAX as f64) as u32);
+            context.seed = seed;
+            context.heightmap = Some(Rc::new(world_heightmap(&generate_heightmap(seed))));
+            self.uploaded = false;
+        }
+        context.new_terrain = false;
+        if !self.uploaded
+            && let Some(heightmap) = &context.heightmap
+        {
+            let (vertices, indices) = tessellation(heightmap);
             queue.write_buffer(
                 &self.terrain_index_buffer,
                 0,
@@ -105,11 +237,31 @@ impl Renderable for PerlinPass {
                 0,
                 bytemuck::cast_slice(&vertices),
             );
+            self.uploaded = true;
+        }
+
+        // the colormap follows the water level and the generated height range
+        if let Some(heightmap) = &context.heightmap {
+            let uniform = TerrainUniform {
+                water_level: context.water_level,
+                min_height: heightmap.min_height(),
+                max_height: heightmap.max_height(),
+                _padding: 0.0,
+            };
+            if uniform != self.uniform {
+                self.uniform = uniform;
+                queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+            }
         }
-        context.new_terrain = false;
     }
 
-    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, camera: &Camera) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        _config: &wgpu::SurfaceConfiguration,
+        camera: &Camera,
+        targets: &RenderTargets,
+        _capabilities: &Capabilities,
+    ) -> Self {
         let terrain_index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Perlin Index Buffer"),
             size: ((SIZE * (SIZE - 1) * 2 + SIZE - 2) * std::mem::size_of::<u32>()) as u64,
@@ -119,79 +271,112 @@ impl Renderable for PerlinPass {
 
         let terrain_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Perlin Vertex Buffer"),
-            size: (SIZE * SIZE * 3 * std::mem::size_of::<f32>()) as u64,
+            size: (SIZE * SIZE * VERTEX_FLOATS * std::mem::size_of::<f32>()) as u64,
             usage: BufferUsages::COPY_DST | BufferUsages::VERTEX,
             mapped_at_creation: false,
         });
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("terrain.wgsl"));
+        let uniform = TerrainUniform {
+            water_level: 0.0,
+            min_height: -SHIFT,
+            max_height: SCALE - SHIFT,
+            _padding: 0.0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Perlin Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Perlin Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Perlin Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("terrain.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("fog.wgsl"),
+                    include_str!("irradiance.wgsl"),
+                    include_str!("terrain.wgsl")
+                )
+                .into(),
+            ),
+        });
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Perlin Pipeline Layout"),
-            bind_group_layouts: &[&camera.bind_group_layout],
+            bind_group_layouts: &[&camera.bind_group_layout, &bind_group_layout],
             push_constant_ranges: &[],
         });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Perlin Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: 3 * std::mem::size_of::<f32>() as u64,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x3,
-                        offset: 0,
-                        shader_location: 0,
-                    }],
-                }],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: Some(wgpu::IndexFormat::Uint32),
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+        let render_pipeline = create_render_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            targets.sample_count,
+            false,
+        );
+        let depth_pipeline = create_render_pipeline(device, &pipeline_layout, &shader, 1, true);
         Self {
-            new_terrain: true,
+            uploaded: false,
+            first_seed: assets::query_parameter("seed").and_then(|seed| seed.parse().ok()),
             render_pipeline,
+            depth_pipeline,
+            shader,
+            pipeline_layout,
+            sample_count: targets.sample_count,
+            uniform,
+            uniform_buffer,
+            bind_group,
             terrain_index_buffer,
             terrain_vertex_buffer,
         }
     }
 
-    fn render(&mut self, pass: &mut wgpu::RenderPass, camera: &Camera) {
+    fn render(&mut self, pass: &mut wgpu::RenderPass, camera: &Camera, _targets: &RenderTargets) {
         pass.set_pipeline(&self.render_pipeline);
+        self.draw(pass, camera);
+    }
+
+    fn render_depth(&mut self, pass: &mut wgpu::RenderPass, camera: &Camera) {
+        pass.set_pipeline(&self.depth_pipeline);
+        self.draw(pass, camera);
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        if targets.sample_count != self.sample_count {
+            self.sample_count = targets.sample_count;
+            self.render_pipeline = create_render_pipeline(
+                device,
+                &self.pipeline_layout,
+                &self.shader,
+                self.sample_count,
+                false,
+            );
+        }
+    }
+}
+
+impl PerlinPass {
+    fn draw(&self, pass: &mut wgpu::RenderPass, camera: &Camera) {
         pass.set_bind_group(0, &camera.bind_group, &[]);
+        pass.set_bind_group(1, &self.bind_group, &[]);
         pass.set_vertex_buffer(0, self.terrain_vertex_buffer.slice(..));
         pass.set_index_buffer(
             self.terrain_index_buffer.slice(..),
@@ -0,0 +1,861 @@
+use crate::input::{Action, ActionEvent};
+use wgpu::util::DeviceExt;
+
+use cgmath::Vector2;
+
+use super::Camera;
+use super::antialiasing::{AntiAliasing, Fxaa, Taa};
+use super::capabilities::Capabilities;
+use super::targets::{self, RenderTargets};
+
+// half resolution and below, each level half the size of the last
+const BLOOM_LEVELS: usize = 5;
+const BLOOM_STRENGTH: f32 = 0.04;
+// exposure compensation step in stops
+const EV_STEP: f32 = 0.5;
+// luminance range the histogram covers, in stops
+const MIN_LOG_LUMINANCE: f32 = -10.0;
+const LOG_LUMINANCE_RANGE: f32 = 12.0;
+// per second, higher adapts to brightness changes faster
+const ADAPTATION_SPEED: f32 = 1.5;
+const HISTOGRAM_BINS: u64 = 256;
+// byte offset of `average_luminance` in `TonemapUniform`, written by the exposure pass
+const AVERAGE_LUMINANCE_OFFSET: u64 = 28;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tonemapper {
+    Aces,
+    AgX,
+    Reinhard,
+}
+
+impl Tonemapper {
+    fn next(self) -> Self {
+        match self {
+            Tonemapper::Aces => Tonemapper::AgX,
+            Tonemapper::AgX => Tonemapper::Reinhard,
+            Tonemapper::Reinhard => Tonemapper::Aces,
+        }
+    }
+}
+
+// Mirrors `Tonemap` in tonemap.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+    exposure: f32,
+    tonemapper: u32,
+    auto_exposure: u32,
+    bloom_strength: f32,
+    encode_srgb: u32,
+    _padding: [u32; 2],
+    average_luminance: f32,
+}
+
+// Mirrors `Exposure` in exposure.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureUniform {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,
+    pixel_count: u32,
+}
+
+pub(super) fn texture_entry(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn buffer_entry(binding: u32, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+pub(super) fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+pub(super) fn fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    shader: &wgpu::ShaderModule,
+    fragment_entry: &str,
+    layout: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+) -> wgpu::RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment_entry),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+pub(super) fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    view: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+// Downsample chain over separate textures, so no pass reads the texture it writes to
+struct Bloom {
+    prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    views: Vec<wgpu::TextureView>,
+    // the scene first, then each level as the source of the next pass
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl Bloom {
+    fn new(device: &wgpu::Device, targets: &RenderTargets, scene: &wgpu::TextureView) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::ShaderStages::FRAGMENT),
+                sampler_entry(1),
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("bloom.wgsl"));
+        let create_pipeline = |label: &str, entry: &str, blend: Option<wgpu::BlendState>| {
+            fullscreen_pipeline(
+                device,
+                label,
+                &shader,
+                entry,
+                &bind_group_layout,
+                targets::HDR_FORMAT,
+                blend,
+            )
+        };
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let prefilter_pipeline = create_pipeline("Bloom Prefilter Pipeline", "fs_prefilter", None);
+        let downsample_pipeline =
+            create_pipeline("Bloom Downsample Pipeline", "fs_downsample", None);
+        let upsample_pipeline = create_pipeline(
+            "Bloom Upsample Pipeline",
+            "fs_upsample",
+            Some(wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            }),
+        );
+
+        let mut bloom = Self {
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            bind_group_layout,
+            sampler,
+            views: Vec::new(),
+            bind_groups: Vec::new(),
+        };
+        bloom.resize(device, targets, scene);
+        bloom
+    }
+
+    fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        targets: &RenderTargets,
+        scene: &wgpu::TextureView,
+    ) {
+        self.views = (0..BLOOM_LEVELS)
+            .map(|level| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Bloom Texture"),
+                    size: wgpu::Extent3d {
+                        width: (targets.width >> (level + 1)).max(1),
+                        height: (targets.height >> (level + 1)).max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: targets::HDR_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                });
+                texture.create_view(&wgpu::TextureViewDescriptor::default())
+            })
+            .collect();
+        self.bind_groups = std::iter::once(scene)
+            .chain(&self.views)
+            .map(|view| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Bloom Bind Group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                })
+            })
+            .collect();
+    }
+
+    // leaves the blurred result in the first level
+    fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        fullscreen_pass(
+            encoder,
+            "Bloom Prefilter Pass",
+            &self.views[0],
+            clear,
+            &self.prefilter_pipeline,
+            &self.bind_groups[0],
+        );
+        for level in 1..BLOOM_LEVELS {
+            fullscreen_pass(
+                encoder,
+                "Bloom Downsample Pass",
+                &self.views[level],
+                clear,
+                &self.downsample_pipeline,
+                &self.bind_groups[level],
+            );
+        }
+        for level in (1..BLOOM_LEVELS).rev() {
+            fullscreen_pass(
+                encoder,
+                "Bloom Upsample Pass",
+                &self.views[level - 1],
+                wgpu::LoadOp::Load,
+                &self.upsample_pipeline,
+                &self.bind_groups[level + 1],
+            );
+        }
+    }
+}
+
+// Histogram metering in compute, only on backends that have it
+struct AutoExposure {
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    histogram_buffer: wgpu::Buffer,
+    luminance_buffer: wgpu::Buffer,
+}
+
+impl AutoExposure {
+    fn supported(capabilities: &Capabilities) -> bool {
+        capabilities.supports_compute(2, 0)
+            && capabilities.limits.max_compute_invocations_per_workgroup >= HISTOGRAM_BINS as u32
+    }
+
+    fn new(device: &wgpu::Device, scene: &wgpu::TextureView) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Exposure Uniform Buffer"),
+            size: std::mem::size_of::<ExposureUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Exposure Histogram Buffer"),
+            size: HISTOGRAM_BINS * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // starts at 0 so the first frame is metered without adapting
+        let luminance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Exposure Luminance Buffer"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage = wgpu::BufferBindingType::Storage { read_only: false };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Exposure Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                buffer_entry(1, storage),
+                buffer_entry(2, storage),
+                buffer_entry(3, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("exposure.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Exposure Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label: &str, entry: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let histogram_pipeline = create_pipeline("Exposure Histogram Pipeline", "build_histogram");
+        let average_pipeline = create_pipeline("Exposure Average Pipeline", "average");
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            scene,
+            [&histogram_buffer, &luminance_buffer, &uniform_buffer],
+        );
+
+        Self {
+            histogram_pipeline,
+            average_pipeline,
+            bind_group_layout,
+            bind_group,
+            uniform_buffer,
+            histogram_buffer,
+            luminance_buffer,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        scene: &wgpu::TextureView,
+        buffers: [&wgpu::Buffer; 3],
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(scene),
+        }];
+        entries.extend(
+            buffers
+                .iter()
+                .zip(1..)
+                .map(|(buffer, binding)| wgpu::BindGroupEntry {
+                    binding,
+                    resource: buffer.as_entire_binding(),
+                }),
+        );
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Exposure Bind Group"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, scene: &wgpu::TextureView) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            scene,
+            [
+                &self.histogram_buffer,
+                &self.luminance_buffer,
+                &self.uniform_buffer,
+            ],
+        );
+    }
+
+    fn update(&self, queue: &wgpu::Queue, dt: f32, targets: &RenderTargets) {
+        let uniform = ExposureUniform {
+            min_log_luminance: MIN_LOG_LUMINANCE,
+            log_luminance_range: LOG_LUMINANCE_RANGE,
+            adaptation: 1.0 - (-dt * ADAPTATION_SPEED).exp(),
+            pixel_count: targets.width * targets.height,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // meters the scene and copies the adapted luminance into the tone mapping uniform
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargets,
+        tonemap_buffer: &wgpu::Buffer,
+    ) {
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Exposure Compute Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.set_pipeline(&self.histogram_pipeline);
+            pass.dispatch_workgroups(targets.width.div_ceil(16), targets.height.div_ceil(16), 1);
+            pass.set_pipeline(&self.average_pipeline);
+            pass.dispatch_workgroups(1, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.luminance_buffer,
+            0,
+            tonemap_buffer,
+            AVERAGE_LUMINANCE_OFFSET,
+            4,
+        );
+    }
+}
+
+// Anti-aliasing, bloom, exposure and tone mapping from the HDR scene target to the surface
+pub struct PostProcess {
+    shader: wgpu::ShaderModule,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    bloom: Bloom,
+    auto_exposure: Option<AutoExposure>,
+    fxaa: Option<Fxaa>,
+    taa: Option<Taa>,
+    tonemapper: Tonemapper,
+    // exposure compensation in stops
+    exposure_value: f32,
+    metering: bool,
+    bloom_enabled: bool,
+    surface_format: wgpu::TextureFormat,
+}
+
+impl PostProcess {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &RenderTargets,
+        antialiasing: AntiAliasing,
+        capabilities: &Capabilities,
+    ) -> Self {
+        let metered = AutoExposure::supported(capabilities);
+        if !metered {
+            log::info!("Compute shaders unavailable, exposure is manual only");
+        }
+        Self::create(device, config, targets, antialiasing, metered)
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &RenderTargets,
+        antialiasing: AntiAliasing,
+        metered: bool,
+    ) -> Self {
+        let fxaa = (antialiasing == AntiAliasing::Fxaa).then(|| Fxaa::new(device, config, targets));
+        let taa = (antialiasing == AntiAliasing::Taa).then(|| Taa::new(device, targets));
+        let scene = Self::scene_view(targets, taa.as_ref());
+        let auto_exposure = metered.then(|| AutoExposure::new(device, scene));
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tonemap Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[TonemapUniform {
+                exposure: 1.0,
+                tonemapper: Tonemapper::Aces as u32,
+                auto_exposure: 0,
+                bloom_strength: BLOOM_STRENGTH,
+                encode_srgb: 0,
+                _padding: Default::default(),
+                average_luminance: 0.18,
+            }]),
+            // the source of `copy_exposure`
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Tonemap Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tonemap Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::ShaderStages::FRAGMENT),
+                texture_entry(1, wgpu::ShaderStages::FRAGMENT),
+                sampler_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("tonemap.wgsl"));
+        let pipeline = Self::create_pipeline(
+            device,
+            &shader,
+            &bind_group_layout,
+            Self::output_format(config.format, fxaa.as_ref()),
+        );
+        let bloom = Bloom::new(device, targets, scene);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            scene,
+            &bloom,
+            &sampler,
+            &uniform_buffer,
+        );
+
+        Self {
+            shader,
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            sampler,
+            uniform_buffer,
+            metering: auto_exposure.is_some(),
+            bloom,
+            auto_exposure,
+            fxaa,
+            taa,
+            tonemapper: Tonemapper::Aces,
+            exposure_value: 0.0,
+            bloom_enabled: true,
+            surface_format: config.format,
+        }
+    }
+
+    // The same tone mapping for other targets, for a screenshot. Without anti-aliasing or
+    // metering of its own, `copy_exposure` hands it the exposure this one adapted to
+    pub fn still(
+        &self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &RenderTargets,
+    ) -> Self {
+        Self {
+            tonemapper: self.tonemapper,
+            exposure_value: self.exposure_value,
+            metering: self.metering,
+            bloom_enabled: self.bloom_enabled,
+            ..Self::create(device, config, targets, AntiAliasing::Off, false)
+        }
+    }
+
+    // after `render`, so this frame's metering is in the uniform
+    pub fn copy_exposure(&self, encoder: &mut wgpu::CommandEncoder, still: &PostProcess) {
+        if self.auto_exposure.is_some() {
+            encoder.copy_buffer_to_buffer(
+                &self.uniform_buffer,
+                AVERAGE_LUMINANCE_OFFSET,
+                &still.uniform_buffer,
+                AVERAGE_LUMINANCE_OFFSET,
+                4,
+            );
+        }
+    }
+
+    // the resolved TAA output when temporal anti-aliasing is on, the scene target otherwise
+    fn scene_view<'a>(targets: &'a RenderTargets, taa: Option<&'a Taa>) -> &'a wgpu::TextureView {
+        match taa {
+            Some(taa) => &taa.output_view,
+            None => &targets.hdr_view,
+        }
+    }
+
+    // tone mapping writes to the FXAA input when FXAA is on, the surface otherwise
+    fn output_format(
+        surface_format: wgpu::TextureFormat,
+        fxaa: Option<&Fxaa>,
+    ) -> wgpu::TextureFormat {
+        match fxaa {
+            Some(_) => Fxaa::FORMAT,
+            None => surface_format,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        fullscreen_pipeline(
+            device,
+            "Tonemap Pipeline",
+            shader,
+            "fs_main",
+            layout,
+            format,
+            None,
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        scene: &wgpu::TextureView,
+        bloom: &Bloom,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tonemap Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(scene),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&bloom.views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    // called after the render targets are recreated
+    pub fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        if let Some(fxaa) = &mut self.fxaa {
+            fxaa.resize(device, targets);
+        }
+        if let Some(taa) = &mut self.taa {
+            taa.resize(device, targets);
+        }
+        let scene = Self::scene_view(targets, self.taa.as_ref());
+        self.bloom.resize(device, targets, scene);
+        if let Some(auto_exposure) = &mut self.auto_exposure {
+            auto_exposure.resize(device, scene);
+        }
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            scene,
+            &self.bloom,
+            &self.sampler,
+            &self.uniform_buffer,
+        );
+    }
+
+    // rebuilds the anti-aliasing passes and the tone mapping pipeline for the new mode
+    pub fn set_antialiasing(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &RenderTargets,
+        antialiasing: AntiAliasing,
+    ) {
+        self.fxaa =
+            (antialiasing == AntiAliasing::Fxaa).then(|| Fxaa::new(device, config, targets));
+        self.taa = (antialiasing == AntiAliasing::Taa).then(|| Taa::new(device, targets));
+        self.pipeline = Self::create_pipeline(
+            device,
+            &self.shader,
+            &self.bind_group_layout,
+            Self::output_format(self.surface_format, self.fxaa.as_ref()),
+        );
+        self.resize(device, targets);
+    }
+
+    // subpixel camera offset for the next frame, zero unless TAA is on
+    pub fn jitter(&mut self, targets: &RenderTargets) -> Vector2<f32> {
+        match &mut self.taa {
+            Some(taa) => taa.next_jitter(targets),
+            None => Vector2::new(0.0, 0.0),
+        }
+    }
+
+    pub fn input(&mut self, input: &ActionEvent) -> bool {
+        if !input.pressed {
+            return false;
+        }
+        // exposure steps are half a stop
+        match input.action {
+            Action::Tonemapper => {
+                self.tonemapper = self.tonemapper.next();
+                log::info!("Tone mapping: {:?}", self.tonemapper);
+            }
+            Action::AutoExposure => {
+                if self.auto_exposure.is_some() {
+                    self.metering = !self.metering;
+                    log::info!("Automatic exposure: {}", self.metering);
+                } else {
+                    log::info!("Automatic exposure needs compute shaders");
+                }
+            }
+            Action::DarkerExposure | Action::BrighterExposure => {
+                self.exposure_value += if input.action == Action::BrighterExposure {
+                    EV_STEP
+                } else {
+                    -EV_STEP
+                };
+                log::info!("Exposure: {:+.1} EV", self.exposure_value);
+            }
+            Action::Bloom => {
+                self.bloom_enabled = !self.bloom_enabled;
+                log::info!("Bloom: {}", self.bloom_enabled);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        dt: f32,
+        targets: &RenderTargets,
+        camera: &Camera,
+    ) {
+        let uniform = TonemapUniform {
+            exposure: self.exposure_value.exp2(),
+            tonemapper: self.tonemapper as u32,
+            auto_exposure: self.metering as u32,
+            bloom_strength: if self.bloom_enabled {
+                BLOOM_STRENGTH
+            } else {
+                0.0
+            },
+            // the FXAA input is plain unorm, like a non sRGB surface
+            encode_srgb: (self.fxaa.is_some() || !self.surface_format.is_srgb()) as u32,
+            _padding: Default::default(),
+            average_luminance: 0.0,
+        };
+        // the average luminance is left to the exposure pass
+        let bytes = bytemuck::bytes_of(&uniform);
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            &bytes[..AVERAGE_LUMINANCE_OFFSET as usize],
+        );
+        if let Some(auto_exposure) = &self.auto_exposure {
+            auto_exposure.update(queue, dt, targets);
+        }
+        if let Some(taa) = &mut self.taa {
+            taa.update(queue, camera);
+        }
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, targets: &RenderTargets) {
+        if let Some(taa) = &self.taa {
+            taa.render(encoder);
+        }
+        if self.bloom_enabled {
+            self.bloom.render(encoder);
+        }
+        if let Some(auto_exposure) = &self.auto_exposure {
+            // keep metering while off so switching it on doesn't start from a stale value
+            auto_exposure.render(encoder, targets, &self.uniform_buffer);
+        }
+    }
+
+    // tone maps into `view` after `render`, without changing any state so it may run more than once
+    pub fn present(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        fullscreen_pass(
+            encoder,
+            "Tonemap Render Pass",
+            self.fxaa.as_ref().map_or(view, |fxaa| &fxaa.ldr_view),
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &self.pipeline,
+            &self.bind_group,
+        );
+        if let Some(fxaa) = &self.fxaa {
+            fxaa.render(encoder, view);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+// rows of a texture to buffer copy are padded to this
+const ROW_ALIGNMENT: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+type MapResult = Rc<Cell<Option<Result<(), wgpu::BufferAsyncError>>>>;
+
+// The final image rendered a second time into a texture the CPU can read back,
+// for frame captures and screenshots. WebGL may finish the copy a few frames later
+pub struct FrameReadback {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    padded_row: u32,
+    // set once `start` maps the buffer, filled by the map callback
+    pending: Option<MapResult>,
+}
+
+impl FrameReadback {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Readback Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let padded_row = (width * 4).div_ceil(ROW_ALIGNMENT) * ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Self {
+            texture,
+            view,
+            buffer,
+            format,
+            padded_row,
+            pending: None,
+        }
+    }
+
+    // whether this can read a surface of the given format and size
+    pub fn fits(&self, format: wgpu::TextureFormat, width: u32, height: u32) -> bool {
+        self.format == format && self.texture.width() == width && self.texture.height() == height
+    }
+
+    // a read is in flight, no new copy may start
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    // after rendering into `view`
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_row),
+                    rows_per_image: None,
+                },
+            },
+            self.texture.size(),
+        );
+    }
+
+    // after the copy was submitted
+    pub fn start(&mut self) {
+        let result: MapResult = Rc::default();
+        let callback = result.clone();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |mapped| {
+                callback.set(Some(mapped))
+            });
+        self.pending = Some(result);
+    }
+
+    // the copied image once the GPU is done with it, None while it is still busy or failed
+    pub fn try_read(&mut self, device: &wgpu::Device) -> Option<image::RgbaImage> {
+        let pending = self.pending.as_ref()?;
+        let _ = device.poll(wgpu::Maintain::Wait);
+        let mapped = pending.take()?;
+        self.pending = None;
+        if let Err(e) = mapped {
+            log::warn!("Failed to read the frame back: {e}");
+            return None;
+        }
+        let (width, height) = (self.texture.width(), self.texture.height());
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for row in data.chunks_exact(self.padded_row as usize) {
+                pixels.extend_from_slice(&row[..(width * 4) as usize]);
+            }
+        }
+        self.buffer.unmap();
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        image::RgbaImage::from_raw(width, height, pixels)
+    }
+}
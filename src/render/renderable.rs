@@ -1,20 +1,54 @@
 use crate::context::ContextState;
-use winit::event::WindowEvent;
+use crate::input::ActionEvent;
 
-use super::Camera;
+use super::{Camera, Capabilities, RenderTargets};
+
+// Opaque passes write depth; transparent passes run afterwards and may sample it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderStage {
+    Opaque,
+    Transparent,
+}
 
 pub trait Renderable {
+    // `targets` are the ones being drawn into, for passes that read the scene depth
     fn render(
         &mut self,
         pass: &mut wgpu::RenderPass,
-        camera: &Camera
+        camera: &Camera,
+        targets: &RenderTargets,
     );
 
-    fn input(&mut self, event: &WindowEvent, context: &ContextState) -> bool;
+    fn input(&mut self, input: &ActionEvent, context: &ContextState) -> bool;
 
     fn update(&mut self, context: &mut ContextState, queue: &wgpu::Queue);
 
-    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, camera: &Camera) -> Self
+    fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera: &Camera,
+        targets: &RenderTargets,
+        capabilities: &Capabilities,
+    ) -> Self
     where
         Self: Sized;
+
+    // depth only prepass for opaque passes, runs when the opaque stage is multisampled
+    fn render_depth(&mut self, _pass: &mut wgpu::RenderPass, _camera: &Camera) {}
+
+    // called between the opaque and transparent stages, for passes with their own targets
+    fn render_offscreen(
+        &mut self,
+        _encoder: &mut wgpu::CommandEncoder,
+        _camera: &Camera,
+        _targets: &RenderTargets,
+    ) {
+    }
+
+    // called after the render targets are recreated
+    fn resize(&mut self, _device: &wgpu::Device, _targets: &RenderTargets) {}
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::Opaque
+    }
 }
@@ -0,0 +1,104 @@
+// range of the render scale
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+// dynamic resolution changes the scale in steps of this
+const SCALE_STEP: f32 = 0.05;
+// weight of the newest frame in the smoothed frame time
+const FRAME_TIME_SMOOTHING: f32 = 0.1;
+// the smoothed frame time is over budget above this share of the target, and within it below the other
+const OVER_BUDGET: f32 = 1.1;
+const WITHIN_BUDGET: f32 = 1.02;
+// seconds since the last change before shrinking or growing again, growing waits longer so the
+// scale settles instead of bouncing between two steps
+const SHRINK_INTERVAL: f32 = 0.5;
+const GROW_INTERVAL: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResolutionSettings {
+    // scene pixels per surface pixel along each axis
+    pub render_scale: f32,
+    // the scene never renders larger than this, keeping its aspect
+    pub max_resolution: Option<(u32, u32)>,
+    // seconds per frame dynamic resolution aims for, None keeps the scale fixed
+    pub target_frame_time: Option<f32>,
+}
+
+impl Default for ResolutionSettings {
+    fn default() -> Self {
+        Self {
+            render_scale: 1.0,
+            max_resolution: None,
+            target_frame_time: None,
+        }
+    }
+}
+
+// Size of the scene targets relative to the surface. The scene renders at this size and the
+// tone mapping pass scales it to the surface. With a target frame time the scale drops
+// while frames take too long and grows back up to `render_scale` once they are fast again
+pub struct Resolution {
+    settings: ResolutionSettings,
+    scale: f32,
+    // smoothed seconds per frame
+    frame_time: Option<f32>,
+    since_change: f32,
+}
+
+impl Resolution {
+    pub fn new(mut settings: ResolutionSettings) -> Self {
+        settings.render_scale = settings
+            .render_scale
+            .clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        Self {
+            settings,
+            scale: settings.render_scale,
+            frame_time: None,
+            since_change: 0.0,
+        }
+    }
+
+    pub fn frame_time(&self) -> Option<f32> {
+        self.frame_time
+    }
+
+    // the scene size for a surface, no side larger than `largest`
+    pub fn render_size(&self, width: u32, height: u32, largest: u32) -> (u32, u32) {
+        let (w, h) = (width.max(1) as f32, height.max(1) as f32);
+        let mut scale = self.scale;
+        if let Some((max_width, max_height)) = self.settings.max_resolution {
+            scale = scale.min(max_width as f32 / w).min(max_height as f32 / h);
+        }
+        scale = scale.min(largest as f32 / w.max(h));
+        let size = |side: f32| ((side * scale).round() as u32).clamp(1, largest);
+        (size(w), size(h))
+    }
+
+    // called every frame with its duration, true when the scale changed
+    pub fn update(&mut self, dt: f32) -> bool {
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time + (dt - frame_time) * FRAME_TIME_SMOOTHING,
+            None => dt,
+        };
+        self.frame_time = Some(frame_time);
+        let Some(target) = self.settings.target_frame_time else {
+            return false;
+        };
+        self.since_change += dt;
+        let step = if frame_time > target * OVER_BUDGET && self.since_change >= SHRINK_INTERVAL {
+            -SCALE_STEP
+        } else if frame_time <= target * WITHIN_BUDGET && self.since_change >= GROW_INTERVAL {
+            SCALE_STEP
+        } else {
+            return false;
+        };
+        let scale = ((self.scale + step) / SCALE_STEP).round() * SCALE_STEP;
+        let scale = scale.clamp(MIN_RENDER_SCALE, self.settings.render_scale);
+        if scale == self.scale {
+            return false;
+        }
+        self.scale = scale;
+        self.since_change = 0.0;
+        log::info!("Dynamic resolution scale: {:.2}", scale);
+        true
+    }
+}
@@ -1,6 +1,20 @@
 use wgpu::util::DeviceExt;
 
-use super::Renderable;
+use super::atmosphere::{self, Fog};
+use super::{Capabilities, RenderTargets, Renderable, targets};
+use crate::context::ContextState;
+use crate::input::{Action, ActionEvent};
+
+// hours past midnight at startup
+const START_TIME_OF_DAY: f32 = 10.0;
+// hours per key press
+const TIME_STEP: f32 = 0.5;
+// real seconds for a full day when the cycle runs
+const DAY_LENGTH: f32 = 240.0;
+// fog density factor per key press
+const FOG_STEP: f32 = 1.5;
+// colour of the fog when it does not follow the sky
+const FOG_COLOR: [f32; 3] = [0.5, 0.55, 0.6];
 
 #[rustfmt::skip]
 const SKYBOX_VERTICES: &[[f32; 3]] = &[
@@ -29,28 +43,137 @@ const SKYBOX_VERTICES: &[[f32; 3]] = &[
     [-1.0,  1.0, -1.0], [-1.0, -1.0, -1.0], [ 1.0, -1.0, -1.0],
 ];
 
+fn create_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    label: &str,
+    fragment_entry: &str,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &[wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                }],
+            }],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment_entry),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: targets::HDR_FORMAT,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: false, // No depth written
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
 
 // Total vertices: 36 (12 triangles)
 pub struct SkyPass {
+    // procedural sky, used until an environment map is loaded
     pipeline: wgpu::RenderPipeline,
+    cubemap_pipeline: wgpu::RenderPipeline,
+    // kept to rebuild the pipelines when the sample count changes
+    shader: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    sample_count: u32,
     vertex_buffer: wgpu::Buffer,
+    // an environment map is loaded, it lives in the camera bind group
+    has_environment: bool,
+    // hours past midnight
+    time_of_day: f32,
+    day_cycle: bool,
+    last_time: f32,
+    fog: Fog,
 }
 
 impl Renderable for SkyPass {
-    fn input(
-        &mut self,
-        _event: &winit::event::WindowEvent,
-        _context: &crate::context::ContextState,
-    ) -> bool {
-        false
+    fn input(&mut self, input: &ActionEvent, _context: &ContextState) -> bool {
+        if !input.pressed {
+            return false;
+        }
+        // the fog colour switches between the sky and a fixed grey
+        match input.action {
+            Action::EarlierTime => self.time_of_day -= TIME_STEP,
+            Action::LaterTime => self.time_of_day += TIME_STEP,
+            Action::DayCycle => self.day_cycle = !self.day_cycle,
+            Action::ThinnerFog | Action::ThickerFog => {
+                let factor = if input.action == Action::ThickerFog {
+                    FOG_STEP
+                } else {
+                    1.0 / FOG_STEP
+                };
+                self.fog.density *= factor;
+                log::info!("Fog density: {}", self.fog.density);
+                return true;
+            }
+            Action::FogColor => {
+                self.fog.color = match self.fog.color {
+                    Some(_) => None,
+                    None => Some(FOG_COLOR.into()),
+                };
+                return true;
+            }
+            _ => return false,
+        }
+        self.time_of_day = self.time_of_day.rem_euclid(24.0);
+        log::info!("Time of day: {:.1}h", self.time_of_day);
+        true
     }
 
-    fn update(&mut self, _context: &mut crate::context::ContextState, _queue: &wgpu::Queue) {}
+    fn update(&mut self, context: &mut ContextState, _queue: &wgpu::Queue) {
+        self.has_environment = context.environment.is_some();
+        if self.day_cycle {
+            let dt = context.time - self.last_time;
+            self.time_of_day = (self.time_of_day + dt / DAY_LENGTH * 24.0).rem_euclid(24.0);
+        }
+        self.last_time = context.time;
+        context.lighting = atmosphere::lighting(atmosphere::sun_direction(self.time_of_day));
+        context.fog = self.fog;
+    }
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        _config: &wgpu::SurfaceConfiguration,
         camera: &super::Camera,
+        targets: &RenderTargets,
+        _capabilities: &Capabilities,
     ) -> Self {
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Skybox Vertex Buffer"),
@@ -58,74 +181,87 @@ impl Renderable for SkyPass {
             usage: wgpu::BufferUsages::VERTEX,
         });
 
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("skybox.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("fog.wgsl"), include_str!("skybox.wgsl")).into(),
+            ),
+        });
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Skybox Pipeline Layout"),
             bind_group_layouts: &[&camera.bind_group_layout],
             push_constant_ranges: &[],
         });
-
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Skybox Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &device.create_shader_module(wgpu::include_wgsl!("skybox.wgsl")),
-                entry_point: Some("vs_main"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x3,
-                        offset: 0,
-                        shader_location: 0,
-                    }],
-                }],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &device.create_shader_module(wgpu::include_wgsl!("skybox.wgsl")),
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: false, // No depth written
-                depth_compare: wgpu::CompareFunction::LessEqual,
-                stencil: Default::default(),
-                bias: Default::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+        let (pipeline, cubemap_pipeline) =
+            Self::create_pipelines(device, &shader, &pipeline_layout, targets.sample_count);
 
         Self {
             pipeline,
+            cubemap_pipeline,
+            shader,
+            pipeline_layout,
+            sample_count: targets.sample_count,
             vertex_buffer,
+            has_environment: false,
+            time_of_day: START_TIME_OF_DAY,
+            day_cycle: false,
+            last_time: 0.0,
+            fog: Fog::default(),
         }
     }
 
-    fn render(&mut self, pass: &mut wgpu::RenderPass, camera: &super::Camera) {
-        pass.set_pipeline(&self.pipeline);
+    fn render(
+        &mut self,
+        pass: &mut wgpu::RenderPass,
+        camera: &super::Camera,
+        _targets: &RenderTargets,
+    ) {
+        if self.has_environment {
+            pass.set_pipeline(&self.cubemap_pipeline);
+        } else {
+            pass.set_pipeline(&self.pipeline);
+        }
         pass.set_bind_group(0, &camera.bind_group, &[]);
         pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         pass.draw(0..36, 0..1);
     }
+
+    fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        if targets.sample_count != self.sample_count {
+            self.sample_count = targets.sample_count;
+            (self.pipeline, self.cubemap_pipeline) = Self::create_pipelines(
+                device,
+                &self.shader,
+                &self.pipeline_layout,
+                self.sample_count,
+            );
+        }
+    }
+}
+
+impl SkyPass {
+    fn create_pipelines(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        sample_count: u32,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let pipeline = create_pipeline(
+            device,
+            shader,
+            layout,
+            "Skybox Render Pipeline",
+            "fs_main",
+            sample_count,
+        );
+        let cubemap_pipeline = create_pipeline(
+            device,
+            shader,
+            layout,
+            "Skybox Cubemap Render Pipeline",
+            "fs_cubemap",
+            sample_count,
+        );
+        (pipeline, cubemap_pipeline)
+    }
 }
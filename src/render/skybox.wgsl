@@ -2,6 +2,12 @@ struct Camera {
     view_pos: vec4<f32>,
     view_proj: mat4x4<f32>,
     time: f32,
+    inv_view_proj: mat4x4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    fog_color: vec4<f32>,
+    fog_params: vec4<f32>,
 }
 @group(0) @binding(0)
 var<uniform> camera: Camera;
@@ -28,12 +34,158 @@ fn vs_main(@location(0) in_pos: vec3<f32>) -> VertexOutput {
     return out;
 }
 
-// @group(0) @binding(1) var skybox_texture: texture_cube<f32>;
-// @group(0) @binding(2) var skybox_sampler: sampler;
+// environment map, see environment.rs and ibl.rs
+@group(0) @binding(1) var skybox_texture: texture_cube<f32>;
+@group(0) @binding(2) var environment_sampler: sampler;
+@group(0) @binding(4) var specular_texture: texture_cube<f32>;
+
+// `height_fog` is prepended from fog.wgsl, the sky is fogged as if this far away
+const FOG_SKY_DISTANCE: f32 = 100000.0;
+
+// ===================
+// ATMOSPHERE, see atmosphere.rs
+// ===================
+
+const PI: f32 = 3.14159265;
+const TURBIDITY: f32 = 2.5;
+const SKY_EXPOSURE: f32 = 0.05;
+const NIGHT_SKY: vec3<f32> = vec3<f32>(0.004, 0.006, 0.015);
+// cosine of the apparent sun radius, a little larger than the real sun
+const SUN_DISC: f32 = 0.9995;
+const SUN_INTENSITY: f32 = 20.0;
+
+fn perez(theta: f32, gamma: f32, a: f32, b: f32, c: f32, d: f32, e: f32) -> f32 {
+    let cos_gamma = cos(gamma);
+    return (1.0 + a * exp(b / cos(theta))) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> vec3<f32> {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    return vec3<f32>(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    );
+}
+
+// Preetham et al. 1999 "A Practical Analytic Model for Daylight"
+fn preetham(direction: vec3<f32>, sun_in: vec3<f32>) -> vec3<f32> {
+    let t = TURBIDITY;
+    let sun = normalize(vec3<f32>(sun_in.x, max(sun_in.y, 0.02), sun_in.z));
+    let theta_s = acos(sun.y);
+    let theta = acos(max(direction.y, 0.001));
+    let gamma = acos(clamp(dot(direction, sun), -1.0, 1.0));
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_y = (4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192;
+    let t1 = theta_s;
+    let t2 = theta_s * theta_s;
+    let t3 = t2 * theta_s;
+    let zenith_x = t * t * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * t1)
+        + t * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * t1 + 0.00394)
+        + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * t1 + 0.25886);
+    let zenith_yc = t * t * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * t1)
+        + t * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * t1 + 0.00516)
+        + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * t1 + 0.26688);
+
+    let ya = 0.1787 * t - 1.4630;
+    let yb = -0.3554 * t + 0.4275;
+    let yc = -0.0227 * t + 5.3251;
+    let yd = 0.1206 * t - 2.5771;
+    let ye = -0.0670 * t + 0.3703;
+    let xa = -0.0193 * t - 0.2592;
+    let xb = -0.0665 * t + 0.0008;
+    let xc = -0.0004 * t + 0.2125;
+    let xd = -0.0641 * t - 0.8989;
+    let xe = -0.0033 * t + 0.0452;
+    let ca = -0.0167 * t - 0.2608;
+    let cb = -0.0950 * t + 0.0092;
+    let cc = -0.0079 * t + 0.2102;
+    let cd = -0.0441 * t - 1.6537;
+    let ce = -0.0109 * t + 0.0529;
+
+    let luminance = zenith_y * perez(theta, gamma, ya, yb, yc, yd, ye)
+        / perez(0.0, theta_s, ya, yb, yc, yd, ye) * SKY_EXPOSURE;
+    let x = zenith_x * perez(theta, gamma, xa, xb, xc, xd, xe)
+        / perez(0.0, theta_s, xa, xb, xc, xd, xe);
+    let y = zenith_yc * perez(theta, gamma, ca, cb, cc, cd, ce)
+        / perez(0.0, theta_s, ca, cb, cc, cd, ce);
+    return max(xyy_to_rgb(x, y, max(luminance, 0.0)), vec3<f32>(0.0));
+}
+
+fn sky_radiance(direction: vec3<f32>, sun: vec3<f32>) -> vec3<f32> {
+    let day = smoothstep(-0.12, 0.05, sun.y);
+    return mix(NIGHT_SKY, preetham(direction, sun), day);
+}
+
+// sky without the sun disc, darkened below the horizon where the terrain usually is
+fn environment(dir: vec3<f32>, sun: vec3<f32>) -> vec3<f32> {
+    return sky_radiance(dir, sun) * mix(0.4, 1.0, smoothstep(-0.2, 0.0, dir.y));
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    // let color = textureSample(skybox_texture, skybox_sampler, normalize(in.dir));
-    let color = vec4<f32>(135.0 / 255.0, 206.0/ 255.0, 235.0/ 255.0, 1.0); // Default sky color (sky blue)
-    return color;
-}
\ No newline at end of file
+    let dir = normalize(in.dir);
+    let sun = normalize(camera.sun_direction.xyz);
+    var color = environment(dir, sun);
+
+    // sun disc, hidden below the horizon
+    let disc = smoothstep(SUN_DISC - 0.0002, SUN_DISC, dot(dir, sun)) * step(0.0, dir.y);
+    color += camera.sun_color.rgb * disc * SUN_INTENSITY;
+    return vec4<f32>(height_fog(color, camera.view_pos.xyz, dir, FOG_SKY_DISTANCE), 1.0);
+}
+
+@fragment
+fn fs_cubemap(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(in.dir);
+    let color = textureSample(skybox_texture, environment_sampler, dir).rgb;
+    return vec4<f32>(height_fog(color, camera.view_pos.xyz, dir, FOG_SKY_DISTANCE), 1.0);
+}
+
+// ===================
+// CUBE FACES, rendered by ibl.rs for image based lighting
+// ===================
+
+struct Face {
+    index: u32,
+    roughness: f32,
+    _padding: vec2<f32>,
+}
+@group(1) @binding(0)
+var<uniform> face: Face;
+
+struct FaceOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// one triangle covering the face, uv in [-1, 1] with v pointing down
+@vertex
+fn vs_face(@builtin(vertex_index) index: u32) -> FaceOutput {
+    var out: FaceOutput;
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    out.pos = vec4<f32>(pos, 0.0, 1.0);
+    out.uv = vec2<f32>(pos.x, -pos.y);
+    return out;
+}
+
+// mirrors `face_direction` in environment.rs
+fn face_direction(index: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x;
+    let v = uv.y;
+    switch index {
+        case 0u: { return normalize(vec3<f32>(1.0, -v, -u)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -v, u)); }
+        case 2u: { return normalize(vec3<f32>(u, 1.0, v)); }
+        case 3u: { return normalize(vec3<f32>(u, -1.0, -v)); }
+        case 4u: { return normalize(vec3<f32>(u, -v, 1.0)); }
+        default: { return normalize(vec3<f32>(-u, -v, -1.0)); }
+    }
+}
+
+@fragment
+fn fs_environment(in: FaceOutput) -> @location(0) vec4<f32> {
+    let dir = face_direction(face.index, in.uv);
+    return vec4<f32>(environment(dir, normalize(camera.sun_direction.xyz)), 1.0);
+}
@@ -0,0 +1,131 @@
+use wgpu::util::DeviceExt;
+
+use super::PostProcess;
+use super::post::{fullscreen_pass, fullscreen_pipeline, texture_entry};
+use super::targets::RenderTargets;
+
+// Mirrors `Downsample` in downsample.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DownsampleUniform {
+    scale: u32,
+    _padding: [u32; 3],
+}
+
+// A screenshot rendered `scale` times larger into targets of its own and filtered back down
+// to the scene size, so the frame's targets, bloom and TAA history are left alone
+pub struct Supersample {
+    pub targets: RenderTargets,
+    pub post: PostProcess,
+    // the tone mapped image at the large size
+    ldr_view: wgpu::TextureView,
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Supersample {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        targets: &RenderTargets,
+        post: &PostProcess,
+        scale: u32,
+    ) -> Self {
+        let (width, height) = (targets.width * scale, targets.height * scale);
+        let large = RenderTargets::new(device, width, height, targets.sample_count);
+        let config = wgpu::SurfaceConfiguration {
+            width,
+            height,
+            ..config.clone()
+        };
+        let post = post.still(device, &config, &large);
+
+        // the surface format, so the filtered image needs no conversion for the readback
+        let ldr_view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Supersample Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Downsample Uniform Buffer"),
+            contents: bytemuck::bytes_of(&DownsampleUniform {
+                scale,
+                _padding: [0; 3],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Downsample Bind Group Layout"),
+            entries: &[
+                texture_entry(0, wgpu::ShaderStages::FRAGMENT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Downsample Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&ldr_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("downsample.wgsl"));
+        let pipeline = fullscreen_pipeline(
+            device,
+            "Downsample Pipeline",
+            &shader,
+            "fs_main",
+            &bind_group_layout,
+            config.format,
+            None,
+        );
+
+        Self {
+            targets: large,
+            post,
+            ldr_view,
+            pipeline,
+            bind_group,
+        }
+    }
+
+    // tone maps the large image and filters it down into `view`, after `post.render`
+    pub fn present(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        self.post.present(encoder, &self.ldr_view);
+        fullscreen_pass(
+            encoder,
+            "Downsample Render Pass",
+            view,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &self.pipeline,
+            &self.bind_group,
+        );
+    }
+}
@@ -0,0 +1,87 @@
+// Temporal anti-aliasing, the jittered scene blended with the reprojected history
+
+// Mirrors `TaaUniform` in antialiasing.rs
+struct Taa {
+    // the current frame as rendered, jitter included
+    inv_view_proj: mat4x4<f32>,
+    // the previous frame without jitter
+    prev_view_proj: mat4x4<f32>,
+    // 1 when the history is invalid, after a resize or a mode change
+    reset: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0) @binding(0)
+var scene_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var history_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var scene_depth: texture_2d<f32>;
+@group(0) @binding(3)
+var history_sampler: sampler;
+@group(0) @binding(4)
+var<uniform> taa: Taa;
+
+// weight of the current frame
+const BLEND: f32 = 0.1;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    out.position = vec4<f32>(pos, 0.0, 1.0);
+    out.uv = vec2<f32>(pos.x * 0.5 + 0.5, 0.5 - pos.y * 0.5);
+    return out;
+}
+
+fn load_scene(texel: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    return textureLoad(scene_texture, clamp(texel, vec2<i32>(0), size - 1), 0).rgb;
+}
+
+// blending in a compressed range keeps bright pixels from dominating, Karis 2014
+fn luminance_weight(color: vec3<f32>) -> f32 {
+    return 1.0 / (1.0 + dot(color, vec3<f32>(0.2126, 0.7152, 0.0722)));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(scene_texture));
+    let texel = vec2<i32>(in.position.xy);
+    let current = load_scene(texel, size);
+
+    // the history is clamped to the colours around the pixel, rejecting disoccluded samples
+    var neighbourhood_min = current;
+    var neighbourhood_max = current;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbour = load_scene(texel + vec2<i32>(x, y), size);
+            neighbourhood_min = min(neighbourhood_min, neighbour);
+            neighbourhood_max = max(neighbourhood_max, neighbour);
+        }
+    }
+
+    // only the camera motion is reprojected, there are no motion vectors. Animated surfaces
+    // like the water find the wrong history texel, the clamp above keeps them from smearing
+    let depth = textureLoad(scene_depth, texel, 0).r;
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+    let world = taa.inv_view_proj * ndc;
+    let previous = taa.prev_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+    let previous_uv = vec2<f32>(previous.x, -previous.y) / previous.w * 0.5 + 0.5;
+    if taa.reset != 0u || any(previous_uv < vec2<f32>(0.0)) || any(previous_uv > vec2<f32>(1.0)) {
+        return vec4<f32>(current, 1.0);
+    }
+
+    var history = textureSampleLevel(history_texture, history_sampler, previous_uv, 0.0).rgb;
+    history = clamp(history, neighbourhood_min, neighbourhood_max);
+    let current_weight = BLEND * luminance_weight(current);
+    let history_weight = (1.0 - BLEND) * luminance_weight(history);
+    let color = (current * current_weight + history * history_weight) / (current_weight + history_weight);
+    return vec4<f32>(color, 1.0);
+}
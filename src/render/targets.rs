@@ -0,0 +1,169 @@
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+// every pass renders linear radiance into this, tone mapping brings it to the surface
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+    let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Scene Depth Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        // sampled by the transparent passes once the opaque passes are done
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_hdr_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+    let hdr_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Scene HDR Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        // read by bloom, exposure and tone mapping
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    hdr_texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// attachment only, resolved into the single sampled targets after the opaque stage
+fn create_multisampled_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Scene Multisampled Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// colour and depth the opaque stage renders into when multisampled
+pub struct MultisampledTargets {
+    pub color_view: wgpu::TextureView,
+    pub depth_view: wgpu::TextureView,
+}
+
+// Offscreen attachments shared by all passes, recreated on resize
+pub struct RenderTargets {
+    pub width: u32,
+    pub height: u32,
+    // opaque pipelines are created with this many samples
+    pub sample_count: u32,
+    pub depth_view: wgpu::TextureView,
+    // the resolved depth for passes that read it, with `depth_bind_group_layout`
+    pub depth_bind_group: wgpu::BindGroup,
+    pub hdr_view: wgpu::TextureView,
+    pub multisampled: Option<MultisampledTargets>,
+}
+
+impl RenderTargets {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, sample_count: u32) -> Self {
+        let depth_view = create_depth_texture(device, width, height);
+        Self {
+            width,
+            height,
+            sample_count,
+            depth_bind_group: Self::depth_bind_group(device, &depth_view),
+            depth_view,
+            hdr_view: create_hdr_texture(device, width, height),
+            multisampled: Self::create_multisampled(device, width, height, sample_count),
+        }
+    }
+
+    fn create_multisampled(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Option<MultisampledTargets> {
+        (sample_count > 1).then(|| MultisampledTargets {
+            color_view: create_multisampled_texture(
+                device,
+                width,
+                height,
+                HDR_FORMAT,
+                sample_count,
+            ),
+            depth_view: create_multisampled_texture(
+                device,
+                width,
+                height,
+                DEPTH_FORMAT,
+                sample_count,
+            ),
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.depth_view = create_depth_texture(device, width, height);
+        self.depth_bind_group = Self::depth_bind_group(device, &self.depth_view);
+        self.hdr_view = create_hdr_texture(device, width, height);
+        self.multisampled = Self::create_multisampled(device, width, height, self.sample_count);
+    }
+
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.sample_count = sample_count;
+        self.multisampled =
+            Self::create_multisampled(device, self.width, self.height, self.sample_count);
+    }
+
+    // layout for passes that read the resolved scene depth
+    pub fn depth_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Scene Depth Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    // read as a plain float texture so the GL backend can load it
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        })
+    }
+
+    // layouts with the same entries are interchangeable, so this one serves every pass's pipeline
+    fn depth_bind_group(device: &wgpu::Device, depth_view: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Scene Depth Bind Group"),
+            layout: &Self::depth_bind_group_layout(device),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(depth_view),
+            }],
+        })
+    }
+}
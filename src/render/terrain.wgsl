@@ -2,48 +2,107 @@ struct Camera {
     view_pos: vec4<f32>,
     view_proj: mat4x4<f32>,
     time: f32,
+    inv_view_proj: mat4x4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    fog_color: vec4<f32>,
+    fog_params: vec4<f32>,
 }
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
+// `sh_irradiance` and `height_fog` are prepended from irradiance.wgsl and fog.wgsl
+
+@group(0) @binding(2)
+var environment_sampler: sampler;
+@group(0) @binding(4)
+var specular_texture: texture_cube<f32>;
+
+struct Terrain {
+    water_level: f32,
+    min_height: f32,
+    max_height: f32,
+}
+@group(1) @binding(0)
+var<uniform> terrain: Terrain;
+
+// height above the water covered by sand
+const BEACH_WIDTH: f32 = 1.5;
+// depth below the water over which the seabed darkens
+const SEABED_DEPTH: f32 = 8.0;
+
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) height: f32,
+    @location(1) normal: vec3<f32>,
+    @location(2) world_pos: vec3<f32>,
+    // sky visibility baked from the heightmap horizon
+    @location(3) occlusion: f32,
 }
 
 
 @vertex
-fn vs_main(@location(0) position: vec3<f32>) -> VertexOutput {
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) occlusion: f32,
+) -> VertexOutput {
     var output: VertexOutput;
     output.position = camera.view_proj * vec4<f32>(position, 1.0);
     
     // Pass the y component (height) to the fragment shader
     output.height = position.y;
+    output.normal = normal;
+    output.world_pos = position;
+    output.occlusion = occlusion;
     
     return output;
 }
 
+// x is the normalised height above the beach
 fn terrain_colormap(x: f32) -> vec3<f32> {
-    if (x <= 0.15) {
-        let t = x / 0.15;
-        return mix(vec3<f32>(0.2, 0.2, 0.6), vec3<f32>(0.0, 0.6, 1.0), t);
-    } else if (x <= 0.375) {
-        let t = (x - 0.15) / (0.375 - 0.15);
-        return mix(vec3<f32>(0.0, 0.6, 1.0), vec3<f32>(0.0, 0.8, 0.4), t);
-    } else if (x <= 0.85) {
-        let t = (x - 0.375) / (0.85 - 0.375);
+    if (x <= 0.35) {
+        let t = x / 0.35;
+        return mix(vec3<f32>(0.0, 0.6, 0.3), vec3<f32>(0.0, 0.8, 0.4), t);
+    } else if (x <= 0.8) {
+        let t = (x - 0.35) / (0.8 - 0.35);
         return mix(vec3<f32>(0.0, 0.8, 0.4), vec3<f32>(1.0, 1.0, 0.6), t);
-    } else if (x <= 0.95) {
-        let t = (x - 0.85) / (0.95 - 0.85);
+    } else if (x <= 0.93) {
+        let t = (x - 0.8) / (0.93 - 0.8);
         return mix(vec3<f32>(1.0, 1.0, 0.6), vec3<f32>(0.5, 0.36, 0.33), t);
     } else {
-        let t = (x - 0.95) / (1.00 - 0.95);
+        let t = (x - 0.93) / (1.00 - 0.93);
         return mix(vec3<f32>(0.5, 0.36, 0.33), vec3<f32>(1.0, 1.0, 1.0), t);
     }
 }
 
+const SAND: vec3<f32> = vec3<f32>(0.93, 0.85, 0.6);
+const SEABED: vec3<f32> = vec3<f32>(0.35, 0.3, 0.22);
+
+// Colour bands relative to the water level, so the beach follows the water
+fn terrain_color(height: f32) -> vec3<f32> {
+    let beach_top = terrain.water_level + BEACH_WIDTH;
+    if (height < terrain.water_level) {
+        let t = clamp((terrain.water_level - height) / SEABED_DEPTH, 0.0, 1.0);
+        return mix(SAND, SEABED, t);
+    } else if (height < beach_top) {
+        return SAND;
+    }
+    let x = (height - beach_top) / max(terrain.max_height - beach_top, 1e-3);
+    // blend out of the sand instead of a hard line
+    let blend = smoothstep(0.0, 0.04, x);
+    return mix(SAND, terrain_colormap(clamp(x, 0.0, 1.0)), blend);
+}
+
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    let h = (input.height + 16.0) / 32.0;
-    return vec4<f32>(terrain_colormap(h), 1.0);
+    let albedo = terrain_color(input.height);
+    let normal = normalize(input.normal);
+    let diffuse = max(dot(normal, camera.sun_direction.xyz), 0.0);
+    let light = camera.sun_color.rgb * diffuse + sh_irradiance(normal) * input.occlusion;
+    let to_fragment = input.world_pos - camera.view_pos.xyz;
+    let distance = length(to_fragment);
+    let color = height_fog(albedo * light, camera.view_pos.xyz, to_fragment / distance, distance);
+    return vec4<f32>(color, 1.0);
 }
@@ -0,0 +1,134 @@
+// Final pass from the HDR scene to the surface
+
+// Mirrors `TonemapUniform` in post.rs
+struct Tonemap {
+    // 2^EV, on top of the metered exposure when automatic
+    exposure: f32,
+    // 0 --> ACES, 1 --> AgX, 2 --> Reinhard
+    tonemapper: u32,
+    auto_exposure: u32,
+    bloom_strength: f32,
+    // the surface isn't an sRGB format, so encode here
+    encode_srgb: u32,
+    _padding0: u32,
+    _padding1: u32,
+    // written by the exposure compute pass
+    average_luminance: f32,
+}
+
+@group(0) @binding(0)
+var scene_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var bloom_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var linear_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> tonemap: Tonemap;
+
+// middle grey the metered average is exposed to
+const KEY_VALUE: f32 = 0.18;
+// Reinhard maps this to white
+const WHITE_POINT: f32 = 4.0;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let pos = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    out.position = vec4<f32>(pos, 0.0, 1.0);
+    out.uv = vec2<f32>(pos.x * 0.5 + 0.5, 0.5 - pos.y * 0.5);
+    return out;
+}
+
+// Stephen Hill's fit of the ACES RRT and ODT
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let fitted = (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.4329510) + 0.238081);
+    return saturate(output * fitted);
+}
+
+// polynomial fit of the AgX base contrast curve
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+// Troy Sobotka's AgX, after Benjamin Wrensch's minimal version
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    var c = inset * color;
+    c = clamp(log2(max(c, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    c = (c - min_ev) / (max_ev - min_ev);
+    c = agx_contrast(c);
+    c = outset * c;
+    // the curve produces display encoded values
+    return pow(max(c, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+// extended Reinhard on luminance, keeps hues instead of desaturating per channel
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    let lum = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    let mapped = lum * (1.0 + lum / (WHITE_POINT * WHITE_POINT)) / (1.0 + lum);
+    return saturate(color * mapped / max(lum, 0.0001));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureSampleLevel(scene_texture, linear_sampler, in.uv, 0.0).rgb;
+    color += textureSampleLevel(bloom_texture, linear_sampler, in.uv, 0.0).rgb * tonemap.bloom_strength;
+
+    var exposure = tonemap.exposure;
+    if tonemap.auto_exposure != 0u {
+        exposure *= KEY_VALUE / max(tonemap.average_luminance, 0.0001);
+    }
+    color *= exposure;
+
+    switch tonemap.tonemapper {
+        case 0u: {
+            color = aces(color);
+        }
+        case 1u: {
+            color = agx(color);
+        }
+        default: {
+            color = reinhard(color);
+        }
+    }
+
+    if tonemap.encode_srgb != 0u {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
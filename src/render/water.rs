@@ -1,122 +1,363 @@
 use wgpu::util::DeviceExt;
 
-use super::Renderable;
+use super::ocean::{Ocean, OceanParams, Spectrum};
+use super::waves::{self, GerstnerWave, WaveUniform};
+use super::{Capabilities, RenderStage, RenderTargets, Renderable, targets};
+use crate::input::{Action, ActionEvent};
 
-const SIZE: usize = 256;
-const SIZE_F32: f32 = SIZE as f32;
+// quads per side of the water grid
+const GRID: usize = 256;
+// area covered before the first terrain is generated
+const DEFAULT_ORIGIN: [f32; 2] = [-256.0, -256.0];
+const DEFAULT_SIZE: f32 = 512.0;
 
-const WATER_LEVEL: f32 = 0.0;
+// 30% of the terrain under water
+const DEFAULT_LEVEL: WaterLevel = WaterLevel::Percentile(0.3);
+// change per key press
+const LEVEL_STEP: f32 = 0.5;
+const PERCENTILE_STEP: f32 = 0.025;
 
-#[rustfmt::skip]
-const WATER_VERTICES:&[[f32; 3]] = &[
-    //   x,     y,     z
-    [-SIZE_F32, WATER_LEVEL, -SIZE_F32],
-    [ SIZE_F32, WATER_LEVEL, -SIZE_F32],
-    [ SIZE_F32, WATER_LEVEL,  SIZE_F32],
-    [-SIZE_F32, WATER_LEVEL,  SIZE_F32],
-];
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WaterLevel {
+    // world height of the surface
+    Absolute(f32),
+    // fraction of the terrain below the surface
+    Percentile(f32),
+}
+
+// Unit grid, scaled to the terrain extent in the shader
+fn water_grid() -> (Vec<[f32; 2]>, Vec<u32>) {
+    let step = 1.0 / GRID as f32;
+    let mut vertices = Vec::with_capacity((GRID + 1) * (GRID + 1));
+    for i in 0..=GRID {
+        for j in 0..=GRID {
+            vertices.push([j as f32 * step, i as f32 * step]);
+        }
+    }
+    let mut indices = Vec::with_capacity(GRID * GRID * 6);
+    for i in 0..GRID {
+        for j in 0..GRID {
+            let a = (i * (GRID + 1) + j) as u32;
+            let b = a + 1;
+            let c = a + (GRID + 1) as u32;
+            let d = c + 1;
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+    (vertices, indices)
+}
+
+// Mirrors `Water` in water.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUniform {
+    origin: [f32; 2],
+    size: f32,
+    level: f32,
+}
 
-#[rustfmt::skip]
-const WATER_INDICES: &[u32] = &[
-    0, 1, 2,
-    0, 2, 3,
-];
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WaterMode {
+    // analytic Gerstner waves, cheap and good for lakes and coasts
+    Waves,
+    // FFT ocean, for large open water
+    Ocean,
+}
 
 pub struct WaterPass {
+    mode: WaterMode,
     pipeline: wgpu::RenderPipeline,
+    ocean_pipeline: wgpu::RenderPipeline,
+    ocean: Ocean,
+    // pending ocean parameters, applied on the next update
+    ocean_params: Option<OceanParams>,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
+    index_count: u32,
+    waves: Vec<GerstnerWave>,
+    waves_changed: bool,
+    wave_buffer: wgpu::Buffer,
+    level: WaterLevel,
+    uniform: WaterUniform,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
 }
 
 impl Renderable for WaterPass {
-    fn render(&mut self, pass: &mut wgpu::RenderPass, camera: &super::Camera) {
-        pass.set_pipeline(&self.pipeline);
+    fn render(
+        &mut self,
+        pass: &mut wgpu::RenderPass,
+        camera: &super::Camera,
+        targets: &RenderTargets,
+    ) {
+        match self.mode {
+            WaterMode::Waves => pass.set_pipeline(&self.pipeline),
+            WaterMode::Ocean => pass.set_pipeline(&self.ocean_pipeline),
+        }
         pass.set_bind_group(0, &camera.bind_group, &[]);
+        pass.set_bind_group(1, &targets.depth_bind_group, &[]);
+        pass.set_bind_group(2, &self.bind_group, &[]);
+        pass.set_bind_group(3, &self.ocean.bind_group, &[]);
         pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-        pass.draw_indexed(0..WATER_INDICES.len() as u32, 0, 0..1);
+        pass.draw_indexed(0..self.index_count, 0, 0..1);
     }
 
-    fn input(
-        &mut self,
-        _event: &winit::event::WindowEvent,
-        _context: &crate::context::ContextState,
-    ) -> bool {
-        false
+    fn input(&mut self, input: &ActionEvent, context: &crate::context::ContextState) -> bool {
+        if !input.pressed {
+            return false;
+        }
+        // the level mode switches between absolute and percentile levels
+        let factor = match input.action {
+            Action::LowerWater | Action::RaiseWater => {
+                let sign = if input.action == Action::RaiseWater { 1.0 } else { -1.0 };
+                self.level = match self.level {
+                    WaterLevel::Absolute(height) => WaterLevel::Absolute(height + sign * LEVEL_STEP),
+                    WaterLevel::Percentile(fraction) => WaterLevel::Percentile(
+                        (fraction + sign * PERCENTILE_STEP).clamp(0.0, 1.0),
+                    ),
+                };
+                log::info!("Water level: {:?}", self.level);
+                return true;
+            }
+            Action::WaterLevelMode => {
+                self.level = match (self.level, &context.heightmap) {
+                    (WaterLevel::Absolute(height), Some(heightmap)) => {
+                        WaterLevel::Percentile(heightmap.fraction_below(height))
+                    }
+                    (WaterLevel::Percentile(_), _) => WaterLevel::Absolute(context.water_level),
+                    (level, None) => level,
+                };
+                log::info!("Water level: {:?}", self.level);
+                return true;
+            }
+            Action::WaterMode => {
+                self.mode = match self.mode {
+                    WaterMode::Waves => WaterMode::Ocean,
+                    WaterMode::Ocean => WaterMode::Waves,
+                };
+                log::info!("Water mode: {:?}", self.mode);
+                return true;
+            }
+            Action::OceanSpectrum if self.mode == WaterMode::Ocean => {
+                let mut params = *self.ocean.params();
+                params.spectrum = match params.spectrum {
+                    Spectrum::Phillips => Spectrum::Jonswap,
+                    Spectrum::Jonswap => Spectrum::Phillips,
+                };
+                self.ocean_params = Some(params);
+                return true;
+            }
+            Action::CalmerWater => 0.8,
+            Action::ChoppierWater => 1.25,
+            _ => return false,
+        };
+        match self.mode {
+            WaterMode::Waves => {
+                waves::scale_steepness(&mut self.waves, factor);
+                self.waves_changed = true;
+            }
+            WaterMode::Ocean => {
+                let mut params = *self.ocean.params();
+                params.wind_speed = (params.wind_speed * factor).clamp(1.0, 40.0);
+                self.ocean_params = Some(params);
+            }
+        }
+        true
     }
 
-    fn update(&mut self, _context: &mut crate::context::ContextState, _queue: &wgpu::Queue) {}
+    fn update(&mut self, context: &mut crate::context::ContextState, queue: &wgpu::Queue) {
+        let (origin, size) = match &context.heightmap {
+            Some(heightmap) => heightmap.extent(),
+            None => (DEFAULT_ORIGIN, DEFAULT_SIZE),
+        };
+        let level = match (self.level, &context.heightmap) {
+            (WaterLevel::Absolute(height), _) => height,
+            (WaterLevel::Percentile(fraction), Some(heightmap)) => heightmap.percentile(fraction),
+            (WaterLevel::Percentile(_), None) => 0.0,
+        };
+        context.water_level = level;
+        context.waves.clear();
+        if self.mode == WaterMode::Waves {
+            context.waves.extend_from_slice(&self.waves);
+        }
+        let uniform = WaterUniform {
+            origin,
+            size,
+            level,
+        };
+        if uniform != self.uniform {
+            self.uniform = uniform;
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        }
+
+        if self.mode == WaterMode::Ocean {
+            if let Some(params) = self.ocean_params.take() {
+                log::info!("Ocean parameters: {params:?}");
+                self.ocean.set_params(queue, params);
+            }
+            self.ocean.update(queue, context.time);
+        }
+        if self.waves_changed {
+            let uniform = WaveUniform::new(&self.waves);
+            queue.write_buffer(&self.wave_buffer, 0, bytemuck::cast_slice(&[uniform]));
+            self.waves_changed = false;
+        }
+    }
 
     fn new(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        _config: &wgpu::SurfaceConfiguration,
         camera: &super::Camera,
+        _targets: &RenderTargets,
+        capabilities: &Capabilities,
     ) -> Self {
+        let (vertices, indices) = water_grid();
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Water Vertex Buffer"),
-            contents: bytemuck::cast_slice(WATER_VERTICES),
+            contents: bytemuck::cast_slice(&vertices),
             usage: wgpu::BufferUsages::VERTEX,
         });
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Water Index Buffer"),
-            contents: bytemuck::cast_slice(WATER_INDICES),
+            contents: bytemuck::cast_slice(&indices),
             usage: wgpu::BufferUsages::INDEX,
         });
 
+        let waves = waves::default_waves();
+        let wave_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Water Wave Buffer"),
+            contents: bytemuck::cast_slice(&[WaveUniform::new(&waves)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform = WaterUniform {
+            origin: DEFAULT_ORIGIN,
+            size: DEFAULT_SIZE,
+            level: 0.0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Water Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Water Bind Group Layout"),
+            entries: &[uniform_entry(0), uniform_entry(1)],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Water Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wave_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let depth_bind_group_layout = RenderTargets::depth_bind_group_layout(device);
+
+        let ocean = Ocean::new(device, capabilities, OceanParams::default());
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Water Pipeline Layout"),
-            bind_group_layouts: &[&camera.bind_group_layout],
+            bind_group_layouts: &[
+                &camera.bind_group_layout,
+                &depth_bind_group_layout,
+                &bind_group_layout,
+                &ocean.bind_group_layout,
+            ],
             push_constant_ranges: &[],
         });
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("water.wgsl"));
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Water Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x3,
-                        offset: 0,
-                        shader_location: 0,
-                    }],
-                }],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                ..Default::default()
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: false,
-                depth_compare: wgpu::CompareFunction::LessEqual,
-                stencil: Default::default(),
-                bias: Default::default(),
-            }),
-            multisample: Default::default(),
-            multiview: None,
-            cache: Default::default(),
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("water.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("fog.wgsl"),
+                    include_str!("irradiance.wgsl"),
+                    include_str!("water.wgsl")
+                )
+                .into(),
+            ),
         });
+        // both modes share the fragment shader and differ in how vertices are displaced
+        let create_pipeline = |label, vertex_entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some(vertex_entry_point),
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &[wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x2,
+                            offset: 0,
+                            shader_location: 0,
+                        }],
+                    }],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: targets::HDR_FORMAT,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    ..Default::default()
+                },
+                // depth testing is done in the shader against the sampled scene depth
+                depth_stencil: None,
+                multisample: Default::default(),
+                multiview: None,
+                cache: Default::default(),
+            })
+        };
+        let pipeline = create_pipeline("Water Render Pipeline", "vs_main");
+        let ocean_pipeline = create_pipeline("Ocean Render Pipeline", "vs_ocean");
 
         Self {
+            mode: WaterMode::Waves,
             pipeline,
+            ocean_pipeline,
+            ocean,
+            ocean_params: None,
             vertex_buffer,
             index_buffer,
+            index_count: indices.len() as u32,
+            waves,
+            waves_changed: false,
+            wave_buffer,
+            level: DEFAULT_LEVEL,
+            uniform,
+            uniform_buffer,
+            bind_group,
         }
     }
+
+    fn stage(&self) -> RenderStage {
+        RenderStage::Transparent
+    }
 }
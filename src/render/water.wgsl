@@ -6,10 +6,86 @@ struct Camera {
     view_pos: vec4<f32>,
     view_proj: mat4x4<f32>,
     time: f32,
+    inv_view_proj: mat4x4<f32>,
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    fog_color: vec4<f32>,
+    fog_params: vec4<f32>,
 }
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
+// `sh_irradiance` and `height_fog` are prepended from irradiance.wgsl and fog.wgsl
+
+// sky reflections prefiltered by roughness, see ibl.rs
+@group(0) @binding(2)
+var environment_sampler: sampler;
+@group(0) @binding(4)
+var specular_texture: texture_cube<f32>;
+
+// depth of the opaque scene, written before the water pass
+@group(1) @binding(0)
+var scene_depth: texture_2d<f32>;
+
+const MAX_WAVES: u32 = 8u;
+
+struct Wave {
+    direction: vec2<f32>,
+    wavelength: f32,
+    steepness: f32,
+    speed: f32,
+    _pad0: f32,
+    _pad1: f32,
+    _pad2: f32,
+}
+
+struct Waves {
+    waves: array<Wave, MAX_WAVES>,
+    count: u32,
+}
+@group(2) @binding(0)
+var<uniform> waves: Waves;
+
+// placement of the unit water grid over the terrain
+struct Water {
+    origin: vec2<f32>,
+    size: f32,
+    level: f32,
+}
+@group(2) @binding(1)
+var<uniform> water: Water;
+
+struct Ocean {
+    resolution: u32,
+    patch_size: f32,
+    time: f32,
+    choppiness: f32,
+}
+@group(3) @binding(0)
+var<uniform> ocean: Ocean;
+// (dx, height, dz) and normal of one tile of the FFT ocean
+@group(3) @binding(1)
+var ocean_displacement: texture_2d<f32>;
+@group(3) @binding(2)
+var ocean_normals: texture_2d<f32>;
+
+// ===================
+// CONSTANTS
+// ===================
+
+const PI: f32 = 3.14159265;
+
+const SHALLOW_COLOR: vec3<f32> = vec3<f32>(0.1, 0.7, 0.75);
+const DEEP_COLOR: vec3<f32> = vec3<f32>(0.0, 0.12, 0.3);
+// per channel extinction per world unit, red is absorbed first
+const ABSORPTION: vec3<f32> = vec3<f32>(0.45, 0.12, 0.08);
+// vertical water depth over which the shoreline fades in
+const EDGE_SOFTNESS: f32 = 0.3;
+// vertical water depth covered by foam
+const FOAM_WIDTH: f32 = 1.2;
+// specular mip the reflections are read from, 0 --> mirror
+const REFLECTION_LOD: f32 = 0.5;
 
 // ===================
 // STRUCTS
@@ -17,20 +93,93 @@ var<uniform> camera: Camera;
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-    @location(1) world_pos: vec3<f32>,
+    @location(0) world_pos: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // vertical displacement relative to the wave amplitude, for crest tinting
+    @location(2) crest: f32,
 }
 
 // ===================
 // VERTEX SHADER
 // ===================
 
+struct Surface {
+    displacement: vec3<f32>,
+    normal: vec3<f32>,
+    amplitude: f32,
+}
+
+// Sum of Gerstner waves, see `waves::gerstner`
+fn gerstner(p: vec2<f32>, time: f32) -> Surface {
+    var displacement = vec3<f32>(0.0);
+    var tangent = vec3<f32>(1.0, 0.0, 0.0);
+    var binormal = vec3<f32>(0.0, 0.0, 1.0);
+    var amplitude = 0.0;
+    for (var i = 0u; i < min(waves.count, MAX_WAVES); i = i + 1u) {
+        let wave = waves.waves[i];
+        let d = wave.direction;
+        let k = 2.0 * PI / wave.wavelength;
+        let a = wave.steepness / k;
+        let s = wave.steepness;
+        let f = k * (dot(d, p) - wave.speed * time);
+        let sin_f = sin(f);
+        let cos_f = cos(f);
+        displacement += vec3<f32>(d.x * a * cos_f, a * sin_f, d.y * a * cos_f);
+        tangent += vec3<f32>(-d.x * d.x * s * sin_f, d.x * s * cos_f, -d.x * d.y * s * sin_f);
+        binormal += vec3<f32>(-d.x * d.y * s * sin_f, d.y * s * cos_f, -d.y * d.y * s * sin_f);
+        amplitude += a;
+    }
+    var surface: Surface;
+    surface.displacement = displacement;
+    surface.normal = normalize(cross(binormal, tangent));
+    surface.amplitude = amplitude;
+    return surface;
+}
+
+// undisturbed world position of a grid vertex
+fn grid_position(grid: vec2<f32>) -> vec3<f32> {
+    let xz = water.origin + grid * water.size;
+    return vec3<f32>(xz.x, water.level, xz.y);
+}
+
 @vertex
-fn vs_main(@location(0) position: vec3<f32>) -> VertexOutput {
+fn vs_main(@location(0) grid: vec2<f32>) -> VertexOutput {
+    let position = grid_position(grid);
+    let surface = gerstner(position.xz, camera.time);
+    let world_pos = position + surface.displacement;
+
     var output: VertexOutput;
-    output.position = camera.view_proj * vec4<f32>(position, 1.0);
-    output.uv = (position.xz + vec2<f32>(256.0, 256.0)) / 512.0;
-    output.world_pos = position;
+    output.position = camera.view_proj * vec4<f32>(world_pos, 1.0);
+    output.world_pos = world_pos;
+    output.normal = surface.normal;
+    output.crest = surface.displacement.y / max(surface.amplitude, 1e-4);
+    return output;
+}
+
+// Bilinear, wrapping lookup into an unfilterable ocean map
+fn sample_tiled(map: texture_2d<f32>, p: vec2<f32>) -> vec4<f32> {
+    let n = i32(ocean.resolution);
+    let texel = p / ocean.patch_size * f32(n) - 0.5;
+    let base = vec2<i32>(floor(texel));
+    let t = texel - floor(texel);
+    let c00 = textureLoad(map, (base % n + n) % n, 0);
+    let c10 = textureLoad(map, ((base + vec2<i32>(1, 0)) % n + n) % n, 0);
+    let c01 = textureLoad(map, ((base + vec2<i32>(0, 1)) % n + n) % n, 0);
+    let c11 = textureLoad(map, ((base + vec2<i32>(1, 1)) % n + n) % n, 0);
+    return mix(mix(c00, c10, t.x), mix(c01, c11, t.x), t.y);
+}
+
+@vertex
+fn vs_ocean(@location(0) grid: vec2<f32>) -> VertexOutput {
+    let position = grid_position(grid);
+    let displacement = sample_tiled(ocean_displacement, position.xz).xyz;
+    let world_pos = position + displacement;
+
+    var output: VertexOutput;
+    output.position = camera.view_proj * vec4<f32>(world_pos, 1.0);
+    output.world_pos = world_pos;
+    output.normal = sample_tiled(ocean_normals, position.xz).xyz;
+    output.crest = clamp(displacement.y, -1.0, 1.0);
     return output;
 }
 
@@ -38,33 +187,67 @@ fn vs_main(@location(0) position: vec3<f32>) -> VertexOutput {
 // FRAGMENT SHADER
 // ===================
 
+// world position of the opaque surface behind a fragment
+fn scene_position(frag_coord: vec2<f32>, depth: f32) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(scene_depth));
+    let ndc = vec2<f32>(frag_coord.x / size.x * 2.0 - 1.0, 1.0 - frag_coord.y / size.y * 2.0);
+    let world = camera.inv_view_proj * vec4<f32>(ndc, depth, 1.0);
+    return world.xyz / world.w;
+}
+
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    // === Dynamic UV offset ===
-    let flow_speed = vec2<f32>(0.1, 0.05);
-    let uv = input.uv + camera.time * flow_speed;
+    // === Manual depth test against the opaque scene ===
+    let depth = textureLoad(scene_depth, vec2<i32>(input.position.xy), 0).r;
+    if (input.position.z > depth) {
+        discard;
+    }
 
-    // === Procedural wave pattern ===
-    let wave1 = sin(uv.x * 10.0 + camera.time) * 0.02;
-    let wave2 = sin(uv.y * 12.0 + camera.time * 1.2) * 0.015;
-    let wave3 = sin((uv.x + uv.y) * 14.0 + camera.time * 0.8) * 0.01;
-    let wave = wave1 + wave2 + wave3;
+    // === Water thickness ===
+    let floor_pos = scene_position(input.position.xy, depth);
+    let view_depth = distance(floor_pos, input.world_pos);
+    let vertical_depth = max(input.world_pos.y - floor_pos.y, 0.0);
 
-    // === Base water color ===
-    let base_color = vec3<f32>(0.0, 0.5, 0.8);
-    let wave_color = base_color + vec3<f32>(wave);
+    // === Depth based colour ===
+    let transmittance = exp(-ABSORPTION * view_depth);
+    let base_color = mix(DEEP_COLOR, SHALLOW_COLOR, transmittance);
+    // crests catch more light than troughs
+    let wave_color = base_color * (0.85 + 0.25 * input.crest);
 
-    // Dynamic normal for Fresnel
-    let dx = cos(uv.x * 8.0 + camera.time) * 0.2;
-    let dz = sin(uv.y * 10.0 + camera.time) * 0.2;
-    let normal = normalize(vec3<f32>(dx, 1.0, dz));
+    // === Lighting ===
+    let normal = normalize(input.normal);
+    let view_dir = normalize(camera.view_pos.xyz - input.world_pos);
+    let light_dir = camera.sun_direction.xyz;
+    let half_dir = normalize(light_dir + view_dir);
+    let specular = pow(max(dot(normal, half_dir), 0.0), 128.0) * camera.sun_color.rgb;
+    let diffuse = max(dot(normal, light_dir), 0.0);
+    let lit_color = wave_color * (camera.sun_color.rgb * diffuse + sh_irradiance(normal));
+    var reflect_dir = reflect(-view_dir, normal);
+    // keep reflections above the horizon, the environment below it is just a dark ground
+    reflect_dir.y = abs(reflect_dir.y);
+    let reflection = textureSampleLevel(specular_texture, environment_sampler, reflect_dir, REFLECTION_LOD).rgb;
 
     // Fresnel term
-    let view_dir = normalize(camera.view_pos.xyz - input.world_pos);
-    let fresnel = pow(1.0 - dot(normal, view_dir), 4.0);
+    let fresnel = pow(1.0 - max(dot(normal, view_dir), 0.0), 4.0);
+
+    var final_color = mix(lit_color, reflection, fresnel * 0.6) + specular;
+    // shallow water lets the terrain through, deep water is opaque
+    var alpha = mix(0.3, 0.9, 1.0 - transmittance.g);
+    alpha = mix(alpha, 1.0, fresnel * 0.5);
+
+    // === Shoreline foam ===
+    let shore = 1.0 - smoothstep(0.0, FOAM_WIDTH, vertical_depth);
+    let ripples = 0.5 + 0.5 * sin(vertical_depth * 12.0 - camera.time * 2.0 + input.crest);
+    let foam = shore * smoothstep(0.3, 0.8, ripples + shore * 0.5);
+    final_color = mix(final_color, camera.sun_color.rgb + camera.ambient_color.rgb, foam);
+    alpha = max(alpha, foam * 0.9);
+
+    // === Soft edge where the water meets the terrain ===
+    alpha = alpha * smoothstep(0.0, EDGE_SOFTNESS, vertical_depth);
 
-    let final_color = mix(wave_color, vec3<f32>(1.0), fresnel * 0.6);
-    let alpha = mix(0.4, 0.7, fresnel);
+    // === Fog ===
+    let distance = length(input.world_pos - camera.view_pos.xyz);
+    final_color = height_fog(final_color, camera.view_pos.xyz, -view_dir, distance);
 
     return vec4<f32>(final_color, alpha);
 }
@@ -0,0 +1,175 @@
+use bytemuck::Zeroable;
+use cgmath::*;
+use std::f32::consts::PI;
+
+pub const MAX_WAVES: usize = 8;
+const GRAVITY: f32 = 9.81;
+
+// Mirrors `Wave` in water.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GerstnerWave {
+    pub direction: [f32; 2],
+    pub wavelength: f32,
+    // 0 --> flat sine, 1 --> sharp crests
+    pub steepness: f32,
+    // phase speed in world units per second
+    pub speed: f32,
+    _padding: [f32; 3],
+}
+
+impl GerstnerWave {
+    // uses the deep water dispersion relation for the speed
+    pub fn new(direction: [f32; 2], wavelength: f32, steepness: f32) -> Self {
+        let k = 2.0 * PI / wavelength;
+        Self::with_speed(direction, wavelength, steepness, (GRAVITY / k).sqrt())
+    }
+
+    pub fn with_speed(direction: [f32; 2], wavelength: f32, steepness: f32, speed: f32) -> Self {
+        let direction = Vector2::from(direction).normalize();
+        Self {
+            direction: direction.into(),
+            wavelength,
+            steepness,
+            speed,
+            _padding: Default::default(),
+        }
+    }
+
+    fn wave_number(&self) -> f32 {
+        2.0 * PI / self.wavelength
+    }
+
+    pub fn amplitude(&self) -> f32 {
+        self.steepness / self.wave_number()
+    }
+}
+
+pub fn default_waves() -> Vec<GerstnerWave> {
+    vec![
+        GerstnerWave::new([1.0, 0.3], 60.0, 0.15),
+        GerstnerWave::new([0.6, 1.0], 31.0, 0.12),
+        GerstnerWave::new([-0.4, 0.9], 18.0, 0.1),
+        GerstnerWave::new([0.9, -0.5], 9.0, 0.08),
+    ]
+}
+
+// Mirrors `Waves` in water.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct WaveUniform {
+    waves: [GerstnerWave; MAX_WAVES],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+impl WaveUniform {
+    pub fn new(waves: &[GerstnerWave]) -> Self {
+        let count = waves.len().min(MAX_WAVES);
+        let mut uniform = Self::zeroed();
+        uniform.waves[..count].copy_from_slice(&waves[..count]);
+        uniform.count = count as u32;
+        uniform
+    }
+}
+
+// Crests start to loop once the summed steepness goes above 1
+pub fn scale_steepness(waves: &mut [GerstnerWave], factor: f32) {
+    let total: f32 = waves.iter().map(|w| w.steepness).sum();
+    let factor = if total * factor > 1.0 {
+        1.0 / total
+    } else {
+        factor
+    };
+    for wave in waves {
+        wave.steepness *= factor;
+    }
+}
+
+// Displacement and normal of the surface point that starts at `p` when undisturbed
+pub fn gerstner(
+    waves: &[GerstnerWave],
+    p: Vector2<f32>,
+    time: f32,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let mut displacement = Vector3::zero();
+    let mut tangent = Vector3::unit_x();
+    let mut binormal = Vector3::unit_z();
+    for wave in waves {
+        let d = Vector2::from(wave.direction);
+        let k = wave.wave_number();
+        let a = wave.amplitude();
+        let s = wave.steepness;
+        let f = k * (d.dot(p) - wave.speed * time);
+        let (sin, cos) = f.sin_cos();
+        displacement += Vector3::new(d.x * a * cos, a * sin, d.y * a * cos);
+        tangent += Vector3::new(-d.x * d.x * s * sin, d.x * s * cos, -d.x * d.y * s * sin);
+        binormal += Vector3::new(-d.x * d.y * s * sin, d.y * s * cos, -d.y * d.y * s * sin);
+    }
+    (displacement, binormal.cross(tangent).normalize())
+}
+
+// Height of the displaced surface above the world position (x, z)
+pub fn wave_height(waves: &[GerstnerWave], x: f32, z: f32, time: f32) -> f32 {
+    // find the undisturbed point that ends up at (x, z) by fixed point iteration
+    let target = Vector2::new(x, z);
+    let mut p = target;
+    for _ in 0..4 {
+        let (displacement, _) = gerstner(waves, p, time);
+        p = target - Vector2::new(displacement.x, displacement.z);
+    }
+    gerstner(waves, p, time).0.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn single_wave_displacement() {
+        // k = pi / 4, a = 0.5 / k, phase k * (x - 2t) = pi / 3 at x = 2, t = 1 / 3
+        let waves = [GerstnerWave::with_speed([1.0, 0.0], 8.0, 0.5, 2.0)];
+        let a = 2.0 / PI;
+        let (displacement, normal) = gerstner(&waves, Vector2::new(2.0, 5.0), 1.0 / 3.0);
+        assert_close(displacement.x, a * 0.5);
+        assert_close(displacement.y, a * 3f32.sqrt() / 2.0);
+        assert_close(displacement.z, 0.0);
+        // tangent (1 - s sin f, s cos f, 0) turned a quarter up
+        let (sin, cos) = (3f32.sqrt() / 2.0, 0.5);
+        let expected = Vector3::new(-0.5 * cos, 1.0 - 0.5 * sin, 0.0).normalize();
+        assert_close(normal.x, expected.x);
+        assert_close(normal.y, expected.y);
+        assert_close(normal.z, expected.z);
+    }
+
+    #[test]
+    fn height_follows_the_horizontal_displacement() {
+        let waves = [GerstnerWave::with_speed([1.0, 0.0], 8.0, 0.5, 2.0)];
+        // the crest starts at x = 2 and moves 2 units per second
+        let crest = Vector2::new(2.0, 0.0);
+        let (displacement, _) = gerstner(&waves, crest, 0.0);
+        assert_close(displacement.x, 0.0);
+        let amplitude = waves[0].amplitude();
+        assert_close(wave_height(&waves, 2.0, 7.0, 0.0), amplitude);
+        assert_close(wave_height(&waves, 3.0, 7.0, 0.5), amplitude);
+        // elsewhere the height is of the point that moved there, which the iteration finds
+        // closely for gentle waves
+        let waves = [GerstnerWave::with_speed([1.0, 0.0], 8.0, 0.1, 2.0)];
+        let start = Vector2::new(1.0, 0.0);
+        let (moved, _) = gerstner(&waves, start, 0.0);
+        let height = wave_height(&waves, 1.0 + moved.x, 0.0, 0.0);
+        assert_close(height, moved.y);
+    }
+
+    #[test]
+    fn no_waves_is_flat() {
+        assert_eq!(wave_height(&[], 12.0, -3.0, 4.0), 0.0);
+    }
+}
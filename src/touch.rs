@@ -0,0 +1,242 @@
+use cgmath::{InnerSpace, Vector2, Zero};
+use instant::{Duration, Instant};
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlCanvasElement, HtmlElement};
+use winit::event::{Touch, TouchPhase};
+
+use crate::render::{AnalogInput, Camera};
+
+// pixels of pinch per wheel line
+const PINCH_PIXELS_PER_LINE: f32 = 100.0;
+// a touch shorter and stiller than this is a tap, two taps this close make a double tap
+const TAP_TIME: Duration = Duration::from_millis(250);
+const TAP_SLOP: f32 = 12.0;
+const DOUBLE_TAP_TIME: Duration = Duration::from_millis(350);
+const DOUBLE_TAP_DISTANCE: f32 = 48.0;
+// full deflection of the virtual joystick, in CSS pixels
+const JOYSTICK_RADIUS: f64 = 60.0;
+
+struct ActiveTouch {
+    id: u64,
+    position: Vector2<f32>,
+    start: Instant,
+    start_position: Vector2<f32>,
+}
+
+struct Joystick {
+    id: u64,
+    origin: Vector2<f32>,
+    // -1..1, y down as on screen
+    deflection: Vector2<f32>,
+}
+
+// Camera gestures for touch screens. One finger looks, two fingers pinch to move
+// forward and back and drag to strafe, a double tap resets the view.
+// With the joystick enabled a touch starting on the left half of the canvas moves instead
+pub struct TouchControls {
+    canvas: Option<HtmlCanvasElement>,
+    touches: Vec<ActiveTouch>,
+    // time and position of the last single tap
+    last_tap: Option<(Instant, Vector2<f32>)>,
+    joystick: Option<Joystick>,
+    joystick_view: Option<JoystickView>,
+}
+
+impl TouchControls {
+    pub fn new(canvas: Option<HtmlCanvasElement>, joystick: bool) -> Self {
+        Self {
+            canvas,
+            touches: Vec::new(),
+            last_tap: None,
+            joystick: None,
+            joystick_view: joystick.then(JoystickView::new).flatten(),
+        }
+    }
+
+    pub fn process(&mut self, touch: &Touch, camera: &mut Camera) {
+        let position = Vector2::new(touch.location.x as f32, touch.location.y as f32);
+        match touch.phase {
+            TouchPhase::Started => self.start(touch.id, position),
+            TouchPhase::Moved => self.moved(touch.id, position, camera),
+            TouchPhase::Ended => self.end(touch.id, camera, true),
+            TouchPhase::Cancelled => self.end(touch.id, camera, false),
+        }
+    }
+
+    // adds the joystick to the gamepad movement
+    pub fn apply(&self, analog: &mut AnalogInput) {
+        if let Some(joystick) = &self.joystick {
+            analog.movement.x += joystick.deflection.x;
+            analog.movement.z -= joystick.deflection.y;
+        }
+    }
+
+    fn start(&mut self, id: u64, position: Vector2<f32>) {
+        let on_left = self
+            .canvas
+            .as_ref()
+            .is_some_and(|canvas| position.x < canvas.width() as f32 / 2.0);
+        if self.joystick_view.is_some() && self.joystick.is_none() && on_left {
+            self.joystick = Some(Joystick {
+                id,
+                origin: position,
+                deflection: Vector2::zero(),
+            });
+            self.update_joystick_view();
+            return;
+        }
+        self.touches.push(ActiveTouch {
+            id,
+            position,
+            start: Instant::now(),
+            start_position: position,
+        });
+    }
+
+    fn moved(&mut self, id: u64, position: Vector2<f32>, camera: &mut Camera) {
+        if let Some(joystick) = &mut self.joystick
+            && joystick.id == id
+        {
+            let radius = (JOYSTICK_RADIUS * pixel_ratio()) as f32;
+            let offset = (position - joystick.origin) / radius;
+            joystick.deflection = if offset.magnitude2() > 1.0 {
+                offset.normalize()
+            } else {
+                offset
+            };
+            self.update_joystick_view();
+            return;
+        }
+        let Some(index) = self.touches.iter().position(|touch| touch.id == id) else {
+            return;
+        };
+        let before = self.pair();
+        let previous = std::mem::replace(&mut self.touches[index].position, position);
+        match (before, self.pair()) {
+            (Some((centre, spread)), Some((new_centre, new_spread))) => {
+                camera.process_pan(new_centre - centre);
+                camera.process_dolly((new_spread - spread) / PINCH_PIXELS_PER_LINE);
+            }
+            _ if self.touches.len() == 1 => camera.process_mouse(position - previous),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, id: u64, camera: &mut Camera, ended: bool) {
+        if self
+            .joystick
+            .as_ref()
+            .is_some_and(|joystick| joystick.id == id)
+        {
+            self.joystick = None;
+            self.update_joystick_view();
+            return;
+        }
+        let Some(index) = self.touches.iter().position(|touch| touch.id == id) else {
+            return;
+        };
+        let touch = self.touches.remove(index);
+        let tap = ended
+            && self.touches.is_empty()
+            && touch.start.elapsed() < TAP_TIME
+            && (touch.position - touch.start_position).magnitude() < TAP_SLOP;
+        if !tap {
+            return;
+        }
+        match self.last_tap.take() {
+            Some((time, position))
+                if time.elapsed() < DOUBLE_TAP_TIME
+                    && (touch.position - position).magnitude() < DOUBLE_TAP_DISTANCE =>
+            {
+                camera.reset();
+            }
+            _ => self.last_tap = Some((Instant::now(), touch.position)),
+        }
+    }
+
+    // centre of and distance between the first two fingers
+    fn pair(&self) -> Option<(Vector2<f32>, f32)> {
+        match self.touches.as_slice() {
+            [first, second, ..] => Some((
+                (first.position + second.position) / 2.0,
+                (first.position - second.position).magnitude(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn update_joystick_view(&self) {
+        let (Some(view), Some(canvas)) = (&self.joystick_view, &self.canvas) else {
+            return;
+        };
+        match &self.joystick {
+            Some(joystick) => {
+                // touch positions are physical pixels inside the canvas
+                let rect = canvas.get_bounding_client_rect();
+                let ratio = pixel_ratio();
+                let origin = joystick.origin.cast::<f64>().unwrap() / ratio;
+                let knob = joystick.deflection.cast::<f64>().unwrap() * JOYSTICK_RADIUS;
+                let (x, y) = (rect.left() + origin.x, rect.top() + origin.y);
+                view.show(x, y, x + knob.x, y + knob.y);
+            }
+            None => view.hide(),
+        }
+    }
+}
+
+// physical pixels per CSS pixel
+fn pixel_ratio() -> f64 {
+    web_sys::window().map_or(1.0, |window| window.device_pixel_ratio())
+}
+
+// The virtual joystick as two circles laid over the page
+struct JoystickView {
+    base: HtmlElement,
+    knob: HtmlElement,
+}
+
+impl JoystickView {
+    fn new() -> Option<Self> {
+        let document = web_sys::window()?.document()?;
+        let body = document.body()?;
+        let circle = |diameter: f64, colour: &str| -> Option<HtmlElement> {
+            let element = document.create_element("div").ok()?;
+            let element: HtmlElement = element.dyn_into().ok()?;
+            let style = element.style();
+            let properties = [
+                ("position", "fixed".to_owned()),
+                ("width", format!("{diameter}px")),
+                ("height", format!("{diameter}px")),
+                ("margin", format!("{}px", -diameter / 2.0)),
+                ("border-radius", "50%".to_owned()),
+                ("background", colour.to_owned()),
+                ("pointer-events", "none".to_owned()),
+                ("display", "none".to_owned()),
+            ];
+            for (property, value) in properties {
+                style.set_property(property, &value).ok()?;
+            }
+            body.append_child(&element).ok()?;
+            Some(element)
+        };
+        Some(Self {
+            base: circle(JOYSTICK_RADIUS * 2.0, "rgba(255, 255, 255, 0.2)")?,
+            knob: circle(JOYSTICK_RADIUS * 0.8, "rgba(255, 255, 255, 0.5)")?,
+        })
+    }
+
+    fn show(&self, x: f64, y: f64, knob_x: f64, knob_y: f64) {
+        for (element, x, y) in [(&self.base, x, y), (&self.knob, knob_x, knob_y)] {
+            let style = element.style();
+            let _ = style.set_property("left", &format!("{x}px"));
+            let _ = style.set_property("top", &format!("{y}px"));
+            let _ = style.set_property("display", "block");
+        }
+    }
+
+    fn hide(&self) {
+        for element in [&self.base, &self.knob] {
+            let _ = element.style().set_property("display", "none");
+        }
+    }
+}